@@ -0,0 +1,156 @@
+//!
+//! Proptest strategies for generated pools, enabled with the `proptest` feature
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::EntityId;
+//! use spawning_pool::storage::{Storage, VectorStorage};
+//! use spawning_pool::arbitrary::arbitrary_pool;
+//! use spawning_pool::proptest::prelude::*;
+//! use spawning_pool::proptest::test_runner::TestRunner;
+//!
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! pub struct Health(u8);
+//!
+//! impl Arbitrary for Health {
+//!     type Parameters = ();
+//!     type Strategy = BoxedStrategy<Health>;
+//!
+//!     fn arbitrary_with(_: ()) -> Self::Strategy {
+//!         any::<u8>().prop_map(Health).boxed()
+//!     }
+//! }
+//!
+//! create_spawning_pool!(
+//!     (Health, health, VectorStorage)
+//! );
+//! TestRunner::default().run(&arbitrary_pool::<SpawningPool, (Health,)>(32), |pool| {
+//!     prop_assert!(pool.count::<Health>() <= 32);
+//!     Ok(())
+//! }).unwrap();
+//! # }
+//! ```
+//!
+
+use std::fmt::Debug;
+use proptest::prelude::*;
+use proptest::option;
+use proptest::collection::vec;
+use super::{EntityId};
+use bundle::SetComponent;
+use inspect::ErasedPool;
+
+///
+/// Tuple of component types that random entities are built from, each entity gets every
+/// component with a 50% chance
+///
+pub trait ArbitraryComponents<P> {
+    type Values: Debug + Clone;
+    fn strategy() -> BoxedStrategy<Self::Values>;
+    fn insert(values: Self::Values, pool: &mut P, id: EntityId);
+}
+
+macro_rules! tuple_arbitrary {
+    ($($name:ident: $index:tt),+) => {
+        impl<P, $($name: Arbitrary + Clone + 'static),+> ArbitraryComponents<P> for ($($name,)+)
+            where $(P: SetComponent<$name>),+ {
+            type Values = ($(Option<$name>,)+);
+
+            fn strategy() -> BoxedStrategy<Self::Values> {
+                ($(option::of(any::<$name>()),)+).boxed()
+            }
+
+            fn insert(values: Self::Values, pool: &mut P, id: EntityId) {
+                $(
+                    if let Some(component) = values.$index {
+                        pool.set_component(id, component);
+                    }
+                )+
+            }
+        }
+    };
+}
+
+tuple_arbitrary!(A: 0);
+tuple_arbitrary!(A: 0, B: 1);
+tuple_arbitrary!(A: 0, B: 1, C: 2);
+tuple_arbitrary!(A: 0, B: 1, C: 2, D: 3);
+tuple_arbitrary!(A: 0, B: 1, C: 2, D: 3, E: 4);
+tuple_arbitrary!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+tuple_arbitrary!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+tuple_arbitrary!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
+/// Pools of up to `max_entities` entities with random components from `C`, some of them removed
+/// and either cleaned up or left pending removal
+pub fn arbitrary_pool<P, C>(max_entities: usize) -> impl Strategy<Value = P>
+    where P: Default + ErasedPool + Debug, C: ArbitraryComponents<P> {
+    let entity = (C::strategy(), prop::bool::weighted(0.2));
+    (vec(entity, 0..max_entities + 1), any::<bool>()).prop_map(|(entities, cleanup)| {
+        let mut pool = P::default();
+        for (values, removed) in entities {
+            let id = pool.spawn_entity();
+            C::insert(values, &mut pool, id);
+            if removed {
+                pool.remove_entity(id);
+            }
+        }
+        if cleanup {
+            pool.cleanup_removed();
+        }
+        pool
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::arbitrary_pool;
+    use proptest::prelude::*;
+    use storage::*;
+    use create_spawning_pool;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Position(i32, i32);
+
+    impl Arbitrary for Position {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Position>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            (any::<i32>(), any::<i32>()).prop_map(|(x, y)| Position(x, y)).boxed()
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub struct Health(u8);
+
+    impl Arbitrary for Health {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Health>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            any::<u8>().prop_map(Health).boxed()
+        }
+    }
+
+    create_spawning_pool!(
+        (Position, pos, VectorStorage),
+        (Health, health, SlabStorage)
+    );
+
+    proptest! {
+        #[test]
+        fn storage_invariants(pool in arbitrary_pool::<SpawningPool, (Position, Health)>(64)) {
+            prop_assert_eq!(pool.count::<Position>(), pool.get_all::<Position>().len());
+            prop_assert_eq!(pool.count::<Health>(), pool.get_all::<Health>().len());
+            for id in pool.pending_removals() {
+                prop_assert!(pool.get::<Position>(id).is_none());
+            }
+
+            let json = ::serde_json::to_string(&pool).unwrap();
+            let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+            prop_assert!(SpawningPool::diff(&pool, &loaded).is_empty());
+        }
+    }
+}
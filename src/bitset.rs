@@ -0,0 +1,488 @@
+//!
+//! Growable bitset of entity IDs, used for the pool's removal queue and for joining component
+//! sets, and ranges of entity IDs for the pool's destroyed IDs
+//!
+
+use std::collections::BTreeMap;
+use std::iter::{self, FromIterator};
+use std::ops::{BitAnd, BitOr, Range, Sub};
+use super::{EntityId, RawEntityId};
+
+///
+/// Set of entity IDs stored as one bit per ID, so membership tests are a shift and a mask
+///
+/// A second layer has one bit per non-empty word, so iterating, draining and combining sets skip
+/// empty stretches of IDs instead of scanning them, even in worlds with hundreds of thousands of
+/// entities. Sets combine with `&`, `|` and `-`.
+///
+/// Serializes as a list of IDs, the same format a `HashSet<EntityId>` uses.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<EntityId>", into = "Vec<EntityId>")]
+pub struct EntitySet {
+    /// Never ends with an empty word, so equal sets compare equal
+    words: Vec<u64>,
+    /// Bit `w % 64` of `layer[w / 64]` is set when `words[w]` isn't empty
+    layer: Vec<u64>,
+    len: usize
+}
+
+impl EntitySet {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[inline]
+    fn position(id: EntityId) -> (usize, u64) {
+        let raw = id.to_raw() as usize;
+        (raw / 64, 1 << (raw % 64))
+    }
+
+    /// Adds the ID, returns `false` if it was already present
+    pub fn insert(&mut self, id: EntityId) -> bool {
+        let (word, mask) = EntitySet::position(id);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+            self.layer.resize(word / 64 + 1, 0);
+        }
+        if self.words[word] & mask != 0 {
+            return false;
+        }
+        self.words[word] |= mask;
+        self.layer[word / 64] |= 1 << (word % 64);
+        self.len += 1;
+        true
+    }
+
+    /// Removes the ID, returns `false` if it wasn't present
+    pub fn remove(&mut self, id: &EntityId) -> bool {
+        let (word, mask) = EntitySet::position(*id);
+        match self.words.get_mut(word) {
+            Some(bits) if *bits & mask != 0 => {
+                *bits &= !mask;
+                self.len -= 1;
+            }
+            _ => return false
+        }
+        if self.words[word] == 0 {
+            self.layer[word / 64] &= !(1 << (word % 64));
+            self.trim();
+        }
+        true
+    }
+
+    #[inline]
+    pub fn contains(&self, id: &EntityId) -> bool {
+        let (word, mask) = EntitySet::position(*id);
+        match self.words.get(word) {
+            Some(bits) => bits & mask != 0,
+            None => false
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// IDs in ascending order
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            words: &self.words,
+            layer: &self.layer,
+            layer_word: 0,
+            layer_bits: self.layer.first().cloned().unwrap_or(0),
+            word: 0,
+            bits: 0,
+            remaining: self.len
+        }
+    }
+
+    /// Empties the set, returning its IDs in ascending order
+    pub fn drain(&mut self) -> Vec<EntityId> {
+        let ids = self.iter().collect();
+        self.words.clear();
+        self.layer.clear();
+        self.len = 0;
+        ids
+    }
+
+    /// Adds every ID of `other`
+    pub fn union_with(&mut self, other: &EntitySet) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, bits) in other.nonempty_words() {
+            self.words[word] |= bits;
+        }
+        self.rebuild();
+    }
+
+    /// Keeps only the IDs also in `other`
+    pub fn intersect_with(&mut self, other: &EntitySet) {
+        self.words.truncate(other.words.len());
+        for (bits, other) in self.words.iter_mut().zip(&other.words) {
+            *bits &= other;
+        }
+        self.rebuild();
+    }
+
+    /// Drops the IDs that are in `other`
+    pub fn difference_with(&mut self, other: &EntitySet) {
+        for (word, bits) in other.nonempty_words() {
+            match self.words.get_mut(word) {
+                Some(own) => *own &= !bits,
+                None => break
+            }
+        }
+        self.rebuild();
+    }
+
+    /// Index and bits of every non-empty word, found through the layer
+    fn nonempty_words(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.layer.iter().enumerate().flat_map(move |(i, &layer_bits)| {
+            let mut layer_bits = layer_bits;
+            ::std::iter::from_fn(move || {
+                if layer_bits == 0 {
+                    return None;
+                }
+                let word = i * 64 + layer_bits.trailing_zeros() as usize;
+                layer_bits &= layer_bits - 1;
+                Some((word, self.words[word]))
+            })
+        })
+    }
+
+    fn trim(&mut self) {
+        while self.words.last() == Some(&0) {
+            self.words.pop();
+        }
+        self.layer.truncate(self.words.len().div_ceil(64));
+    }
+
+    /// Recomputes the layer and length after the words were changed in bulk
+    fn rebuild(&mut self) {
+        self.trim();
+        self.layer.clear();
+        self.layer.resize(self.words.len().div_ceil(64), 0);
+        self.len = 0;
+        for (word, bits) in self.words.iter().enumerate() {
+            if *bits != 0 {
+                self.layer[word / 64] |= 1 << (word % 64);
+                self.len += bits.count_ones() as usize;
+            }
+        }
+    }
+}
+
+///
+/// Iterator over the IDs of an `EntitySet` in ascending order
+///
+#[derive(Debug, Clone)]
+pub struct Iter<'a> {
+    words: &'a [u64],
+    layer: &'a [u64],
+    layer_word: usize,
+    layer_bits: u64,
+    word: usize,
+    bits: u64,
+    remaining: usize
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = EntityId;
+
+    fn next(&mut self) -> Option<EntityId> {
+        while self.bits == 0 {
+            while self.layer_bits == 0 {
+                self.layer_word += 1;
+                self.layer_bits = *self.layer.get(self.layer_word)?;
+            }
+            self.word = self.layer_word * 64 + self.layer_bits.trailing_zeros() as usize;
+            self.layer_bits &= self.layer_bits - 1;
+            self.bits = self.words[self.word];
+        }
+        let bit = self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1;
+        self.remaining -= 1;
+        EntityId::from_raw((self.word * 64 + bit) as RawEntityId)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
+
+impl BitOr for &EntitySet {
+    type Output = EntitySet;
+
+    fn bitor(self, other: &EntitySet) -> EntitySet {
+        let mut set = self.clone();
+        set.union_with(other);
+        set
+    }
+}
+
+impl BitAnd for &EntitySet {
+    type Output = EntitySet;
+
+    fn bitand(self, other: &EntitySet) -> EntitySet {
+        let mut set = self.clone();
+        set.intersect_with(other);
+        set
+    }
+}
+
+impl Sub for &EntitySet {
+    type Output = EntitySet;
+
+    fn sub(self, other: &EntitySet) -> EntitySet {
+        let mut set = self.clone();
+        set.difference_with(other);
+        set
+    }
+}
+
+impl FromIterator<EntityId> for EntitySet {
+    fn from_iter<I: IntoIterator<Item = EntityId>>(ids: I) -> Self {
+        let mut set = EntitySet::new();
+        for id in ids {
+            set.insert(id);
+        }
+        set
+    }
+}
+
+impl From<Vec<EntityId>> for EntitySet {
+    fn from(ids: Vec<EntityId>) -> Self {
+        ids.into_iter().collect()
+    }
+}
+
+impl From<EntitySet> for Vec<EntityId> {
+    fn from(set: EntitySet) -> Self {
+        set.iter().collect()
+    }
+}
+
+///
+/// Set of entity IDs stored as runs of consecutive IDs
+///
+/// Memory grows with the number of runs rather than with the largest ID, which suits the
+/// destroyed IDs of a pool: they form the gaps between live entities, so there are never more
+/// runs than live entities plus one.
+///
+/// Serializes as a list of inclusive `(first, last)` pairs. A plain list of IDs, the format of
+/// `EntitySet`, is read as well.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RangesRepr", into = "RangesRepr")]
+pub struct EntityRanges {
+    /// First ID of each run to its last, runs never overlap or touch
+    runs: BTreeMap<RawEntityId, RawEntityId>,
+    len: usize
+}
+
+impl EntityRanges {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds the ID, returns `false` if it was already present
+    pub fn insert(&mut self, id: EntityId) -> bool {
+        self.insert_range(id, id) == 1
+    }
+
+    /// Adds every ID from `first` to `last` inclusive, returns how many weren't present yet
+    pub fn insert_range(&mut self, first: EntityId, last: EntityId) -> usize {
+        let (mut first, mut last) = (first.to_raw(), last.to_raw());
+        if first > last {
+            return 0;
+        }
+        let touching: Vec<(RawEntityId, RawEntityId)> = self.runs.range(..=last.saturating_add(1))
+            .rev()
+            .take_while(|&(_, &end)| end.saturating_add(1) >= first)
+            .map(|(&start, &end)| (start, end))
+            .collect();
+        let before = self.len;
+        for (start, end) in touching {
+            self.runs.remove(&start);
+            self.len -= (end - start) as usize + 1;
+            first = first.min(start);
+            last = last.max(end);
+        }
+        self.runs.insert(first, last);
+        self.len += (last - first) as usize + 1;
+        self.len - before
+    }
+
+    /// Removes the ID, returns `false` if it wasn't present
+    pub fn remove(&mut self, id: &EntityId) -> bool {
+        let raw = id.to_raw();
+        let (start, end) = match self.runs.range(..=raw).next_back() {
+            Some((&start, &end)) if end >= raw => (start, end),
+            _ => return false
+        };
+        self.runs.remove(&start);
+        if start < raw {
+            self.runs.insert(start, raw - 1);
+        }
+        if raw < end {
+            self.runs.insert(raw + 1, end);
+        }
+        self.len -= 1;
+        true
+    }
+
+    pub fn contains(&self, id: &EntityId) -> bool {
+        let raw = id.to_raw();
+        self.runs.range(..=raw).next_back().is_some_and(|(_, &end)| end >= raw)
+    }
+
+    /// The lowest ID
+    pub fn first(&self) -> Option<EntityId> {
+        self.runs.keys().next().and_then(|&raw| EntityId::from_raw(raw))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of runs of consecutive IDs
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// The IDs below `end` that aren't in the set, as ascending ranges of raw IDs
+    pub fn gaps(&self, end: RawEntityId) -> impl Iterator<Item = Range<RawEntityId>> + '_ {
+        let mut next = 1;
+        self.runs.iter()
+            .map(|(&first, &last)| (first, last.saturating_add(1)))
+            .chain(iter::once((end, end)))
+            .filter_map(move |(first, after)| {
+                let gap = next..first.min(end);
+                next = next.max(after);
+                if gap.start < gap.end { Some(gap) } else { None }
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RangesRepr {
+    Runs(Vec<(EntityId, EntityId)>),
+    Ids(Vec<EntityId>)
+}
+
+impl From<RangesRepr> for EntityRanges {
+    fn from(repr: RangesRepr) -> Self {
+        let mut ranges = EntityRanges::new();
+        match repr {
+            RangesRepr::Runs(runs) => for (first, last) in runs {
+                ranges.insert_range(first, last);
+            },
+            RangesRepr::Ids(ids) => for id in ids {
+                ranges.insert(id);
+            }
+        }
+        ranges
+    }
+}
+
+impl From<EntityRanges> for RangesRepr {
+    fn from(ranges: EntityRanges) -> Self {
+        RangesRepr::Runs(ranges.runs.into_iter()
+            .filter_map(|(first, last)| Some((EntityId::from_raw(first)?, EntityId::from_raw(last)?)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EntityRanges, EntitySet};
+    use EntityId;
+    use serde_json;
+
+    #[test]
+    fn test_entity_set() {
+        let id = |raw| EntityId::from_raw(raw).unwrap();
+        let mut set = EntitySet::new();
+        assert!(set.insert(id(130)));
+        assert!(set.insert(id(3)));
+        assert!(!set.insert(id(3)));
+        assert!(set.contains(&id(130)));
+        assert!(!set.contains(&id(4)));
+        assert!(!set.contains(&id(100_000)));
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![id(3), id(130)]);
+        assert_eq!(set.iter().len(), 2);
+
+        let json = serde_json::to_string(&set).unwrap();
+        assert_eq!(json, "[3,130]");
+        assert_eq!(serde_json::from_str::<EntitySet>(&json).unwrap(), set);
+
+        assert!(set.remove(&id(130)));
+        assert!(!set.remove(&id(130)));
+        assert_eq!(set, [id(3)].iter().cloned().collect());
+        assert_eq!(set.drain(), vec![id(3)]);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_entity_set_operations() {
+        let set = |raws: &[u64]| raws.iter().map(|&raw| EntityId::from_raw(raw as ::RawEntityId).unwrap()).collect::<EntitySet>();
+        let a = set(&[1, 64, 5000, 200_000]);
+        let b = set(&[64, 70, 200_000]);
+        assert_eq!(&a & &b, set(&[64, 200_000]));
+        assert_eq!(&a | &b, set(&[1, 64, 70, 5000, 200_000]));
+        assert_eq!(&a - &b, set(&[1, 5000]));
+        assert_eq!(&b - &a, set(&[70]));
+        assert_eq!((&a & &b).len(), 2);
+        assert_eq!((&a | &b).iter().len(), 5);
+        assert!((&set(&[1]) & &set(&[200_000])).is_empty());
+        assert_eq!(&set(&[1]) & &set(&[200_000]), EntitySet::new());
+    }
+
+    #[test]
+    fn test_entity_ranges() {
+        let id = |raw| EntityId::from_raw(raw).unwrap();
+        let mut ranges = EntityRanges::new();
+        assert_eq!(ranges.insert_range(id(10), id(19)), 10);
+        assert!(ranges.insert(id(20)));
+        assert!(ranges.insert(id(5)));
+        assert!(!ranges.insert(id(15)));
+        assert_eq!((ranges.len(), ranges.runs()), (12, 2));
+        assert_eq!(ranges.insert_range(id(4), id(12)), 5);
+        assert_eq!((ranges.len(), ranges.runs()), (17, 1));
+        assert!(ranges.contains(&id(4)) && ranges.contains(&id(20)));
+        assert!(!ranges.contains(&id(3)) && !ranges.contains(&id(21)));
+
+        assert!(ranges.remove(&id(12)));
+        assert!(!ranges.remove(&id(12)));
+        assert!(!ranges.contains(&id(12)));
+        assert_eq!((ranges.len(), ranges.runs(), ranges.first()), (16, 2, Some(id(4))));
+
+        let json = serde_json::to_string(&ranges).unwrap();
+        assert_eq!(json, "[[4,11],[13,20]]");
+        assert_eq!(serde_json::from_str::<EntityRanges>(&json).unwrap(), ranges);
+        let ids = serde_json::from_str::<EntityRanges>("[1,2,3,7]").unwrap();
+        assert_eq!((ids.len(), ids.runs()), (4, 2));
+        assert_eq!(ids.gaps(10).collect::<Vec<_>>(), vec![4..7, 8..10]);
+        assert_eq!(ids.gaps(6).collect::<Vec<_>>(), vec![4..6]);
+        assert_eq!(ids.gaps(3).count(), 0);
+
+        let mut huge = EntityRanges::new();
+        assert_eq!(huge.insert_range(id(1), id(::RawEntityId::MAX)), ::RawEntityId::MAX as usize);
+        assert!(huge.remove(&id(::RawEntityId::MAX)));
+        assert_eq!(huge.runs(), 1);
+    }
+}
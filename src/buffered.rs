@@ -0,0 +1,84 @@
+//!
+//! Double buffered pools, reading last frame's state while writing the next one
+//!
+//! The next frame starts as a fork of the current one, so only the storages written during the
+//! frame get copied forward, and `current_and_next_mut` hands out both frames at once without
+//! aliasing.
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::buffered::DoubleBuffered;
+//! use spawning_pool::storage::{Storage, VectorStorage};
+//!
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Heat(i32);
+//!
+//! create_spawning_pool!((Heat, heat, VectorStorage));
+//! let mut pool = SpawningPool::new();
+//! let a = spawn_with!(pool, Heat(10));
+//! let b = spawn_with!(pool, Heat(0));
+//!
+//! let mut frames = DoubleBuffered::new(pool);
+//! let (current, next) = frames.current_and_next_mut();
+//! let average = (current.get::<Heat>(a).unwrap().0 + current.get::<Heat>(b).unwrap().0) / 2;
+//! next.set(a, Heat(average));
+//! next.set(b, Heat(average));
+//! assert_eq!(frames.current().get::<Heat>(a).unwrap().0, 10);
+//!
+//! frames.swap();
+//! assert_eq!(frames.current().get::<Heat>(b).unwrap().0, 5);
+//! # }
+//! ```
+//!
+
+use std::mem;
+use pool::Pool;
+
+///
+/// The current frame of a pool along with the next one being written, see the module
+/// documentation
+///
+#[derive(Debug)]
+pub struct DoubleBuffered<P> {
+    current: P,
+    next: P
+}
+
+impl<P: Pool> DoubleBuffered<P> {
+    /// Makes `pool` the current frame, the next frame starts as its fork
+    pub fn new(pool: P) -> Self {
+        let next = pool.fork();
+        DoubleBuffered { current: pool, next }
+    }
+
+    /// Last frame's state, unchanged until `swap`
+    pub fn current(&self) -> &P {
+        &self.current
+    }
+
+    pub fn next(&self) -> &P {
+        &self.next
+    }
+
+    pub fn next_mut(&mut self) -> &mut P {
+        &mut self.next
+    }
+
+    /// Reads of the current frame next to writes of the next one
+    pub fn current_and_next_mut(&mut self) -> (&P, &mut P) {
+        (&self.current, &mut self.next)
+    }
+
+    /// Makes the next frame current and starts a new next frame as its fork
+    pub fn swap(&mut self) {
+        let next = self.next.fork();
+        self.current = mem::replace(&mut self.next, next);
+    }
+
+    /// Unwraps the current frame, dropping the next one
+    pub fn into_current(self) -> P {
+        self.current
+    }
+}
@@ -0,0 +1,107 @@
+//!
+//! Bundles, groups of components inserted with a single `set_bundle` call
+//!
+//! Tuples of registered components are bundles out of the box. Named bundles are declared with
+//! the `bundle!` macro:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::EntityId;
+//! use spawning_pool::storage::{Storage, HashMapStorage, VectorStorage};
+//!
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Pos(i32, i32);
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Health(u32);
+//!
+//! bundle! {
+//!     struct Monster {
+//!         pos: Pos,
+//!         health: Health
+//!     }
+//! }
+//!
+//! create_spawning_pool!(
+//!     (Pos, pos, VectorStorage),
+//!     (Health, health, HashMapStorage)
+//! );
+//! let mut pool = SpawningPool::new();
+//! let a = pool.spawn_entity();
+//! pool.set_bundle(a, (Pos(1, 2), Health(10)));
+//! let b = pool.spawn_entity();
+//! pool.set_bundle(b, Monster { pos: Pos(3, 4), health: Health(5) });
+//! assert_eq!(pool.get::<Health>(b).unwrap().0, 5);
+//! # }
+//! ```
+//!
+
+use super::{EntityId};
+
+///
+/// Implemented by generated pools for every registered component type
+///
+pub trait SetComponent<T> {
+    fn set_component(&mut self, id: EntityId, component: T);
+}
+
+///
+/// A group of components that can be attached to an entity of pool `P` in one go
+///
+pub trait Bundle<P> {
+    fn insert_into(self, pool: &mut P, id: EntityId);
+}
+
+macro_rules! tuple_bundle {
+    ($($name:ident),+) => {
+        impl<P, $($name),+> Bundle<P> for ($($name,)+) where $(P: SetComponent<$name>),+ {
+            #[allow(non_snake_case)]
+            fn insert_into(self, pool: &mut P, id: EntityId) {
+                let ($($name,)+) = self;
+                $(
+                    pool.set_component(id, $name);
+                )+
+            }
+        }
+    };
+}
+
+tuple_bundle!(A);
+tuple_bundle!(A, B);
+tuple_bundle!(A, B, C);
+tuple_bundle!(A, B, C, D);
+tuple_bundle!(A, B, C, D, E);
+tuple_bundle!(A, B, C, D, E, F);
+tuple_bundle!(A, B, C, D, E, F, G);
+tuple_bundle!(A, B, C, D, E, F, G, H);
+tuple_bundle!(A, B, C, D, E, F, G, H, I);
+tuple_bundle!(A, B, C, D, E, F, G, H, I, J);
+tuple_bundle!(A, B, C, D, E, F, G, H, I, J, K);
+tuple_bundle!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+///
+/// Declares a struct whose fields are components and implements `Bundle` for it
+///
+#[macro_export]
+macro_rules! bundle {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident : $ty:ty),+ $(,)*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($field_vis $field: $ty),+
+        }
+
+        impl<P> $crate::bundle::Bundle<P> for $name where $(P: $crate::bundle::SetComponent<$ty>),+ {
+            fn insert_into(self, pool: &mut P, id: $crate::EntityId) {
+                $(
+                    $crate::bundle::SetComponent::set_component(pool, id, self.$field);
+                )+
+            }
+        }
+    };
+}
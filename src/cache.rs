@@ -0,0 +1,116 @@
+//!
+//! Caching of query results, for several systems running the same join within a tick
+//!
+//! Once enabled with `cache_queries`, the entities matched by `query_entities` and by the
+//! `for_each` of a `Query` are kept per set of components. A cached list is reused until one of
+//! its components is added to or removed from an entity, or an entity is removed, so writing to
+//! existing components doesn't invalidate it. Changes made through `storage_mut` or `split`
+//! bypass the pool and aren't seen, call `clear_query_cache` after them.
+//!
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use super::{EntityId};
+
+struct Cached {
+    /// `ChangeTicks::structure` of every component when the entities were matched
+    structure: Vec<u64>,
+    entities: Arc<Vec<EntityId>>
+}
+
+///
+/// Matched entities of the queries run on a pool, keyed by the sorted indices of their components
+///
+#[derive(Default)]
+pub struct QueryCache {
+    enabled: bool,
+    queries: Mutex<HashMap<Vec<usize>, Cached>>
+}
+
+impl fmt::Debug for QueryCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("QueryCache")
+            .field("enabled", &self.enabled)
+            .field("queries", &self.len())
+            .finish()
+    }
+}
+
+impl Clone for QueryCache {
+    /// The copy starts out empty
+    fn clone(&self) -> Self {
+        QueryCache { enabled: self.enabled, queries: Default::default() }
+    }
+}
+
+impl QueryCache {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Disabling the cache also empties it
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.clear();
+        }
+    }
+
+    /// Number of cached queries
+    pub fn len(&self) -> usize {
+        self.queries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.queries.lock().unwrap().clear();
+    }
+
+    /// Entities matched by the query of `components`, if they were cached with the same
+    /// `structure`
+    pub fn get(&self, components: &[usize], structure: &[u64]) -> Option<Arc<Vec<EntityId>>> {
+        if !self.enabled {
+            return None;
+        }
+        match self.queries.lock().unwrap().get(components) {
+            Some(cached) if cached.structure == structure => Some(cached.entities.clone()),
+            _ => None
+        }
+    }
+
+    /// Caches the entities matched by the query of `components`, does nothing while disabled
+    pub fn insert(&self, components: Vec<usize>, structure: Vec<u64>, entities: Arc<Vec<EntityId>>) {
+        if self.enabled {
+            self.queries.lock().unwrap().insert(components, Cached { structure, entities });
+        }
+    }
+}
+
+///
+/// Implemented by generated pools, lets queries look up and fill the pool's cache
+///
+pub trait CachedQueries {
+    fn query_cache(&self) -> &QueryCache;
+
+    /// `ChangeTicks::structure` of the component at `index`
+    fn structure(&self, index: usize) -> u64;
+
+    /// Entities matched by the query of `components`, computed with `matches` unless cached
+    fn cached_entities<F>(&self, components: &[usize], matches: F) -> Arc<Vec<EntityId>> where F: FnOnce(&Self) -> Vec<EntityId>, Self: Sized {
+        let cache = self.query_cache();
+        if !cache.is_enabled() {
+            return Arc::new(matches(self));
+        }
+        let structure: Vec<u64> = components.iter().map(|&index| self.structure(index)).collect();
+        if let Some(entities) = cache.get(components, &structure) {
+            return entities;
+        }
+        let entities = Arc::new(matches(self));
+        cache.insert(components.to_vec(), structure, entities.clone());
+        entities
+    }
+}
@@ -0,0 +1,97 @@
+//!
+//! Async notifications of component changes, enabled with the `async` feature
+//!
+//! `pool.changes::<T>()` returns a stream receiving a `Change` every time component `T` is set
+//! or removed, so async servers can await changes instead of polling the pool every tick. Streams
+//! are unbounded and dropping one unsubscribes it.
+//!
+
+use super::{EntityId};
+
+///
+/// A component of the entity was set, or removed by `remove` or `cleanup_removed`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Change {
+    Set(EntityId),
+    Removed(EntityId)
+}
+
+///
+/// Stream of the changes of one component type, returned by `changes`
+///
+#[cfg(feature = "async")]
+pub type ChangeStream = ::futures_channel::mpsc::UnboundedReceiver<Change>;
+
+///
+/// Subscribers of every component, empty without the `async` feature
+///
+#[derive(Debug, Default)]
+pub struct ChangeObservers {
+    #[cfg(feature = "async")]
+    senders: Vec<(usize, ::futures_channel::mpsc::UnboundedSender<Change>)>
+}
+
+impl ChangeObservers {
+    /// Sends the change of the component at `index` to its streams, forgetting dropped ones
+    #[inline]
+    pub fn notify(&mut self, index: usize, change: Change) {
+        #[cfg(feature = "async")]
+        self.senders.retain(|(i, sender)| *i != index || sender.unbounded_send(change).is_ok());
+        #[cfg(not(feature = "async"))]
+        let _ = (index, change);
+    }
+
+    #[cfg(feature = "async")]
+    pub fn subscribe(&mut self, index: usize) -> ChangeStream {
+        let (sender, receiver) = ::futures_channel::mpsc::unbounded();
+        self.senders.push((index, sender));
+        receiver
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "async")]
+macro_rules! __spawning_pool_if_async {
+    ($($tokens:tt)*) => { $($tokens)* };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "async"))]
+macro_rules! __spawning_pool_if_async {
+    ($($tokens:tt)*) => {};
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use super::Change;
+    use storage::*;
+    use create_spawning_pool;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Health(i32);
+
+    #[test]
+    fn test_changes() {
+        create_spawning_pool!(
+            (Health, health, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let mut changes = pool.changes::<Health>();
+        let a = pool.spawn_entity();
+        pool.set(a, Health(3));
+        pool.remove::<Health>(a);
+        let b = pool.spawn_entity();
+        pool.set(b, Health(1));
+        pool.remove_entity(b);
+        pool.cleanup_removed();
+
+        let received: Vec<Change> = ::std::iter::from_fn(|| changes.try_recv().ok()).collect();
+        assert_eq!(received, vec![Change::Set(a), Change::Removed(a), Change::Set(b), Change::Removed(b)]);
+
+        drop(changes);
+        pool.set(a, Health(2));
+    }
+}
@@ -0,0 +1,143 @@
+//!
+//! Stable hashing of pool state, for desync detection in lockstep games
+//!
+
+use std::hash::Hasher;
+use inspect::Inspect;
+
+///
+/// 64 bit FNV-1a hasher, unlike `DefaultHasher` its output is specified and the same on every
+/// platform and Rust version
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Fnv64(u64);
+
+impl Default for Fnv64 {
+    fn default() -> Self {
+        Fnv64(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv64 {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    // Integers are hashed little endian and `usize` as 64 bits, so `Hash` impls give the same
+    // output on every platform
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+///
+/// Hash over every live entity and its components' serialized values, visited in entity id and
+/// component declaration order
+///
+pub fn state_hash<P: Inspect>(pool: &P) -> u64 {
+    let mut hasher = Fnv64::default();
+    for id in pool.entity_ids() {
+        hasher.write(&id.to_raw().to_le_bytes());
+        for name in pool.component_names() {
+            if let Some(value) = pool.component_value(id, name) {
+                hasher.write(name.as_bytes());
+                hasher.write(&[0]);
+                hasher.write(value.to_string().as_bytes());
+                hasher.write(&[0]);
+            }
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(all(test, feature = "deterministic"))]
+mod tests {
+    use std::hash::{Hash, Hasher};
+    use super::Fnv64;
+    use storage::*;
+    use uuid::Uuid;
+    use create_spawning_pool;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Health(i32);
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Team(u8);
+
+    impl IndexKey for Team {
+        type Key = u8;
+        fn index_key(&self) -> u8 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        create_spawning_pool!(
+            (Health, health, HashMapStorage),
+            (Team, team, IndexedStorage)
+        );
+
+        fn build(reversed: bool) -> SpawningPool {
+            let mut pool = SpawningPool::new();
+            let ids: Vec<_> = (0..32).map(|_| pool.spawn_entity()).collect();
+            let mut order: Vec<_> = ids.iter().cloned().enumerate().collect();
+            if reversed {
+                order.reverse();
+            }
+            for (i, id) in order {
+                pool.set(id, Health(i as i32 * 7));
+                pool.set(id, Team(i as u8 % 3));
+                pool.set_uuid(id, Uuid::from_u128(i as u128 + 1));
+            }
+            pool
+        }
+
+        let (a, b) = (build(false), build(true));
+        let ids: Vec<_> = a.get_all::<Health>().iter().map(|(id, _)| *id).collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(ids, sorted);
+        assert_eq!(ids, b.get_all::<Health>().iter().map(|(id, _)| *id).collect::<Vec<_>>());
+        assert_eq!(a.get_all::<Team>().len(), 32);
+
+        let (mut saved_a, mut saved_b) = (vec![], vec![]);
+        a.save(&mut saved_a).unwrap();
+        b.save(&mut saved_b).unwrap();
+        assert_eq!(saved_a, saved_b);
+
+        // Known values, any other run or platform has to come up with the same ones
+        assert_eq!(a.state_hash(), b.state_hash());
+        #[cfg(not(feature = "u32-ids"))]
+        assert_eq!(a.state_hash(), 1091311453437394943);
+        #[cfg(feature = "u32-ids")]
+        assert_eq!(a.state_hash(), 2264880303161783487);
+        let mut hasher = Fnv64::default();
+        (-3i64, 7usize, 0x0102u16).hash(&mut hasher);
+        assert_eq!(hasher.finish(), 17312997544963314749);
+    }
+}
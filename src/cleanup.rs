@@ -0,0 +1,82 @@
+//!
+//! Reports of what `cleanup_removed` destroyed, automatic cleanup and scheduled despawns
+//!
+
+use std::collections::BTreeMap;
+use map::{EntityMap, MapEntities};
+use super::{EntityId};
+
+///
+/// An entity destroyed by `cleanup_removed`, with the names of the components it still had
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destroyed {
+    pub id: EntityId,
+    pub components: Vec<&'static str>
+}
+
+///
+/// When the pool runs `cleanup_removed` by itself, so the removal queue can't grow without bound
+///
+/// The reports of automatic cleanups are dropped, enable `track_removals` and use
+/// `removed_this_frame` to find out what they destroyed.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AutoCleanup {
+    /// Only explicit `cleanup_removed` calls destroy entities
+    #[default]
+    Never,
+    /// Cleans up once this many entities are pending removal
+    AfterNRemoved(usize),
+    /// Cleans up pending removals whenever an entity is spawned
+    OnSpawn
+}
+
+///
+/// Entities scheduled for removal with `despawn_after`, with the number of `tick` calls left
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DespawnTimers {
+    timers: BTreeMap<EntityId, u64>
+}
+
+impl DespawnTimers {
+    pub fn set(&mut self, id: EntityId, ticks: u64) {
+        self.timers.insert(id, ticks);
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<u64> {
+        self.timers.get(&id).cloned()
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<u64> {
+        self.timers.remove(&id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Counts every timer down by one and returns the entities whose timer ran out, sorted by id
+    pub fn tick(&mut self) -> Vec<EntityId> {
+        let mut expired = vec![];
+        for (id, left) in self.timers.iter_mut() {
+            *left = left.saturating_sub(1);
+            if *left == 0 {
+                expired.push(*id);
+            }
+        }
+        for id in &expired {
+            self.timers.remove(id);
+        }
+        expired
+    }
+}
+
+impl MapEntities for DespawnTimers {
+    /// Drops the timers of entities that aren't in the map
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.timers = self.timers.iter().filter_map(|(id, left)| Some((map.get(*id)?, *left))).collect();
+    }
+}
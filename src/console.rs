@@ -0,0 +1,120 @@
+//!
+//! Text commands for in-game debug consoles
+//!
+//! `execute` parses one command line and applies it to the pool:
+//!
+//! * `list` lists live entities and their component names
+//! * `inspect <id>` shows the components of an entity as JSON
+//! * `set <id> <component> <json>` attaches a component to an entity
+//! * `despawn <id>` marks an entity for removal
+//! * `help` lists the commands
+//!
+
+use std::fmt;
+use serde_json::{self, Value};
+use super::{EntityId, RawEntityId};
+use inspect::{ErasedPool, ValueError};
+
+const HELP: &str = "list\ninspect <id>\nset <id> <component> <json>\ndespawn <id>";
+
+///
+/// Error from running a console command, its `Display` output is meant for the console
+///
+#[derive(Debug)]
+pub enum ConsoleError {
+    UnknownCommand(String),
+    /// Missing arguments, holds the expected usage
+    Usage(&'static str),
+    InvalidId(String),
+    Value(ValueError)
+}
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConsoleError::UnknownCommand(ref cmd) => write!(f, "unknown command: {}, try help", cmd),
+            ConsoleError::Usage(usage) => write!(f, "usage: {}", usage),
+            ConsoleError::InvalidId(ref id) => write!(f, "invalid entity id: {}", id),
+            ConsoleError::Value(ref e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl ::std::error::Error for ConsoleError {}
+
+impl From<ValueError> for ConsoleError {
+    fn from(e: ValueError) -> Self {
+        ConsoleError::Value(e)
+    }
+}
+
+fn parse_id(arg: Option<&str>, usage: &'static str) -> Result<EntityId, ConsoleError> {
+    let arg = arg.ok_or(ConsoleError::Usage(usage))?;
+    arg.parse::<RawEntityId>().ok()
+        .and_then(EntityId::from_raw)
+        .ok_or_else(|| ConsoleError::InvalidId(arg.to_string()))
+}
+
+/// What's left of `s` after its first `n` words
+fn skip_words(s: &str, n: usize) -> &str {
+    let mut s = s.trim();
+    for _ in 0..n {
+        s = s.trim_start_matches(|c: char| !c.is_whitespace()).trim_start();
+    }
+    s
+}
+
+fn component_names<P: ErasedPool + ?Sized>(pool: &P, id: EntityId) -> Vec<&'static str> {
+    pool.component_names().iter()
+        .filter(|name| pool.component_value(id, name).is_some())
+        .cloned()
+        .collect()
+}
+
+/// Runs a single command line against the pool and returns its printable output
+pub fn execute<P: ErasedPool + ?Sized>(pool: &mut P, line: &str) -> Result<String, ConsoleError> {
+    let line = line.trim();
+    let (command, rest) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim_start()),
+        None => (line, "")
+    };
+    let mut args = rest.split_whitespace();
+    match command {
+        "help" => Ok(HELP.to_string()),
+        "list" => {
+            let lines: Vec<String> = pool.entity_ids().into_iter()
+                .map(|id| format!("{}: {}", id, component_names(pool, id).join(", ")))
+                .collect();
+            Ok(lines.join("\n"))
+        }
+        "inspect" => {
+            let id = parse_id(args.next(), "inspect <id>")?;
+            let mut out = format!("Entity {}", id);
+            for name in component_names(pool, id) {
+                if let Some(value) = pool.component_value(id, name) {
+                    out.push_str(&format!("\n    {}: {}", name, value));
+                }
+            }
+            Ok(out)
+        }
+        "set" => {
+            let usage = "set <id> <component> <json>";
+            let id = parse_id(args.next(), usage)?;
+            let name = args.next().ok_or(ConsoleError::Usage(usage))?;
+            let json = skip_words(rest, 2);
+            if json.is_empty() {
+                return Err(ConsoleError::Usage(usage));
+            }
+            let value: Value = serde_json::from_str(json).map_err(ValueError::from)?;
+            pool.set_component_value(id, name, value)?;
+            Ok(format!("set {} on {}", name, id))
+        }
+        "despawn" => {
+            let id = parse_id(args.next(), "despawn <id>")?;
+            pool.remove_entity(id);
+            Ok(format!("despawned {}", id))
+        }
+        "" => Ok(String::new()),
+        other => Err(ConsoleError::UnknownCommand(other.to_string()))
+    }
+}
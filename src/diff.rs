@@ -0,0 +1,90 @@
+//!
+//! Structural comparison of two pools, mostly useful for tests
+//!
+
+use std::fmt;
+use serde_json::Value;
+use super::{EntityId};
+use inspect::Inspect;
+
+///
+/// A single component difference between two pools
+///
+#[derive(Clone, PartialEq)]
+pub enum ComponentChange {
+    Added { entity: EntityId, component: &'static str, value: Value },
+    Removed { entity: EntityId, component: &'static str, value: Value },
+    Changed { entity: EntityId, component: &'static str, from: Value, to: Value }
+}
+
+impl fmt::Debug for ComponentChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ComponentChange::Added { entity, component, ref value } =>
+                write!(f, "+ {}.{} = {}", entity, component, value),
+            ComponentChange::Removed { entity, component, ref value } =>
+                write!(f, "- {}.{} = {}", entity, component, value),
+            ComponentChange::Changed { entity, component, ref from, ref to } =>
+                write!(f, "~ {}.{}: {} -> {}", entity, component, from, to)
+        }
+    }
+}
+
+///
+/// Differences between two pools, components are compared through their serialized values
+///
+#[derive(Clone, PartialEq, Default)]
+pub struct PoolDiff {
+    pub added_entities: Vec<EntityId>,
+    pub removed_entities: Vec<EntityId>,
+    pub changes: Vec<ComponentChange>
+}
+
+impl PoolDiff {
+    /// Changes needed to go from pool `a` to pool `b`
+    pub fn between<P: Inspect>(a: &P, b: &P) -> PoolDiff {
+        let mut diff = PoolDiff::default();
+        let (a_ids, b_ids) = (a.entity_ids(), b.entity_ids());
+        diff.added_entities = b_ids.iter().filter(|id| !a_ids.contains(id)).cloned().collect();
+        diff.removed_entities = a_ids.iter().filter(|id| !b_ids.contains(id)).cloned().collect();
+
+        let mut ids: Vec<EntityId> = a_ids.into_iter().chain(b_ids).collect();
+        ids.sort();
+        ids.dedup();
+        for id in ids {
+            for component in a.component_names() {
+                match (a.component_value(id, component), b.component_value(id, component)) {
+                    (None, Some(value)) => diff.changes.push(ComponentChange::Added { entity: id, component, value }),
+                    (Some(value), None) => diff.changes.push(ComponentChange::Removed { entity: id, component, value }),
+                    (Some(from), Some(to)) => {
+                        if from != to {
+                            diff.changes.push(ComponentChange::Changed { entity: id, component, from, to });
+                        }
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_entities.is_empty() && self.removed_entities.is_empty() && self.changes.is_empty()
+    }
+}
+
+impl fmt::Debug for PoolDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "PoolDiff {{")?;
+        for id in &self.added_entities {
+            writeln!(f, "    + entity {}", id)?;
+        }
+        for id in &self.removed_entities {
+            writeln!(f, "    - entity {}", id)?;
+        }
+        for change in &self.changes {
+            writeln!(f, "    {:?}", change)?;
+        }
+        write!(f, "}}")
+    }
+}
@@ -0,0 +1,533 @@
+//!
+//! A pool whose component types are registered at runtime instead of listed in a macro
+//!
+//! Components are found by `TypeId` and downcast, which costs a hash lookup per access compared
+//! to a generated pool, in exchange prototypes and plugins don't need to know every component at
+//! compile time. Saving and loading go through the names given when registering.
+//!
+//! Generated pools carry a `DynamicComponents` section as well, filled with `register_dynamic`,
+//! so mods can add components next to the pool's static ones and reach both through `get_any`
+//! and `set_any`.
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! # extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::dynamic::DynamicPool;
+//! use spawning_pool::storage::VectorStorage;
+//!
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Pos(i32, i32);
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Health(u32);
+//!
+//! let mut pool = DynamicPool::new();
+//! pool.register_with::<Pos, VectorStorage<Pos>>("Pos");
+//! pool.register::<Health>("Health");
+//!
+//! let id = pool.spawn_entity();
+//! pool.set(id, Pos(1, 2));
+//! pool.set(id, Health(5));
+//! pool.get_mut::<Health>(id).unwrap().0 -= 1;
+//!
+//! let mut bytes = vec![];
+//! pool.save(&mut bytes).unwrap();
+//! let mut loaded = DynamicPool::new();
+//! loaded.register_with::<Pos, VectorStorage<Pos>>("Pos");
+//! loaded.register::<Health>("Health");
+//! loaded.load(&bytes[..]).unwrap();
+//! assert_eq!(loaded.get::<Health>(id).unwrap().0, 4);
+//! # }
+//! ```
+//!
+
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{Read, Write};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use bitset::{EntityRanges, EntitySet};
+use cleanup::Destroyed;
+use save::{self, SaveError, SaveHeader};
+use pool::{HasComponent, Pool};
+use storage::{HashMapStorage, Storage};
+use super::{EntityId, RawEntityId, SpawnError};
+
+///
+/// Types that can be registered as dynamic components
+///
+pub trait DynamicComponent: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {}
+
+impl<T> DynamicComponent for T where T: Clone + Serialize + DeserializeOwned + Send + Sync + 'static {}
+
+///
+/// Storages that can hold dynamic components of type `T`
+///
+pub trait DynamicStorage<T: Clone>: Storage<T> + Clone + Serialize + DeserializeOwned + Send + Sync + 'static {}
+
+impl<T: Clone, S> DynamicStorage<T> for S where S: Storage<T> + Clone + Serialize + DeserializeOwned + Send + Sync + 'static {}
+
+/// A storage of `T` that can be saved without knowing its type
+trait ErasedStorage<T: Clone>: Storage<T> + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn to_value(&self) -> Result<Value, serde_json::Error>;
+    fn load_value(&mut self, value: Value) -> Result<(), serde_json::Error>;
+}
+
+impl<T: Clone, S> ErasedStorage<T> for S where S: Storage<T> + Serialize + DeserializeOwned + Send + Sync + 'static {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn to_value(&self) -> Result<Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+
+    fn load_value(&mut self, value: Value) -> Result<(), serde_json::Error> {
+        *self = serde_json::from_value(value)?;
+        Ok(())
+    }
+}
+
+struct Column<T: Clone> {
+    name: &'static str,
+    storage: Box<dyn ErasedStorage<T>>,
+    clone_storage: fn(&dyn ErasedStorage<T>) -> Box<dyn ErasedStorage<T>>
+}
+
+fn clone_storage<T, S>(storage: &dyn ErasedStorage<T>) -> Box<dyn ErasedStorage<T>>
+    where T: Clone + 'static, S: ErasedStorage<T> + Clone + 'static {
+    let storage = storage.as_any().downcast_ref::<S>().expect("column storage type doesn't change");
+    Box::new(storage.clone())
+}
+
+/// A column of any component type
+trait AnyColumn: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn clone_column(&self) -> Box<dyn AnyColumn>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn contains(&self, id: EntityId) -> bool;
+    fn remove(&mut self, id: EntityId);
+    fn copy(&mut self, from: EntityId, to: EntityId);
+    fn to_value(&self) -> Result<Value, serde_json::Error>;
+    fn load_value(&mut self, value: Value) -> Result<(), serde_json::Error>;
+}
+
+impl<T: Clone + 'static> AnyColumn for Column<T> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn clone_column(&self) -> Box<dyn AnyColumn> {
+        Box::new(Column {
+            name: self.name,
+            storage: (self.clone_storage)(&*self.storage),
+            clone_storage: self.clone_storage
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn contains(&self, id: EntityId) -> bool {
+        self.storage.get(id).is_some()
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        self.storage.remove(id);
+    }
+
+    fn copy(&mut self, from: EntityId, to: EntityId) {
+        if let Some(component) = self.storage.get(from).cloned() {
+            self.storage.set(to, component);
+        }
+    }
+
+    fn to_value(&self) -> Result<Value, serde_json::Error> {
+        self.storage.to_value()
+    }
+
+    fn load_value(&mut self, value: Value) -> Result<(), serde_json::Error> {
+        self.storage.load_value(value)
+    }
+}
+
+///
+/// Components registered at runtime, keyed by type, without any entity bookkeeping. Used by
+/// `DynamicPool` and as the dynamic section of generated pools.
+///
+#[derive(Default)]
+pub struct DynamicComponents {
+    columns: HashMap<TypeId, Box<dyn AnyColumn>>
+}
+
+impl DynamicComponents {
+    pub fn new() -> Self {
+        DynamicComponents { columns: HashMap::new() }
+    }
+
+    /// Registers component `T` stored in a `HashMapStorage`, `name` identifies it in save files
+    pub fn register<T: DynamicComponent>(&mut self, name: &'static str) {
+        self.register_with::<T, HashMapStorage<T>>(name);
+    }
+
+    /// Registers component `T` stored in an `S`, registering a type again does nothing
+    pub fn register_with<T: Clone + 'static, S: DynamicStorage<T>>(&mut self, name: &'static str) {
+        if self.columns.contains_key(&TypeId::of::<T>()) {
+            return;
+        }
+        assert!(
+            self.columns.values().all(|column| column.name() != name),
+            "component name `{}` is already registered", name
+        );
+        let column = Column::<T> {
+            name,
+            storage: Box::new(S::new()),
+            clone_storage: clone_storage::<T, S>
+        };
+        self.columns.insert(TypeId::of::<T>(), Box::new(column));
+    }
+
+    pub fn is_registered<T: 'static>(&self) -> bool {
+        self.columns.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Registered component names, sorted
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.columns.values().map(|column| column.name()).collect();
+        names.sort();
+        names
+    }
+
+    fn column<T: Clone + 'static>(&self) -> Option<&Column<T>> {
+        self.columns.get(&TypeId::of::<T>()).and_then(|column| column.as_any().downcast_ref())
+    }
+
+    fn column_mut<T: Clone + 'static>(&mut self) -> Option<&mut Column<T>> {
+        self.columns.get_mut(&TypeId::of::<T>()).and_then(|column| column.as_any_mut().downcast_mut())
+    }
+
+    /// The entity's component, `None` for unregistered types too
+    pub fn get<T: Clone + 'static>(&self, id: EntityId) -> Option<&T> {
+        self.column::<T>()?.storage.get(id)
+    }
+
+    pub fn get_mut<T: Clone + 'static>(&mut self, id: EntityId) -> Option<&mut T> {
+        self.column_mut::<T>()?.storage.get_mut(id)
+    }
+
+    /// Sets the entity's component, panics if `T` isn't registered
+    pub fn set<T: Clone + 'static>(&mut self, id: EntityId, component: T) {
+        let column = self.column_mut::<T>().expect("component type isn't registered");
+        column.storage.set(id, component);
+    }
+
+    pub fn remove<T: Clone + 'static>(&mut self, id: EntityId) {
+        if let Some(column) = self.column_mut::<T>() {
+            column.storage.remove(id);
+        }
+    }
+
+    /// Number of stored `T` components
+    pub fn count<T: Clone + 'static>(&self) -> usize {
+        self.column::<T>().map_or(0, |column| column.storage.len())
+    }
+
+    /// Every stored `T` component
+    pub fn get_all<T: Clone + 'static>(&self) -> Vec<(EntityId, &T)> {
+        self.column::<T>().map_or_else(Vec::new, |column| column.storage.get_all())
+    }
+
+    /// Removes every component of the entity, returning the sorted names of the removed ones
+    pub fn remove_entity(&mut self, id: EntityId) -> Vec<&'static str> {
+        let mut names = vec![];
+        for column in self.columns.values_mut() {
+            if column.contains(id) {
+                names.push(column.name());
+                column.remove(id);
+            }
+        }
+        names.sort();
+        names
+    }
+
+    /// Clones every component of `from` onto `to`
+    pub fn copy_entity(&mut self, from: EntityId, to: EntityId) {
+        for column in self.columns.values_mut() {
+            column.copy(from, to);
+        }
+    }
+
+    /// Hash of the registered component names, stored in save headers
+    pub fn schema_hash(&self) -> u64 {
+        let schema: Vec<_> = self.names().into_iter().map(|name| (name, name, "")).collect();
+        save::schema_hash(&schema)
+    }
+
+    /// Every storage as JSON, keyed by component name
+    pub fn to_value(&self) -> Result<Value, serde_json::Error> {
+        let mut components = serde_json::Map::new();
+        for column in self.columns.values() {
+            components.insert(column.name().to_string(), column.to_value()?);
+        }
+        Ok(Value::Object(components))
+    }
+
+    /// Replaces the storages with the ones in `value`, as written by `to_value`, every
+    /// registered component has to be present
+    pub fn load_value(&mut self, value: Value) -> Result<(), SaveError> {
+        let mut components: BTreeMap<String, Value> = serde_json::from_value(value)?;
+        for column in self.columns.values_mut() {
+            let value = components.remove(column.name())
+                .ok_or_else(|| SaveError::UnknownComponent(column.name().to_string()))?;
+            column.load_value(value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Clone for DynamicComponents {
+    fn clone(&self) -> Self {
+        DynamicComponents {
+            columns: self.columns.iter().map(|(&type_id, column)| (type_id, column.clone_column())).collect()
+        }
+    }
+}
+
+impl fmt::Debug for DynamicComponents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+///
+/// Pool with components registered at runtime, see the module documentation
+///
+#[derive(Debug, Clone)]
+pub struct DynamicPool {
+    next_id: RawEntityId,
+    removed: EntitySet,
+    destroyed: EntityRanges,
+    components: DynamicComponents
+}
+
+impl DynamicPool {
+    pub fn new() -> Self {
+        DynamicPool {
+            next_id: 1,
+            removed: EntitySet::new(),
+            destroyed: EntityRanges::new(),
+            components: DynamicComponents::new()
+        }
+    }
+
+    /// Registers component `T` stored in a `HashMapStorage`, `name` identifies it in save files
+    pub fn register<T: DynamicComponent>(&mut self, name: &'static str) {
+        self.components.register::<T>(name);
+    }
+
+    /// Registers component `T` stored in an `S`, registering a type again does nothing
+    pub fn register_with<T: Clone + 'static, S: DynamicStorage<T>>(&mut self, name: &'static str) {
+        self.components.register_with::<T, S>(name);
+    }
+
+    pub fn is_registered<T: 'static>(&self) -> bool {
+        self.components.is_registered::<T>()
+    }
+
+    /// Registered component names, sorted
+    pub fn component_names(&self) -> Vec<&'static str> {
+        self.components.names()
+    }
+
+    /// Panics when the pool runs out of entity IDs, see `try_spawn_entity`
+    pub fn spawn_entity(&mut self) -> EntityId {
+        match self.try_spawn_entity() {
+            Ok(id) => id,
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    /// Like `spawn_entity`, returning an error when the pool runs out of IDs, reuses the IDs of
+    /// destroyed entities once every ID has been handed out
+    pub fn try_spawn_entity(&mut self) -> Result<EntityId, SpawnError> {
+        if self.next_id < RawEntityId::MAX {
+            let id = EntityId::from_raw(self.next_id).expect("entity ids start at 1");
+            self.next_id += 1;
+            return Ok(id);
+        }
+        let id = self.destroyed.first().ok_or(SpawnError::Exhausted)?;
+        self.destroyed.remove(&id);
+        Ok(id)
+    }
+
+    pub fn is_alive(&self, id: EntityId) -> bool {
+        id.to_raw() < self.next_id && !self.removed.contains(&id) && !self.destroyed.contains(&id)
+    }
+
+    pub fn remove_entity(&mut self, id: EntityId) {
+        if self.is_alive(id) {
+            self.removed.insert(id);
+        }
+    }
+
+    /// Destroys the components of all entities pending removal and reports them, sorted by
+    /// entity id
+    pub fn cleanup_removed(&mut self) -> Vec<Destroyed> {
+        let mut destroyed = vec![];
+        for id in self.removed.drain() {
+            let components = self.components.remove_entity(id);
+            self.destroyed.insert(id);
+            destroyed.push(Destroyed { id, components });
+        }
+        destroyed
+    }
+
+    /// The entity's component, `None` for unregistered types too
+    pub fn get<T: Clone + 'static>(&self, id: EntityId) -> Option<&T> {
+        if !self.is_alive(id) {
+            return None;
+        }
+        self.components.get(id)
+    }
+
+    pub fn get_mut<T: Clone + 'static>(&mut self, id: EntityId) -> Option<&mut T> {
+        if !self.is_alive(id) {
+            return None;
+        }
+        self.components.get_mut(id)
+    }
+
+    /// Sets the entity's component, panics if `T` isn't registered
+    pub fn set<T: Clone + 'static>(&mut self, id: EntityId, component: T) {
+        if self.is_alive(id) {
+            self.components.set(id, component);
+        }
+    }
+
+    pub fn remove<T: Clone + 'static>(&mut self, id: EntityId) {
+        self.components.remove::<T>(id);
+    }
+
+    /// Number of entities with component `T`, pending removals included
+    pub fn count<T: Clone + 'static>(&self) -> usize {
+        self.components.count::<T>()
+    }
+
+    /// Number of alive entities, with or without components
+    pub fn entity_count(&self) -> usize {
+        (self.next_id as usize - 1) - self.removed.len() - self.destroyed.len()
+    }
+
+    /// Every live entity with component `T`
+    pub fn get_all<T: Clone + 'static>(&self) -> Vec<(EntityId, &T)> {
+        let mut all = self.components.get_all();
+        all.retain(|(id, _)| !self.removed.contains(id));
+        all
+    }
+
+    /// Hash of the registered component names, stored in save headers
+    pub fn schema_hash(&self) -> u64 {
+        self.components.schema_hash()
+    }
+
+    /// Writes a save file header followed by the pool as JSON, storages keyed by their names
+    pub fn save<W: Write>(&self, mut writer: W) -> Result<(), SaveError> {
+        let header = SaveHeader {
+            version: save::FORMAT_VERSION,
+            schema: self.schema_hash()
+        };
+        header.write(&mut writer)?;
+        let mut fields = serde_json::Map::new();
+        fields.insert("next_id".to_string(), serde_json::to_value(self.next_id)?);
+        fields.insert("removed".to_string(), serde_json::to_value(&self.removed)?);
+        fields.insert("destroyed".to_string(), serde_json::to_value(&self.destroyed)?);
+        fields.insert("components".to_string(), self.components.to_value()?);
+        serde_json::to_writer(writer, &Value::Object(fields))?;
+        Ok(())
+    }
+
+    /// Replaces the entities and components of the pool with the ones of a save file written
+    /// by a pool with the same registered names
+    pub fn load<R: Read>(&mut self, mut reader: R) -> Result<(), SaveError> {
+        let header = SaveHeader::read(&mut reader)?;
+        if header.schema != self.schema_hash() {
+            return Err(SaveError::SchemaMismatch {
+                expected: self.schema_hash(),
+                found: header.schema
+            });
+        }
+        #[derive(Deserialize)]
+        struct Saved {
+            next_id: RawEntityId,
+            removed: EntitySet,
+            destroyed: EntityRanges,
+            components: Value
+        }
+        let saved: Saved = serde_json::from_reader(reader)?;
+        self.components.load_value(saved.components)?;
+        self.next_id = saved.next_id;
+        self.removed = saved.removed;
+        self.destroyed = saved.destroyed;
+        Ok(())
+    }
+}
+
+impl Default for DynamicPool {
+    fn default() -> Self {
+        DynamicPool::new()
+    }
+}
+
+impl Pool for DynamicPool {
+    fn spawn_entity(&mut self) -> EntityId {
+        DynamicPool::spawn_entity(self)
+    }
+
+    fn remove_entity(&mut self, id: EntityId) {
+        DynamicPool::remove_entity(self, id);
+    }
+
+    fn cleanup_removed(&mut self) -> Vec<Destroyed> {
+        DynamicPool::cleanup_removed(self)
+    }
+
+    fn is_alive(&self, id: EntityId) -> bool {
+        DynamicPool::is_alive(self, id)
+    }
+
+    fn entity_count(&self) -> usize {
+        DynamicPool::entity_count(self)
+    }
+
+    fn fork(&self) -> Self {
+        self.clone()
+    }
+}
+
+/// Every type is accepted, `insert_component` panics like `set` for unregistered ones
+impl<T: Clone + 'static> HasComponent<T> for DynamicPool {
+    fn component(&self, id: EntityId) -> Option<&T> {
+        DynamicPool::get(self, id)
+    }
+
+    fn component_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        DynamicPool::get_mut(self, id)
+    }
+
+    fn insert_component(&mut self, id: EntityId, component: T) {
+        DynamicPool::set(self, id, component);
+    }
+
+    fn clear_component(&mut self, id: EntityId) {
+        DynamicPool::remove::<T>(self, id);
+    }
+}
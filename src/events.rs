@@ -0,0 +1,190 @@
+//!
+//! Event channels stored in the pool, so systems can talk to each other through the world they
+//! already share
+//!
+//! Every reader keeps its own cursor, so any number of systems can read the same events.
+//! Events are kept until the second `update_events` after they were sent, which gives every
+//! system a full frame to see them when `update_events` is called once per frame.
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::EntityId;
+//! use spawning_pool::events::EventReader;
+//! use spawning_pool::storage::{Storage, VectorStorage};
+//!
+//! # #[derive(Clone, Debug, Serialize, Deserialize)]
+//! # struct Pos(i32, i32);
+//! struct Damage { target: EntityId, amount: u32 }
+//!
+//! create_spawning_pool!((Pos, pos, VectorStorage));
+//! let mut pool = SpawningPool::new();
+//! let mut reader = EventReader::default();
+//! let target = pool.spawn_entity();
+//! pool.emit(Damage { target, amount: 5 });
+//!
+//! let total: u32 = pool.events::<Damage>().read(&mut reader).map(|d| d.amount).sum();
+//! assert_eq!(total, 5);
+//! assert_eq!(pool.events::<Damage>().read(&mut reader).count(), 0);
+//! # }
+//! ```
+//!
+
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+
+///
+/// Cursor of one reader into an event channel, starting at the oldest kept event
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventReader {
+    cursor: u64
+}
+
+///
+/// Events of type `E` in the order they were sent
+///
+#[derive(Debug)]
+pub struct EventChannel<E> {
+    events: VecDeque<E>,
+    /// Sequence number of the first event in `events`
+    first: u64,
+    /// Sequence number of the first event sent since the last `update`
+    frame_start: u64
+}
+
+impl<E> Default for EventChannel<E> {
+    fn default() -> Self {
+        EventChannel {
+            events: VecDeque::new(),
+            first: 0,
+            frame_start: 0
+        }
+    }
+}
+
+impl<E> EventChannel<E> {
+    pub fn send(&mut self, event: E) {
+        self.events.push_back(event);
+    }
+
+    /// Reader that only sees events sent from now on
+    pub fn reader(&self) -> EventReader {
+        EventReader { cursor: self.end() }
+    }
+
+    /// Events the reader hasn't seen yet, advancing its cursor past them. Readers that fell
+    /// behind skip the events already dropped.
+    pub fn read<'a>(&'a self, reader: &mut EventReader) -> impl Iterator<Item = &'a E> + 'a {
+        let skip = reader.cursor.saturating_sub(self.first) as usize;
+        reader.cursor = self.end();
+        self.events.iter().skip(skip)
+    }
+
+    /// Drops the events sent before the previous `update`
+    pub fn update(&mut self) {
+        let stale = (self.frame_start - self.first) as usize;
+        self.events.drain(..stale);
+        self.first = self.frame_start;
+        self.frame_start = self.end();
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    fn end(&self) -> u64 {
+        self.first + self.events.len() as u64
+    }
+}
+
+///
+/// Read access to the channel of one event type, empty if nothing was ever sent
+///
+pub struct Events<'a, E: 'a> {
+    channel: Option<&'a EventChannel<E>>
+}
+
+impl<'a, E> Events<'a, E> {
+    pub fn read(&self, reader: &mut EventReader) -> Box<dyn Iterator<Item = &'a E> + 'a> {
+        match self.channel {
+            Some(channel) => Box::new(channel.read(reader)),
+            None => Box::new(::std::iter::empty())
+        }
+    }
+
+    /// Reader that only sees events sent from now on
+    pub fn reader(&self) -> EventReader {
+        self.channel.map(|c| c.reader()).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.channel.map(|c| c.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+trait AnyChannel: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn update(&mut self);
+}
+
+impl<E: Send + Sync + 'static> AnyChannel for EventChannel<E> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn update(&mut self) {
+        EventChannel::update(self);
+    }
+}
+
+///
+/// Event channels of a pool, one per event type
+///
+#[derive(Default)]
+pub struct EventRegistry {
+    channels: HashMap<TypeId, Box<dyn AnyChannel>>
+}
+
+impl EventRegistry {
+    pub fn get<E: Send + Sync + 'static>(&self) -> Events<'_, E> {
+        Events {
+            channel: self.channels.get(&TypeId::of::<E>()).and_then(|c| c.as_any().downcast_ref())
+        }
+    }
+
+    pub fn get_mut<E: Send + Sync + 'static>(&mut self) -> &mut EventChannel<E> {
+        self.channels.entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(EventChannel::<E>::default()))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("event channel stored under its own type id")
+    }
+
+    /// Calls `update` on every channel
+    pub fn update(&mut self) {
+        for channel in self.channels.values_mut() {
+            channel.update();
+        }
+    }
+}
+
+impl ::std::fmt::Debug for EventRegistry {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "EventRegistry({} channels)", self.channels.len())
+    }
+}
@@ -0,0 +1,71 @@
+//!
+//! Tabular export of component data, for looking at world state in spreadsheets or pandas
+//!
+
+use std::io::{self, Write};
+use serde_json::{Map, Value};
+use super::EntityId;
+
+/// Flattens a serialized component into `(column, cell)` pairs, nested objects get dotted column
+/// names and components that aren't structs end up in a single `value` column
+pub fn flatten(value: &Value) -> Vec<(String, String)> {
+    let mut columns = vec![];
+    match *value {
+        Value::Object(ref fields) => flatten_object("", fields, &mut columns),
+        ref other => columns.push(("value".to_string(), cell(other)))
+    }
+    columns
+}
+
+fn flatten_object(prefix: &str, fields: &Map<String, Value>, columns: &mut Vec<(String, String)>) {
+    for (name, value) in fields {
+        let name = format!("{}{}", prefix, name);
+        match *value {
+            Value::Object(ref nested) => flatten_object(&format!("{}.", name), nested, columns),
+            ref other => columns.push((name, cell(other)))
+        }
+    }
+}
+
+fn cell(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref s) => s.clone(),
+        ref other => other.to_string()
+    }
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes the rows as CSV with an `entity_id` column followed by the columns of every row in
+/// first seen order, cells of columns a row doesn't have are left empty
+pub fn write_csv<W: Write>(mut writer: W, rows: &[(EntityId, Value)]) -> io::Result<()> {
+    let rows: Vec<(EntityId, Vec<(String, String)>)> = rows.iter()
+        .map(|(id, value)| (*id, flatten(value)))
+        .collect();
+    let mut header: Vec<&str> = vec![];
+    for (_, columns) in &rows {
+        for (name, _) in columns {
+            if !header.contains(&name.as_str()) {
+                header.push(name);
+            }
+        }
+    }
+    let line: Vec<String> = Some("entity_id").into_iter().chain(header.iter().cloned()).map(escape).collect();
+    writeln!(writer, "{}", line.join(","))?;
+    for (id, columns) in &rows {
+        let mut line = vec![id.to_string()];
+        for name in &header {
+            let value = columns.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str()).unwrap_or("");
+            line.push(escape(value));
+        }
+        writeln!(writer, "{}", line.join(","))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//!
+//! Fetching several components of one entity at once with `get_components`
+//!
+
+use super::{EntityId};
+
+///
+/// Implemented by generated pools for every registered component type
+///
+pub trait GetComponent<T> {
+    /// Position of the component in the macro invocation
+    const INDEX: usize;
+    fn get_component(&self, id: EntityId) -> Option<&T>;
+}
+
+///
+/// A tuple of component types fetched together from pool `P`
+///
+pub trait Fetch<'a, P> {
+    type Item;
+    fn fetch(pool: &'a P, id: EntityId) -> Option<Self::Item>;
+
+    /// Whether the entity has every component of the tuple
+    fn exists(pool: &P, id: EntityId) -> bool;
+
+    /// Whether the component at `index` is part of the tuple
+    fn contains(index: usize) -> bool;
+}
+
+macro_rules! tuple_fetch {
+    ($($name:ident),+) => {
+        impl<'a, P, $($name: 'a),+> Fetch<'a, P> for ($($name,)+) where $(P: GetComponent<$name>),+ {
+            type Item = ($(&'a $name,)+);
+
+            fn fetch(pool: &'a P, id: EntityId) -> Option<Self::Item> {
+                Some(($(GetComponent::<$name>::get_component(pool, id)?,)+))
+            }
+
+            fn exists(pool: &P, id: EntityId) -> bool {
+                $(GetComponent::<$name>::get_component(pool, id).is_some())&&+
+            }
+
+            fn contains(index: usize) -> bool {
+                $(<P as GetComponent<$name>>::INDEX == index)||+
+            }
+        }
+    };
+}
+
+tuple_fetch!(A);
+tuple_fetch!(A, B);
+tuple_fetch!(A, B, C);
+tuple_fetch!(A, B, C, D);
+tuple_fetch!(A, B, C, D, E);
+tuple_fetch!(A, B, C, D, E, F);
+tuple_fetch!(A, B, C, D, E, F, G);
+tuple_fetch!(A, B, C, D, E, F, G, H);
+tuple_fetch!(A, B, C, D, E, F, G, H, I);
+tuple_fetch!(A, B, C, D, E, F, G, H, I, J);
+tuple_fetch!(A, B, C, D, E, F, G, H, I, J, K);
+tuple_fetch!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+///
+/// Iterator over every unordered pair of entities having a component, returned by
+/// `iter_combinations`
+///
+pub struct Combinations<'a, T: 'a> {
+    items: Vec<(EntityId, &'a T)>,
+    a: usize,
+    b: usize
+}
+
+impl<'a, T> Combinations<'a, T> {
+    pub fn new(items: Vec<(EntityId, &'a T)>) -> Self {
+        Combinations { items, a: 0, b: 1 }
+    }
+}
+
+impl<'a, T> Iterator for Combinations<'a, T> {
+    type Item = ((EntityId, &'a T), (EntityId, &'a T));
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.b >= self.items.len() {
+            self.a += 1;
+            self.b = self.a + 1;
+            if self.b >= self.items.len() {
+                return None;
+            }
+        }
+        let pair = (self.items[self.a], self.items[self.b]);
+        self.b += 1;
+        Some(pair)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.items.len();
+        let left = if self.b >= n {
+            if self.a + 1 >= n { 0 } else { (n - self.a - 1) * (n - self.a - 2) / 2 }
+        } else {
+            (n - self.b) + (n - self.a - 1) * (n - self.a - 2) / 2
+        };
+        (left, Some(left))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Combinations<'a, T> {}
+
+///
+/// Iterator over the live entities having a component in groups of at most `chunk_size`,
+/// returned by `iter_chunks`
+///
+/// Groups follow storage order, so a `VectorStorage` yields them by ascending entity id. Each
+/// group is an owned `Vec` that can be handed to another thread.
+///
+pub struct Chunks<'a, T: 'a> {
+    items: ::std::vec::IntoIter<(EntityId, &'a T)>,
+    chunk_size: usize
+}
+
+impl<'a, T> Chunks<'a, T> {
+    pub fn new(items: Vec<(EntityId, &'a T)>, chunk_size: usize) -> Self {
+        assert!(chunk_size != 0, "chunk size must be non-zero");
+        Chunks { items: items.into_iter(), chunk_size }
+    }
+}
+
+impl<'a, T> Iterator for Chunks<'a, T> {
+    type Item = Vec<(EntityId, &'a T)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk: Vec<_> = self.items.by_ref().take(self.chunk_size).collect();
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.items.len().div_ceil(self.chunk_size);
+        (left, Some(left))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Chunks<'a, T> {}
+
+///
+/// Keeps the allocation of a `get_all_into` buffer between frames
+///
+/// A `Vec<(EntityId, &T)>` can't outlive the borrow of the pool it was filled from, so it can't
+/// simply be stored in a system. `ScratchBuffer` stores the empty allocation instead and lends it
+/// out with a fresh lifetime each frame.
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// # #[macro_use] extern crate spawning_pool;
+/// # fn main() {
+/// # use spawning_pool::EntityId;
+/// # use spawning_pool::storage::{Storage, VectorStorage};
+/// use spawning_pool::fetch::ScratchBuffer;
+///
+/// #[derive(Clone, Debug, Serialize, Deserialize)]
+/// struct Pos(i32, i32);
+///
+/// create_spawning_pool!((Pos, pos, VectorStorage));
+/// let mut pool = SpawningPool::new();
+/// let mut scratch = ScratchBuffer::new();
+/// for frame in 0..3 {
+///     let id = pool.spawn_entity();
+///     pool.set(id, Pos(frame, 0));
+///
+///     let mut all = scratch.take();
+///     pool.get_all_into::<Pos>(&mut all);
+///     assert_eq!(all.len(), frame as usize + 1);
+///     scratch.give_back(all);
+/// }
+/// # }
+/// ```
+///
+#[derive(Debug)]
+pub struct ScratchBuffer<T: 'static> {
+    items: Vec<(EntityId, &'static T)>
+}
+
+impl<T: 'static> ScratchBuffer<T> {
+    pub fn new() -> Self {
+        ScratchBuffer { items: vec![] }
+    }
+
+    /// The stored allocation as an empty `Vec`
+    pub fn take<'a>(&mut self) -> Vec<(EntityId, &'a T)> {
+        recycle(::std::mem::take(&mut self.items))
+    }
+
+    /// Stores the allocation of `items` for the next `take`, dropping its contents
+    pub fn give_back(&mut self, items: Vec<(EntityId, &T)>) {
+        self.items = recycle(items);
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.items.capacity()
+    }
+}
+
+impl<T: 'static> Default for ScratchBuffer<T> {
+    fn default() -> Self {
+        ScratchBuffer::new()
+    }
+}
+
+/// Empties the `Vec` and changes the lifetime of its references, collecting an empty iterator of
+/// the same layout reuses the allocation
+fn recycle<'b, T>(mut items: Vec<(EntityId, &T)>) -> Vec<(EntityId, &'b T)> {
+    items.clear();
+    items.into_iter().map(|_| unreachable!()).collect()
+}
@@ -0,0 +1,285 @@
+//!
+//! C FFI layer, enabled with the `ffi` feature
+//!
+//! The Rust side creates the pool with `create_spawning_pool!` as usual and hands it over with
+//! `into_raw`. From there the host engine drives it through the `extern "C"` functions below.
+//! Components are addressed by their registered index (declaration order in the macro) and passed
+//! across the boundary as JSON encoded buffers. Entity ids are passed as their raw value, where 0
+//! means no entity.
+//!
+
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+use serde_json;
+use super::{EntityId, RawEntityId};
+use inspect::ErasedPool;
+
+///
+/// Opaque handle to a pool owned by the FFI layer
+///
+pub struct FfiPool {
+    pool: Box<dyn ErasedPool>
+}
+
+///
+/// Byte buffer allocated by Rust, must be released with `spawning_pool_buffer_free`
+///
+#[repr(C)]
+pub struct FfiBuffer {
+    pub data: *mut u8,
+    pub len: usize
+}
+
+impl FfiBuffer {
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let mut bytes = bytes.into_boxed_slice();
+        let buffer = FfiBuffer {
+            data: bytes.as_mut_ptr(),
+            len: bytes.len()
+        };
+        ::std::mem::forget(bytes);
+        buffer
+    }
+}
+
+///
+/// Moves a pool behind an opaque handle for use from C
+///
+pub fn into_raw<P: ErasedPool + 'static>(pool: P) -> *mut FfiPool {
+    Box::into_raw(Box::new(FfiPool {
+        pool: Box::new(pool)
+    }))
+}
+
+unsafe fn pool_mut<'a>(pool: *mut FfiPool) -> Option<&'a mut FfiPool> {
+    pool.as_mut()
+}
+
+/// Releases a pool handle created by `into_raw`
+///
+/// # Safety
+/// `pool` must be null or a handle from `into_raw` that hasn't been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_free(pool: *mut FfiPool) {
+    if !pool.is_null() {
+        drop(Box::from_raw(pool));
+    }
+}
+
+/// Spawns a new entity and returns its raw id, or 0 if `pool` is null or the ids ran out
+///
+/// # Safety
+/// `pool` must be null or a live handle from `into_raw`.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_spawn(pool: *mut FfiPool) -> RawEntityId {
+    match pool_mut(pool).map(|p| p.pool.try_spawn_entity()) {
+        Some(Ok(id)) => id.to_raw(),
+        _ => 0
+    }
+}
+
+/// Marks an entity for removal
+///
+/// # Safety
+/// `pool` must be null or a live handle from `into_raw`.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_despawn(pool: *mut FfiPool, id: RawEntityId) {
+    if let (Some(p), Some(id)) = (pool_mut(pool), EntityId::from_raw(id)) {
+        p.pool.remove_entity(id);
+    }
+}
+
+/// Removes the components of all entities marked for removal, returns how many were destroyed
+///
+/// # Safety
+/// `pool` must be null or a live handle from `into_raw`.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_cleanup(pool: *mut FfiPool) -> usize {
+    match pool_mut(pool) {
+        Some(p) => p.pool.cleanup_removed().len(),
+        None => 0
+    }
+}
+
+/// Number of registered component types
+///
+/// # Safety
+/// `pool` must be null or a live handle from `into_raw`.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_component_count(pool: *mut FfiPool) -> usize {
+    match pool_mut(pool) {
+        Some(p) => p.pool.component_names().len(),
+        None => 0
+    }
+}
+
+/// Registered id of the component type called `name`, or -1 if there is none
+///
+/// # Safety
+/// `pool` must be null or a live handle from `into_raw`, `name` must be null or a valid nul
+/// terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_component_id(pool: *mut FfiPool, name: *const c_char) -> i32 {
+    let p = match pool_mut(pool) {
+        Some(p) => p,
+        None => return -1
+    };
+    if name.is_null() {
+        return -1;
+    }
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) => name,
+        Err(_) => return -1
+    };
+    match p.pool.component_names().iter().position(|n| *n == name) {
+        Some(index) => index as i32,
+        None => -1
+    }
+}
+
+/// Sets a component from a JSON encoded buffer, returns 0 on success and -1 on failure, including
+/// when the entity isn't alive
+///
+/// # Safety
+/// `pool` must be null or a live handle from `into_raw`, `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_set_component(pool: *mut FfiPool, id: RawEntityId, component: u32, data: *const u8, len: usize) -> i32 {
+    let (p, id) = match (pool_mut(pool), EntityId::from_raw(id)) {
+        (Some(p), Some(id)) => (p, id),
+        _ => return -1
+    };
+    if !p.pool.is_alive(id) {
+        return -1;
+    }
+    let name = match p.pool.component_names().get(component as usize) {
+        Some(name) => *name,
+        None => return -1
+    };
+    if data.is_null() {
+        return -1;
+    }
+    let value = match serde_json::from_slice(slice::from_raw_parts(data, len)) {
+        Ok(value) => value,
+        Err(_) => return -1
+    };
+    match p.pool.set_component_value(id, name, value) {
+        Ok(()) => 0,
+        Err(_) => -1
+    }
+}
+
+/// Writes a JSON encoded component into `out`, returns 0 on success and -1 if the entity doesn't
+/// have the component
+///
+/// # Safety
+/// `pool` must be null or a live handle from `into_raw`, `out` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_get_component(pool: *mut FfiPool, id: RawEntityId, component: u32, out: *mut FfiBuffer) -> i32 {
+    let (p, id) = match (pool_mut(pool), EntityId::from_raw(id)) {
+        (Some(p), Some(id)) => (p, id),
+        _ => return -1
+    };
+    if out.is_null() {
+        return -1;
+    }
+    let value = match p.pool.component_names().get(component as usize) {
+        Some(name) => p.pool.component_value(id, name),
+        None => None
+    };
+    match value.and_then(|v| serde_json::to_vec(&v).ok()) {
+        Some(bytes) => {
+            ptr::write(out, FfiBuffer::from_vec(bytes));
+            0
+        }
+        None => -1
+    }
+}
+
+/// Serializes the whole pool as JSON into `out`, returns 0 on success and -1 on failure
+///
+/// # Safety
+/// `pool` must be null or a live handle from `into_raw`, `out` must be null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_serialize(pool: *mut FfiPool, out: *mut FfiBuffer) -> i32 {
+    let p = match pool_mut(pool) {
+        Some(p) => p,
+        None => return -1
+    };
+    if out.is_null() {
+        return -1;
+    }
+    match p.pool.to_json_bytes() {
+        Ok(bytes) => {
+            ptr::write(out, FfiBuffer::from_vec(bytes));
+            0
+        }
+        Err(_) => -1
+    }
+}
+
+/// Releases a buffer filled in by this library
+///
+/// # Safety
+/// `buffer` must come from one of the functions above and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn spawning_pool_buffer_free(buffer: FfiBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use storage::*;
+    use create_spawning_pool;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Position {
+        pub x: i32,
+        pub y: i32
+    }
+
+    #[test]
+    fn test_ffi_roundtrip() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage)
+        );
+        let pool = into_raw(SpawningPool::new());
+        unsafe {
+            let id = spawning_pool_spawn(pool);
+            assert_eq!(id, 1);
+            assert_eq!(spawning_pool_component_count(pool), 1);
+
+            let name = CString::new("Position").unwrap();
+            let component = spawning_pool_component_id(pool, name.as_ptr());
+            assert_eq!(component, 0);
+
+            let json = b"{\"x\": 4, \"y\": 2}";
+            assert_eq!(spawning_pool_set_component(pool, id, 0, json.as_ptr(), json.len()), 0);
+            assert_eq!(spawning_pool_set_component(pool, id, 1, json.as_ptr(), json.len()), -1);
+
+            let mut out = FfiBuffer { data: ptr::null_mut(), len: 0 };
+            assert_eq!(spawning_pool_get_component(pool, id, 0, &mut out), 0);
+            assert_eq!(slice::from_raw_parts(out.data, out.len), b"{\"x\":4,\"y\":2}");
+            spawning_pool_buffer_free(out);
+
+            let mut out = FfiBuffer { data: ptr::null_mut(), len: 0 };
+            assert_eq!(spawning_pool_serialize(pool, &mut out), 0);
+            assert!(out.len > 0);
+            spawning_pool_buffer_free(out);
+
+            spawning_pool_despawn(pool, id);
+            assert_eq!(spawning_pool_cleanup(pool), 1);
+            let mut out = FfiBuffer { data: ptr::null_mut(), len: 0 };
+            assert_eq!(spawning_pool_get_component(pool, id, 0, &mut out), -1);
+            assert_eq!(spawning_pool_set_component(pool, id, 0, json.as_ptr(), json.len()), -1);
+            assert_eq!(spawning_pool_set_component(pool, 100, 0, json.as_ptr(), json.len()), -1);
+
+            spawning_pool_free(pool);
+        }
+    }
+}
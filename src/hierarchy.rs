@@ -0,0 +1,143 @@
+//!
+//! Parent/child links between entities, kept consistent by the pool when entities are destroyed
+//!
+
+use std::collections::HashMap;
+use map::{EntityMap, MapEntities};
+use super::EntityId;
+
+///
+/// What happens to the children of a despawned entity
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanPolicy {
+    /// Children and all their descendants are despawned too
+    Despawn,
+    /// Children stay alive without a parent
+    Detach,
+    /// Children are attached to the despawned entity's parent, or detached if it has none
+    Reparent
+}
+
+///
+/// Parent of every child entity along with the reverse lookup
+///
+/// Serializes as a list of `(child, parent)` pairs sorted by child.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<(EntityId, EntityId)>", into = "Vec<(EntityId, EntityId)>")]
+pub struct Hierarchy {
+    parents: HashMap<EntityId, EntityId>,
+    children: HashMap<EntityId, Vec<EntityId>>
+}
+
+impl Hierarchy {
+    pub fn parent(&self, id: EntityId) -> Option<EntityId> {
+        self.parents.get(&id).cloned()
+    }
+
+    /// Children in the order they were attached
+    pub fn children(&self, id: EntityId) -> &[EntityId] {
+        match self.children.get(&id) {
+            Some(children) => children,
+            None => &[]
+        }
+    }
+
+    /// Every descendant of the entity, parents before their children
+    pub fn descendants(&self, id: EntityId) -> Vec<EntityId> {
+        let mut descendants = vec![];
+        let mut stack: Vec<EntityId> = self.children(id).iter().rev().cloned().collect();
+        while let Some(next) = stack.pop() {
+            descendants.push(next);
+            stack.extend(self.children(next).iter().rev());
+        }
+        descendants
+    }
+
+    /// Attaches `child` to `parent`, replacing its previous parent. Returns `false` and changes
+    /// nothing if `parent` is `child` or one of its descendants.
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) -> bool {
+        let mut ancestor = Some(parent);
+        while let Some(id) = ancestor {
+            if id == child {
+                return false;
+            }
+            ancestor = self.parent(id);
+        }
+        self.remove_parent(child);
+        self.parents.insert(child, parent);
+        self.children.entry(parent).or_default().push(child);
+        true
+    }
+
+    /// Detaches the entity from its parent, returns the parent it had
+    pub fn remove_parent(&mut self, child: EntityId) -> Option<EntityId> {
+        let parent = self.parents.remove(&child)?;
+        if let Some(siblings) = self.children.get_mut(&parent) {
+            siblings.retain(|id| *id != child);
+            if siblings.is_empty() {
+                self.children.remove(&parent);
+            }
+        }
+        Some(parent)
+    }
+
+    /// Adds the links of `other`, whose entities don't overlap with these, children keep their
+    /// order
+    pub fn merge(&mut self, other: Hierarchy) {
+        let mut parents: Vec<_> = other.children.keys().cloned().collect();
+        parents.sort();
+        for parent in parents {
+            for &child in other.children(parent) {
+                self.set_parent(child, parent);
+            }
+        }
+    }
+
+    /// Drops every link of the entity, its children are detached
+    pub fn remove(&mut self, id: EntityId) {
+        self.remove_parent(id);
+        if let Some(children) = self.children.remove(&id) {
+            for child in children {
+                self.parents.remove(&child);
+            }
+        }
+    }
+}
+
+impl MapEntities for Hierarchy {
+    /// Drops the links of entities that aren't in the map, children keep their order
+    fn map_entities(&mut self, map: &EntityMap) {
+        self.parents = self.parents.iter()
+            .filter_map(|(child, parent)| Some((map.get(*child)?, map.get(*parent)?)))
+            .collect();
+        self.children = self.children.iter()
+            .filter_map(|(parent, children)| {
+                let children: Vec<_> = children.iter().filter_map(|child| map.get(*child)).collect();
+                if children.is_empty() {
+                    return None;
+                }
+                Some((map.get(*parent)?, children))
+            })
+            .collect();
+    }
+}
+
+impl From<Vec<(EntityId, EntityId)>> for Hierarchy {
+    fn from(links: Vec<(EntityId, EntityId)>) -> Self {
+        let mut hierarchy = Hierarchy::default();
+        for (child, parent) in links {
+            hierarchy.set_parent(child, parent);
+        }
+        hierarchy
+    }
+}
+
+impl From<Hierarchy> for Vec<(EntityId, EntityId)> {
+    fn from(hierarchy: Hierarchy) -> Self {
+        let mut links: Vec<_> = hierarchy.parents.into_iter().collect();
+        links.sort();
+        links
+    }
+}
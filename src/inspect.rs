@@ -0,0 +1,155 @@
+//!
+//! Type-erased component access for tools like debug inspectors
+//!
+
+use std::fmt;
+use serde_json::{self, Value};
+use super::{EntityId, SpawnError};
+use cleanup::Destroyed;
+
+///
+/// Error returned when writing a component from a JSON value fails
+///
+#[derive(Debug)]
+pub enum ValueError {
+    /// No component with that name is registered in the pool
+    UnknownComponent(String),
+    /// The value could not be deserialized into the component type
+    Json(serde_json::Error)
+}
+
+impl fmt::Display for ValueError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValueError::UnknownComponent(ref name) => write!(f, "unknown component type: {}", name),
+            ValueError::Json(ref e) => write!(f, "invalid component value: {}", e)
+        }
+    }
+}
+
+impl ::std::error::Error for ValueError {}
+
+impl From<serde_json::Error> for ValueError {
+    fn from(e: serde_json::Error) -> Self {
+        ValueError::Json(e)
+    }
+}
+
+///
+/// Component access by type name, implemented by every generated pool
+///
+/// Component values are round-tripped through their serde implementations, so this is meant for
+/// debugging tools and editors rather than game logic.
+///
+pub trait Inspect {
+    /// Names of all registered component types, in declaration order
+    fn component_names(&self) -> &'static [&'static str];
+    /// All live entities that have at least one component, sorted by id
+    fn entity_ids(&self) -> Vec<EntityId>;
+    /// Serialized value of the named component, if the entity has it
+    fn component_value(&self, id: EntityId, name: &str) -> Option<Value>;
+    /// Deserializes `value` and attaches it to the entity as the named component
+    fn set_component_value(&mut self, id: EntityId, name: &str, value: Value) -> Result<(), ValueError>;
+    /// Removes the named component from the entity
+    fn remove_component(&mut self, id: EntityId, name: &str) -> Result<(), ValueError>;
+}
+
+///
+/// Object safe view of a whole pool, used where the concrete pool type can't be named, like the
+/// C FFI layer
+///
+pub trait ErasedPool: Inspect {
+    fn spawn_entity(&mut self) -> EntityId;
+    fn try_spawn_entity(&mut self) -> Result<EntityId, SpawnError>;
+    fn is_alive(&self, id: EntityId) -> bool;
+    /// Every alive entity, with or without components, sorted by id
+    fn live_entity_ids(&self) -> Vec<EntityId>;
+    fn remove_entity(&mut self, id: EntityId);
+    fn cleanup_removed(&mut self) -> Vec<Destroyed>;
+    /// Serializes the whole pool as JSON
+    fn to_json_bytes(&self) -> Result<Vec<u8>, serde_json::Error>;
+}
+
+///
+/// An entity matched by a `DynQuery`, with the values of the components the query asked for
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynRow {
+    pub entity: EntityId,
+    pub components: Vec<(&'static str, Value)>
+}
+
+///
+/// Query built at runtime from component names, for consoles and editors
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// # #[macro_use] extern crate spawning_pool;
+/// # fn main() {
+/// # use spawning_pool::EntityId;
+/// # use spawning_pool::storage::{Storage, HashMapStorage};
+/// use spawning_pool::inspect::DynQuery;
+/// # #[derive(Clone, Debug, Serialize, Deserialize)]
+/// # struct Pos(i32, i32);
+/// # #[derive(Clone, Debug, Serialize, Deserialize)]
+/// # struct Dead;
+/// # create_spawning_pool!((Pos, pos, HashMapStorage), (Dead, dead, HashMapStorage));
+/// # let mut pool = SpawningPool::new();
+/// # spawn_with!(pool, Pos(1, 2));
+/// # spawn_with!(pool, Pos(3, 4), Dead);
+/// let rows = DynQuery::new().with("Pos").without("Dead").run(&pool).unwrap();
+/// assert_eq!(rows.len(), 1);
+/// # }
+/// ```
+///
+#[derive(Debug, Clone, Default)]
+pub struct DynQuery {
+    with: Vec<String>,
+    without: Vec<String>
+}
+
+impl DynQuery {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only match entities having the component, its value is included in the results
+    pub fn with(mut self, name: &str) -> Self {
+        self.with.push(name.to_string());
+        self
+    }
+
+    /// Only match entities lacking the component
+    pub fn without(mut self, name: &str) -> Self {
+        self.without.push(name.to_string());
+        self
+    }
+
+    /// Matching entities sorted by id, fails if a name isn't a registered component
+    pub fn run<P: Inspect + ?Sized>(&self, pool: &P) -> Result<Vec<DynRow>, ValueError> {
+        let resolve = |name: &String| {
+            pool.component_names().iter()
+                .find(|n| **n == name.as_str())
+                .cloned()
+                .ok_or_else(|| ValueError::UnknownComponent(name.clone()))
+        };
+        let with = self.with.iter().map(resolve).collect::<Result<Vec<_>, _>>()?;
+        let without = self.without.iter().map(resolve).collect::<Result<Vec<_>, _>>()?;
+
+        let mut rows = vec![];
+        'entities: for id in pool.entity_ids() {
+            if without.iter().any(|name| pool.component_value(id, name).is_some()) {
+                continue;
+            }
+            let mut components = vec![];
+            for name in &with {
+                match pool.component_value(id, name) {
+                    Some(value) => components.push((*name, value)),
+                    None => continue 'entities
+                }
+            }
+            rows.push(DynRow { entity: id, components });
+        }
+        Ok(rows)
+    }
+}
@@ -0,0 +1,127 @@
+//!
+//! egui based entity inspector, enabled with the `inspector` feature
+//!
+
+use egui;
+use serde_json::{self, Value};
+use super::{EntityId};
+use inspect::Inspect;
+
+///
+/// Draws an entity inspector for any generated pool
+///
+/// Every live entity is listed with its components shown as editable JSON. Edits are only
+/// written back to the pool when they parse and deserialize into the component type.
+///
+pub trait InspectorUi {
+    fn ui(&mut self, ui: &mut egui::Ui);
+}
+
+impl<P: Inspect> InspectorUi for P {
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        for id in self.entity_ids() {
+            egui::CollapsingHeader::new(format!("Entity {}", id))
+                .id_salt(("spawning_pool_entity", id))
+                .show(ui, |ui| {
+                    for name in self.component_names() {
+                        if let Some(value) = self.component_value(id, name) {
+                            component_ui(self, ui, id, name, &value);
+                        }
+                    }
+                });
+        }
+    }
+}
+
+/// Edit buffer of one component, kept in egui's temp data between frames
+#[derive(Clone, Default)]
+struct EditState {
+    /// The component's JSON when `text` was last reset to it
+    seeded: String,
+    text: String,
+    error: Option<String>
+}
+
+fn component_ui<P: Inspect>(pool: &mut P, ui: &mut egui::Ui, id: EntityId, name: &str, value: &Value) {
+    let key = egui::Id::new(("spawning_pool_component", id, name));
+    let edit_key = key.with("text");
+    let current = serde_json::to_string_pretty(value).unwrap_or_default();
+    let mut state = ui.data_mut(|d| d.get_temp::<EditState>(key)).unwrap_or_default();
+    // Changes made outside the inspector replace the text, unless it is being edited
+    if state.seeded != current && !ui.memory(|m| m.has_focus(edit_key)) {
+        state = EditState { seeded: current.clone(), text: current.clone(), error: None };
+    }
+
+    ui.label(name);
+    ui.add(egui::TextEdit::multiline(&mut state.text).id(edit_key));
+    let parsed = serde_json::from_str::<Value>(&state.text);
+    ui.horizontal(|ui| {
+        if ui.add_enabled(parsed.is_ok(), egui::Button::new("Apply")).clicked() {
+            if let Ok(new_value) = parsed {
+                state.error = pool.set_component_value(id, name, new_value).err().map(|e| e.to_string());
+            }
+        }
+        if ui.button("Reset").clicked() {
+            state = EditState { seeded: current.clone(), text: current.clone(), error: None };
+        }
+    });
+    if let Some(ref error) = state.error {
+        ui.colored_label(egui::Color32::RED, error.as_str());
+    }
+    ui.data_mut(|d| d.insert_temp(key, state));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{component_ui, EditState, InspectorUi};
+    use inspect::Inspect;
+    use storage::*;
+    use create_spawning_pool;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Position {
+        pub x: i32,
+        pub y: i32
+    }
+
+    #[test]
+    fn test_ui_runs_headless() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        pool.set(id, Position{x: 1, y: 2});
+
+        let ctx = ::egui::Context::default();
+        let _ = ctx.run(Default::default(), |ctx| {
+            ::egui::CentralPanel::default().show(ctx, |ui| pool.ui(ui));
+        });
+        assert_eq!(pool.get::<Position>(id).unwrap().x, 1);
+    }
+
+    #[test]
+    fn test_ui_follows_outside_changes() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        pool.set(id, Position{x: 1, y: 2});
+
+        let ctx = ::egui::Context::default();
+        let key = ::egui::Id::new(("spawning_pool_component", id, "Position"));
+        let frame = |pool: &mut SpawningPool| {
+            let _ = ctx.run(Default::default(), |ctx| {
+                ::egui::CentralPanel::default().show(ctx, |ui| {
+                    let value = pool.component_value(id, "Position").unwrap();
+                    component_ui(pool, ui, id, "Position", &value);
+                });
+            });
+            ctx.data(|d| d.get_temp::<EditState>(key)).unwrap().text
+        };
+        assert!(frame(&mut pool).contains("\"x\": 1"));
+        pool.set(id, Position{x: 5, y: 2});
+        assert!(frame(&mut pool).contains("\"x\": 5"));
+    }
+}
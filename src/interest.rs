@@ -0,0 +1,185 @@
+//!
+//! Interest management, the entities each replication peer should hear about
+//!
+//! A peer given an `InterestFilter` with `set_interest` only receives updates of the entities
+//! matching it. The pool keeps every peer's relevant set up to date as components are set and
+//! removed: entities entering it are sent in full, entities leaving it are reported to the peer as
+//! despawned. Changes made through `get_mut` aren't seen until `refresh_interests` is called.
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::interest::InterestFilter;
+//! use spawning_pool::storage::{HashMapStorage, HasPosition, Storage};
+//!
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Pos(f32, f32);
+//!
+//! impl HasPosition for Pos {
+//!     fn position(&self) -> (f32, f32) {
+//!         (self.0, self.1)
+//!     }
+//! }
+//!
+//! create_spawning_pool!((Pos, pos, HashMapStorage, replicated));
+//! let mut server = SpawningPool::new();
+//! let near = spawn_with!(server, Pos(1.0, 1.0));
+//! spawn_with!(server, Pos(500.0, 0.0));
+//!
+//! server.add_peer(1);
+//! server.set_interest(1, InterestFilter::region::<Pos>((0.0, 0.0), (100.0, 100.0)));
+//! let packet = server.collect_updates(1);
+//! assert_eq!(packet.updates.len(), 1);
+//! assert_eq!(packet.updates[0].entity, near);
+//!
+//! server.set(near, Pos(200.0, 0.0));
+//! assert_eq!(server.collect_updates(1).despawned, vec![near]);
+//! # }
+//! ```
+//!
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use map::{EntityMap, MapEntities};
+use pool::HasComponent;
+use replication::PeerId;
+use storage::HasPosition;
+use super::{EntityId};
+
+type Matcher<P> = Box<dyn Fn(&P, EntityId) -> bool + Send + Sync>;
+
+///
+/// Which entities a peer is interested in
+///
+pub struct InterestFilter<P> {
+    matches: Matcher<P>
+}
+
+impl<P> InterestFilter<P> {
+    pub fn predicate<F>(matches: F) -> Self where F: Fn(&P, EntityId) -> bool + Send + Sync + 'static {
+        InterestFilter { matches: Box::new(matches) }
+    }
+
+    /// Entities whose component `T` is inside the rectangle, bounds included
+    pub fn region<T>(min: (f32, f32), max: (f32, f32)) -> Self where P: HasComponent<T>, T: HasPosition {
+        InterestFilter::predicate(move |pool: &P, id| match pool.component(id) {
+            Some(component) => {
+                let (x, y) = component.position();
+                x >= min.0 && x <= max.0 && y >= min.1 && y <= max.1
+            },
+            None => false
+        })
+    }
+
+    pub fn matches(&self, pool: &P, id: EntityId) -> bool {
+        (self.matches)(pool, id)
+    }
+}
+
+struct Interest<P> {
+    filter: InterestFilter<P>,
+    relevant: BTreeSet<EntityId>,
+    /// Entities that stopped being relevant since the peer's last update
+    left: BTreeSet<EntityId>
+}
+
+///
+/// Interest of every peer with a filter, kept by the pool and not serialized
+///
+pub struct Interests<P> {
+    peers: BTreeMap<PeerId, Interest<P>>
+}
+
+impl<P> Default for Interests<P> {
+    fn default() -> Self {
+        Interests { peers: BTreeMap::new() }
+    }
+}
+
+impl<P> fmt::Debug for Interests<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.peers.iter().map(|(peer, interest)| (peer, interest.relevant.len())))
+            .finish()
+    }
+}
+
+impl<P> Interests<P> {
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    pub fn has(&self, peer: PeerId) -> bool {
+        self.peers.contains_key(&peer)
+    }
+
+    /// Sets the peer's filter along with the entities matching it, returns the entities that
+    /// became relevant
+    pub fn set(&mut self, peer: PeerId, filter: InterestFilter<P>, relevant: BTreeSet<EntityId>) -> Vec<EntityId> {
+        let (previous, mut left) = match self.peers.remove(&peer) {
+            Some(interest) => (interest.relevant, interest.left),
+            None => (BTreeSet::new(), BTreeSet::new())
+        };
+        let entered = relevant.difference(&previous).cloned().collect();
+        left.extend(previous.difference(&relevant));
+        for id in &relevant {
+            left.remove(id);
+        }
+        self.peers.insert(peer, Interest { filter, relevant, left });
+        entered
+    }
+
+    /// Drops the peer's filter, returns the entities that were relevant to it
+    pub fn remove(&mut self, peer: PeerId) -> Option<BTreeSet<EntityId>> {
+        self.peers.remove(&peer).map(|interest| interest.relevant)
+    }
+
+    /// Entities relevant to the peer, in ascending order
+    pub fn relevant(&self, peer: PeerId) -> Option<&BTreeSet<EntityId>> {
+        self.peers.get(&peer).map(|interest| &interest.relevant)
+    }
+
+    /// Checks the entity again against every filter, returns the peers it became relevant to
+    pub fn refresh(&mut self, pool: &P, id: EntityId) -> Vec<PeerId> {
+        let mut entered = vec![];
+        for (peer, interest) in self.peers.iter_mut() {
+            if interest.filter.matches(pool, id) {
+                if interest.relevant.insert(id) {
+                    interest.left.remove(&id);
+                    entered.push(*peer);
+                }
+            } else if interest.relevant.remove(&id) {
+                interest.left.insert(id);
+            }
+        }
+        entered
+    }
+
+    /// Drops the entity from every relevant set, peers that had it are told it left
+    pub fn remove_entity(&mut self, id: EntityId) {
+        for interest in self.peers.values_mut() {
+            if interest.relevant.remove(&id) {
+                interest.left.insert(id);
+            }
+        }
+    }
+
+    /// Takes the entities that left the peer's relevant set, in ascending order
+    pub fn take_left(&mut self, peer: PeerId) -> Vec<EntityId> {
+        match self.peers.get_mut(&peer) {
+            Some(interest) => ::std::mem::take(&mut interest.left).into_iter().collect(),
+            None => vec![]
+        }
+    }
+}
+
+impl<P> MapEntities for Interests<P> {
+    /// Drops the entities that aren't in the map
+    fn map_entities(&mut self, map: &EntityMap) {
+        for interest in self.peers.values_mut() {
+            interest.relevant = interest.relevant.iter().filter_map(|id| map.get(*id)).collect();
+            interest.left = interest.left.iter().filter_map(|id| map.get(*id)).collect();
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//!
+//! Per-entity layer bitmasks kept by the pool, for render/physics/AI style filtering without a
+//! user component
+//!
+
+use std::ops::{BitAnd, BitOr, Not};
+use map::{EntityMap, MapEntities};
+use super::{EntityId, RawEntityId};
+
+///
+/// Set of up to 32 layers, combined with `|`
+///
+/// ```
+/// use spawning_pool::layers::Layers;
+///
+/// const RENDER: Layers = Layers(1 << 0);
+/// const PHYSICS: Layers = Layers(1 << 1);
+///
+/// let both = RENDER | PHYSICS;
+/// assert!(both.contains(RENDER));
+/// assert!(!RENDER.intersects(PHYSICS));
+/// ```
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Layers(pub u32);
+
+impl Layers {
+    pub const NONE: Layers = Layers(0);
+    pub const ALL: Layers = Layers(!0);
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every layer of `other` is set
+    pub fn contains(self, other: Layers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether any layer of `other` is set
+    pub fn intersects(self, other: Layers) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for Layers {
+    type Output = Layers;
+
+    fn bitor(self, other: Layers) -> Layers {
+        Layers(self.0 | other.0)
+    }
+}
+
+impl BitAnd for Layers {
+    type Output = Layers;
+
+    fn bitand(self, other: Layers) -> Layers {
+        Layers(self.0 & other.0)
+    }
+}
+
+impl Not for Layers {
+    type Output = Layers;
+
+    fn not(self) -> Layers {
+        Layers(!self.0)
+    }
+}
+
+///
+/// Dense map from entity ID to its layers, one `u32` per ID
+///
+/// Serializes as a list of `(id, layers)` pairs for entities with any layer set.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<(EntityId, Layers)>", into = "Vec<(EntityId, Layers)>")]
+pub struct LayerMap {
+    masks: Vec<u32>
+}
+
+impl LayerMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn set(&mut self, id: EntityId, layers: Layers) {
+        let index = id.to_raw() as usize;
+        if index >= self.masks.len() {
+            if layers.is_empty() {
+                return;
+            }
+            self.masks.resize(index + 1, 0);
+        }
+        self.masks[index] = layers.0;
+    }
+
+    #[inline]
+    pub fn get(&self, id: EntityId) -> Layers {
+        Layers(self.masks.get(id.to_raw() as usize).cloned().unwrap_or(0))
+    }
+
+    pub fn remove(&mut self, id: EntityId) {
+        self.set(id, Layers::NONE);
+    }
+
+    /// IDs with any layer of `mask` set, in ascending order
+    pub fn iter_matching(&self, mask: Layers) -> impl Iterator<Item = EntityId> + '_ {
+        self.masks.iter().enumerate()
+            .filter(move |(_, bits)| *bits & mask.0 != 0)
+            .filter_map(|(i, _)| EntityId::from_raw(i as RawEntityId))
+    }
+}
+
+impl MapEntities for LayerMap {
+    /// Drops the layers of entities that aren't in the map
+    fn map_entities(&mut self, map: &EntityMap) {
+        let mut mapped = LayerMap::new();
+        for id in self.iter_matching(Layers::ALL) {
+            if let Some(new) = map.get(id) {
+                mapped.set(new, self.get(id));
+            }
+        }
+        *self = mapped;
+    }
+}
+
+impl From<Vec<(EntityId, Layers)>> for LayerMap {
+    fn from(entries: Vec<(EntityId, Layers)>) -> Self {
+        let mut map = LayerMap::new();
+        for (id, layers) in entries {
+            map.set(id, layers);
+        }
+        map
+    }
+}
+
+impl From<LayerMap> for Vec<(EntityId, Layers)> {
+    fn from(map: LayerMap) -> Self {
+        map.iter_matching(Layers::ALL).map(|id| (id, map.get(id))).collect()
+    }
+}
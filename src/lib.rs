@@ -5,7 +5,33 @@
 //!
 //! Kind of like an Entity Component System, but without the system part.
 //!
-//! Components needs to implement `Clone`, `Debug`, `Serialize` and `Deserialize`
+//! Components needs to implement `Clone`, `Debug`, `Serialize` and `Deserialize`. Attributes
+//! placed before the component list are forwarded to the generated struct, which allows custom
+//! `#[serde(bound = "...")]` or `#[serde(rename = "...")]` attributes.
+//!
+//! The pool struct is `pub struct SpawningPool` unless a visibility and name are given before
+//! the component list, `create_spawning_pool!(pub(crate) WorldPool; (Pos, pos, VectorStorage))`,
+//! which allows several pools in one module. The expansion declares nothing but the pool struct,
+//! refers to this crate by full paths and keeps its helpers in a private scope, so the invoking
+//! module only needs the storage types and serde's derive macros in scope.
+//! Storages can be named by path, `storages::QuadTreeStorage`, and given with all their generic
+//! parameters, `CellStorage<Pos, VectorStorage<Pos>>`, otherwise the component is their only one.
+//!
+//! Entity IDs are `u64` by default, enable the `u32-ids` feature to use `u32` instead. Note that
+//! this applies to every pool in the dependency graph.
+//!
+//! Components flagged `accessors` also get methods named after their storage field, so a
+//! component stored in `pos` can be used with `pool.pos(id)`, `pool.pos_mut(id)` and
+//! `pool.set_pos(id, pos)`. The names must not clash with the pool's other methods.
+//!
+//! `set` and `get_mut` do nothing for entities that aren't alive. The `strict` feature makes them
+//! panic instead, naming the entity, the component and whether the entity was never spawned, is
+//! pending removal or was destroyed. Meant for debug builds.
+//!
+//! Lockstep games can enable the `deterministic` feature. Hash based storages then keep their
+//! components ordered by entity id and hash their indices with `checksum::Fnv64`, so `get_all`,
+//! joins, `checksum::state_hash` and saves come out the same on every peer and platform, given
+//! the same calls in the same order.
 //! 
 //! # Examples
 //! ```
@@ -32,343 +58,5715 @@
 //! ```
 //!
 
+extern crate serde;
 #[macro_use] extern crate serde_derive;
+pub extern crate serde_json;
+pub extern crate uuid;
+#[doc(hidden)]
+pub extern crate paste;
+#[cfg(feature = "inspector")]
+pub extern crate egui;
+#[cfg(feature = "lua")]
+pub extern crate mlua;
+#[cfg(feature = "tracing")]
+pub extern crate tracing;
+#[cfg(feature = "indexmap")]
+extern crate indexmap;
+#[cfg(feature = "proptest")]
+pub extern crate proptest;
+#[cfg(feature = "async")]
+pub extern crate futures_channel;
+#[cfg(feature = "rayon")]
+extern crate rayon;
 
 pub mod storage;
+pub mod inspect;
+pub mod uuids;
+pub mod replication;
+pub mod diff;
+pub mod checksum;
+pub mod metrics;
+pub mod lifecycle;
+#[macro_use]
+pub mod bundle;
+pub mod fetch;
+pub mod ticks;
+pub mod cleanup;
+pub mod bitset;
+pub mod query;
+pub mod console;
+pub mod testing;
+pub mod save;
+pub mod layers;
+pub mod mask;
+pub mod export;
+pub mod summary;
+pub mod weak;
+pub mod map;
+pub mod hierarchy;
+#[macro_use]
+pub mod relations;
+pub mod events;
+pub mod live;
+pub mod changes;
+pub mod pool;
+pub mod dynamic;
+pub mod buffered;
+pub mod interest;
+pub mod cache;
+#[macro_use]
+pub mod soa;
+#[cfg(feature = "inspector")]
+pub mod inspector;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "lua")]
+pub mod lua;
+#[cfg(feature = "schedule")]
+pub mod schedule;
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+
+/// Raw integer backing an entity ID
+#[cfg(not(feature = "u32-ids"))]
+pub type RawEntityId = u64;
+#[cfg(not(feature = "u32-ids"))]
+type NonZeroRawEntityId = std::num::NonZeroU64;
+#[doc(hidden)]
+#[cfg(not(feature = "u32-ids"))]
+pub type AtomicRawEntityId = std::sync::atomic::AtomicU64;
 
+/// Raw integer backing an entity ID, 32 bits wide since the `u32-ids` feature is enabled
+#[cfg(feature = "u32-ids")]
+pub type RawEntityId = u32;
+#[cfg(feature = "u32-ids")]
+type NonZeroRawEntityId = std::num::NonZeroU32;
+#[doc(hidden)]
+#[cfg(feature = "u32-ids")]
+pub type AtomicRawEntityId = std::sync::atomic::AtomicU32;
+
+///
 /// Entity ID
-pub type EntityId = u64;
+///
+/// IDs are never zero, which lets `Option<EntityId>` be the same size as `EntityId`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EntityId(NonZeroRawEntityId);
+
+impl EntityId {
+    /// Creates an ID from its raw value, returns `None` for zero
+    pub fn from_raw(raw: RawEntityId) -> Option<EntityId> {
+        NonZeroRawEntityId::new(raw).map(EntityId)
+    }
+
+    pub fn to_raw(self) -> RawEntityId {
+        self.0.get()
+    }
+}
+
+impl std::fmt::Display for EntityId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+///
+/// Error from spawning an entity
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// Every entity ID is taken and no destroyed entity's ID can be reused
+    Exhausted
+}
+
+impl std::fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            SpawnError::Exhausted => write!(f, "all {} entity ids are in use", RawEntityId::MAX - 1)
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
 
 #[macro_export]
 macro_rules! create_spawning_pool {
-    ($((
-        // component type
-        $component:ty,
-        // internal storage container name
-        $store_name: ident,
-        // storage type, implements storage::Storage trait
-        $storage: ident
-        )), +)
-        => (
-            use std::collections::HashSet;
+    (
+        @pool [$(#[$pool_meta:meta])*] $vis:vis $name:ident;
+        $(($component:ty, $store_name:ident, [$storage:ty], $storage_name:expr $(, $flag:ident)*)),+
+    ) => (
+            $($(
+                $crate::__spawning_pool_check_flag!($flag);
+            )*)+
+
             #[derive(Debug, Serialize, Deserialize)]
-            pub struct SpawningPool {
-                next_id: u64,
-                removed: HashSet<EntityId>,
+            $(#[$pool_meta])*
+            $vis struct $name {
+                next_id: $crate::AtomicRawEntityId,
+                removed: $crate::bitset::EntitySet,
+                #[serde(default)]
+                destroyed: $crate::bitset::EntityRanges,
+                #[serde(default)]
+                uuids: $crate::uuids::UuidMap,
+                #[serde(default)]
+                layers: $crate::layers::LayerMap,
+                #[serde(default)]
+                despawn_timers: $crate::cleanup::DespawnTimers,
+                #[serde(default)]
+                generations: $crate::weak::Generations,
+                #[serde(default)]
+                hierarchy: $crate::hierarchy::Hierarchy,
+                #[serde(default)]
+                relations: $crate::relations::Relations,
+                #[serde(skip)]
+                replication: $crate::replication::ReplicationState,
+                #[serde(skip)]
+                metrics: $crate::metrics::MetricsRecorder,
+                #[serde(skip)]
+                ticks: $crate::ticks::ChangeTicks,
+                #[serde(skip)]
+                spawn_ticks: $crate::ticks::SpawnTicks,
+                #[serde(skip)]
+                events: $crate::events::EventRegistry,
+                #[serde(skip)]
+                live: $crate::live::LiveQueries<$name>,
+                #[serde(skip)]
+                interests: $crate::interest::Interests<$name>,
+                #[serde(skip)]
+                removal_tracker: $crate::ticks::RemovalTracker,
+                #[serde(skip)]
+                observers: $crate::changes::ChangeObservers,
+                #[serde(skip)]
+                auto_cleanup: $crate::cleanup::AutoCleanup,
+                #[serde(skip)]
+                preserved: $crate::save::PreservedData,
+                #[serde(skip)]
+                dynamic: $crate::dynamic::DynamicComponents,
+                #[serde(skip)]
+                query_cache: $crate::cache::QueryCache,
             $(
-                $store_name: $storage<$component>,
+                $store_name: ::std::sync::Arc<$storage>,
             )+
             }
 
-            impl SpawningPool {
-                #[allow(dead_code)]
-                pub fn new() -> Self {
-                    SpawningPool{
-                        next_id: 1,
-                        removed: Default::default(),
+            // Helpers are scoped to this block, so several pools can live in one module
+            const _: () = {
+                use $crate::storage::Storage as _;
+                use ::std::any::Any as __SpawningPoolAny;
+
+                #[doc(hidden)]
+                #[allow(non_camel_case_types, dead_code)]
+                enum __SpawningPoolComponent {
+                    $($store_name,)+
+                }
+
+                impl $name {
+                    #[allow(dead_code)]
+                    pub fn new() -> Self {
+                        $name{
+                            next_id: $crate::AtomicRawEntityId::new(1),
+                            removed: Default::default(),
+                            destroyed: Default::default(),
+                            uuids: Default::default(),
+                            layers: Default::default(),
+                            despawn_timers: Default::default(),
+                            generations: Default::default(),
+                            hierarchy: Default::default(),
+                            relations: Default::default(),
+                            replication: Default::default(),
+                            metrics: Default::default(),
+                            ticks: Default::default(),
+                            spawn_ticks: Default::default(),
+                            events: Default::default(),
+                            live: Default::default(),
+                            interests: Default::default(),
+                            removal_tracker: Default::default(),
+                            observers: Default::default(),
+                            auto_cleanup: Default::default(),
+                            preserved: Default::default(),
+                            dynamic: Default::default(),
+                            query_cache: Default::default(),
+                            $(
+                                $store_name: ::std::sync::Arc::new(<$storage as $crate::storage::Storage<$component>>::new()),
+                            )+
+                        }
+                    }
+
+                    /// Logically independent copy of the pool. Storages are shared until either pool
+                    /// modifies them, so forking is cheap regardless of pool size.
+                    #[allow(dead_code)]
+                    pub fn fork(&self) -> Self {
+                        $name{
+                            next_id: $crate::AtomicRawEntityId::new(self.next_id.load(::std::sync::atomic::Ordering::Relaxed)),
+                            removed: self.removed.clone(),
+                            destroyed: self.destroyed.clone(),
+                            uuids: self.uuids.clone(),
+                            layers: self.layers.clone(),
+                            despawn_timers: self.despawn_timers.clone(),
+                            generations: self.generations.clone(),
+                            hierarchy: self.hierarchy.clone(),
+                            relations: self.relations.clone(),
+                            replication: Default::default(),
+                            metrics: Default::default(),
+                            ticks: self.ticks.clone(),
+                            spawn_ticks: self.spawn_ticks.clone(),
+                            events: Default::default(),
+                            live: self.live.clone(),
+                            interests: Default::default(),
+                            removal_tracker: Default::default(),
+                            observers: Default::default(),
+                            auto_cleanup: self.auto_cleanup,
+                            preserved: self.preserved.clone(),
+                            dynamic: self.dynamic.clone(),
+                            query_cache: self.query_cache.clone(),
+                            $(
+                                $store_name: self.$store_name.clone(),
+                            )+
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn set_auto_cleanup(&mut self, policy: $crate::cleanup::AutoCleanup) {
+                        self.auto_cleanup = policy;
+                    }
+
+                    /// Destroys the components of all entities pending removal and reports them, sorted
+                    /// by entity id
+                    #[allow(dead_code)]
+                    pub fn cleanup_removed(&mut self) -> Vec<$crate::cleanup::Destroyed> {
+                        let _span = $crate::__spawning_pool_span!("cleanup_removed", removed = self.removed.len());
+                        let start = ::std::time::Instant::now();
+                        let ids = self.removed.drain();
+                        let destroyed = self.destroy_entities(ids);
+                        self.metrics.record_cleanup(start.elapsed());
+                        destroyed
+                    }
+
+                    /// Like `cleanup_removed`, but destroys at most `max_entities` entities and leaves
+                    /// the rest queued for later calls
+                    #[allow(dead_code)]
+                    pub fn cleanup_removed_budgeted(&mut self, max_entities: usize) -> Vec<$crate::cleanup::Destroyed> {
+                        let _span = $crate::__spawning_pool_span!("cleanup_removed_budgeted", removed = self.removed.len(), budget = max_entities);
+                        let start = ::std::time::Instant::now();
+                        let ids: Vec<$crate::EntityId> = self.removed.iter().take(max_entities).collect();
+                        for id in &ids {
+                            self.removed.remove(id);
+                        }
+                        let destroyed = self.destroy_entities(ids);
+                        self.metrics.record_cleanup(start.elapsed());
+                        destroyed
+                    }
+
+                    /// Destroys the components of sorted `ids`, going through the ids once per storage
+                    #[allow(dead_code)]
+                    fn destroy_entities(&mut self, ids: Vec<$crate::EntityId>) -> Vec<$crate::cleanup::Destroyed> {
+                        let mut destroyed: Vec<_> = ids.into_iter()
+                            .map(|id| $crate::cleanup::Destroyed { id, components: vec![] })
+                            .collect();
+                        for entry in &destroyed {
+                            self.destroyed.insert(entry.id);
+                            self.uuids.remove(entry.id);
+                            self.layers.remove(entry.id);
+                            self.ticks.remove_entity(entry.id);
+                            self.spawn_ticks.remove(entry.id);
+                            self.despawn_timers.remove(entry.id);
+                            self.generations.bump(entry.id);
+                            self.hierarchy.remove(entry.id);
+                            self.relations.remove_entity(entry.id);
+                        }
                         $(
-                            $store_name: $storage::new(),
+                            for entry in destroyed.iter_mut() {
+                                let id = entry.id;
+                                if self.$store_name.get(id).is_none() {
+                                    continue;
+                                }
+                                entry.components.push(stringify!($component));
+                                self.observers.notify(<Self as ComponentLoader<$component>>::INDEX, $crate::changes::Change::Removed(id));
+                                let storage = ::std::sync::Arc::make_mut(&mut self.$store_name);
+                                $crate::__spawning_pool_if_lifecycle!([$($flag),*] {
+                                    if let Some(old) = storage.take(id) {
+                                        $crate::lifecycle::ComponentLifecycle::on_removed(old, id);
+                                    }
+                                } else {
+                                    storage.remove(id);
+                                });
+                            }
                         )+
+                        for entry in destroyed.iter_mut() {
+                            entry.components.extend(self.dynamic.remove_entity(entry.id));
+                        }
+                        destroyed
+                    }
+
+                    /// Panics when the pool runs out of entity IDs, see `try_spawn_entity`
+                    #[allow(dead_code)]
+                    pub fn spawn_entity(&mut self) -> $crate::EntityId {
+                        match self.try_spawn_entity() {
+                            Ok(id) => id,
+                            Err(e) => panic!("{}", e)
+                        }
                     }
-                }
 
-                #[allow(dead_code)]
-                pub fn cleanup_removed(&mut self) {
-                    for id in &self.removed {
+                    /// Spawns an entity with the `Default` value of every component flagged `default`
+                    #[allow(dead_code)]
+                    pub fn spawn_default(&mut self) -> $crate::EntityId {
+                        let id = self.spawn_entity();
                         $(
-                            self.$store_name.remove(*id);
+                            $crate::__spawning_pool_if_default!([$($flag),*] {
+                                self.set::<$component>(id, ::std::default::Default::default());
+                            } else {});
                         )+
+                        id
                     }
-                    self.removed.clear();
-                }
 
-                #[allow(dead_code)]
-                pub fn spawn_entity(&mut self) -> EntityId {
-                    let id = self.next_id;
-                    self.next_id += 1;
-                    id
-                }
+                    $(
+                        $crate::__spawning_pool_accessors!([$($flag),*] $store_name, $component);
+                    )+
 
-                #[allow(dead_code)]
-                pub fn remove_entity(&mut self, id: EntityId) {
-                    self.removed.insert(id);
-                }
+                    /// Like `spawn_entity`, returning an error when the pool runs out of IDs. Once
+                    /// every ID has been handed out, the IDs of destroyed entities are reused, lowest
+                    /// first, and weak references to them stay dead as their generation was bumped.
+                    #[allow(dead_code)]
+                    pub fn try_spawn_entity(&mut self) -> Result<$crate::EntityId, $crate::SpawnError> {
+                        if self.auto_cleanup == $crate::cleanup::AutoCleanup::OnSpawn && !self.removed.is_empty() {
+                            self.cleanup_removed();
+                        }
+                        let next_id = self.next_id.get_mut();
+                        let id = if *next_id < $crate::RawEntityId::MAX {
+                            let id = $crate::EntityId::from_raw(*next_id).expect("entity ids start at 1");
+                            *next_id += 1;
+                            id
+                        } else {
+                            let id = self.destroyed.first().ok_or($crate::SpawnError::Exhausted)?;
+                            self.destroyed.remove(&id);
+                            id
+                        };
+                        self.spawn_ticks.record(id, self.ticks.current());
+                        $crate::__spawning_pool_event!(entity = id.to_raw(), "spawn_entity");
+                        Ok(id)
+                    }
 
-                #[allow(dead_code)]
-                pub fn set<T>(&mut self, id: EntityId, component: T) where Self: ComponentLoader<T> {
-                    if self.removed.get(&id).is_none() {
-                        self.set_overloaded(id, component);
+                    /// Whether the entity was spawned or reserved and hasn't been removed since
+                    #[allow(dead_code)]
+                    pub fn is_alive(&self, id: $crate::EntityId) -> bool {
+                        id.to_raw() < self.next_id.load(::std::sync::atomic::Ordering::Relaxed)
+                            && !self.removed.contains(&id)
+                            && !self.destroyed.contains(&id)
                     }
-                }
 
-                #[allow(dead_code)]
-                pub fn get<T>(&self, id: EntityId) -> Option<&T> where Self: ComponentLoader<T> {
-                    if self.removed.get(&id).is_none() {
-                        self.get_overloaded(id)
-                    } else {
-                        None
+                    /// Panics unless the entity is alive, used by `set` and `get_mut` with the `strict`
+                    /// feature
+                    #[allow(dead_code)]
+                    fn assert_alive(&self, id: $crate::EntityId, method: &str, component: &str) {
+                        let problem = if id.to_raw() >= self.next_id.load(::std::sync::atomic::Ordering::Relaxed) {
+                            "was never spawned"
+                        } else if self.removed.contains(&id) {
+                            "is pending removal"
+                        } else if self.destroyed.contains(&id) {
+                            "was destroyed"
+                        } else {
+                            return;
+                        };
+                        panic!("{}::<{}> on entity {}, which {}", method, component, id, problem);
                     }
-                }
 
-                #[allow(dead_code)]
-                pub fn force_get<T>(&self, id: EntityId) -> Option<&T> where Self: ComponentLoader<T> {
-                    self.get_overloaded(id)
-                }
+                    /// Every alive entity, in ascending order. Runs of destroyed IDs are skipped
+                    /// without visiting them.
+                    #[allow(dead_code)]
+                    pub fn entities(&self) -> impl Iterator<Item = $crate::EntityId> + '_ {
+                        let end = self.next_id.load(::std::sync::atomic::Ordering::Relaxed);
+                        self.destroyed.gaps(end)
+                            .flatten()
+                            .filter_map(|raw| $crate::EntityId::from_raw(raw))
+                            .filter(move |id| !self.removed.contains(id))
+                    }
 
-                #[allow(dead_code)]
-                pub fn get_mut<T>(&mut self, id: EntityId) -> Option<&mut T> where Self: ComponentLoader<T> {
-                    if self.removed.get(&id).is_none() {
-                        self.get_mut_overloaded(id)
-                    } else {
-                        None
+                    /// Number of alive entities, with or without components
+                    #[allow(dead_code)]
+                    pub fn entity_count(&self) -> usize {
+                        let spawned = self.next_id.load(::std::sync::atomic::Ordering::Relaxed) as usize - 1;
+                        spawned - self.removed.len() - self.destroyed.len()
                     }
-                }
 
-                #[allow(dead_code)]
-                pub fn remove<T>(&mut self, id: EntityId) where Self: ComponentLoader<T> {
-                    if self.removed.get(&id).is_none() {
-                        self.remove_overloaded(id);
+                    /// Takes the next entity ID through a shared reference, panics when the IDs run out
+                    /// since reserving can't reuse the IDs of destroyed entities
+                    #[allow(dead_code)]
+                    pub fn reserve_entity(&self) -> $crate::EntityId {
+                        let raw = self.next_id.fetch_update(
+                            ::std::sync::atomic::Ordering::Relaxed,
+                            ::std::sync::atomic::Ordering::Relaxed,
+                            |raw| if raw < $crate::RawEntityId::MAX { Some(raw + 1) } else { None }
+                        );
+                        match raw {
+                            Ok(raw) => $crate::EntityId::from_raw(raw).expect("entity ids start at 1"),
+                            Err(_) => panic!("{}", $crate::SpawnError::Exhausted)
+                        }
                     }
-                }
 
-                #[allow(dead_code)]
-                pub fn get_all<T>(&self) -> Vec<(EntityId, &T)> where Self: ComponentLoader<T> {
-                    let ids = self.get_all_overloaded();
-                    ids.iter()
-                        .filter(|(id, _)| self.removed.get(id).is_none())
-                        .map(|i| *i)
-                        .collect()
-                }
-            }
+                    #[allow(dead_code)]
+                    pub fn remove_entity(&mut self, id: $crate::EntityId) {
+                        $crate::__spawning_pool_event!(entity = id.to_raw(), "remove_entity");
+                        self.queue_removal(id);
+                        self.auto_cleanup_removed();
+                    }
 
-            pub trait ComponentLoader<T> {
-                fn get_overloaded(&self, id: EntityId) -> Option<&T>;
-                fn get_all_overloaded(&self) -> Vec<(EntityId, &T)>;
-                fn get_mut_overloaded(&mut self, id: EntityId) -> Option<&mut T>;
-                fn set_overloaded(&mut self, id: EntityId, component: T);
-                fn remove_overloaded(&mut self, id: EntityId);
-            }
+                    /// Queues every entity for removal, like calling `remove_entity` for each but
+                    /// checking the auto cleanup policy once at the end
+                    #[allow(dead_code)]
+                    pub fn remove_entities<I: IntoIterator<Item = $crate::EntityId>>(&mut self, ids: I) {
+                        for id in ids {
+                            $crate::__spawning_pool_event!(entity = id.to_raw(), "remove_entity");
+                            self.queue_removal(id);
+                        }
+                        self.auto_cleanup_removed();
+                    }
 
-            $(
-            impl ComponentLoader<$component> for SpawningPool {
-                fn get_overloaded(&self, id: EntityId) -> Option<&$component> {
-                    self.$store_name.get(id)
-                }
-                fn get_all_overloaded(&self) -> Vec<(EntityId, &$component)> {
-                    self.$store_name.get_all()
-                }
-                fn get_mut_overloaded(&mut self, id: EntityId) -> Option<&mut $component> {
-                    self.$store_name.get_mut(id)
-                }
-                fn set_overloaded(&mut self, id: EntityId, component: $component) {
-                    self.$store_name.set(id, component);
-                }
-                fn remove_overloaded(&mut self, id: EntityId) {
-                    self.$store_name.remove(id);
-                }
-            }
-            )+
-    )
-}
+                    fn queue_removal(&mut self, id: $crate::EntityId) {
+                        if !self.is_alive(id) {
+                            return;
+                        }
+                        $(
+                            if self.$store_name.get(id).is_some() {
+                                self.removal_tracker.record(<Self as ComponentLoader<$component>>::INDEX, id);
+                            }
+                        )+
+                        self.removed.insert(id);
+                        self.ticks.touch_removals();
+                        self.replication.mark_despawned(id);
+                        self.live.remove_entity(id);
+                        self.interests.remove_entity(id);
+                    }
 
-#[cfg(test)]
-mod tests {
-    use super::{EntityId};
-    use storage::*;
+                    fn auto_cleanup_removed(&mut self) {
+                        if let $crate::cleanup::AutoCleanup::AfterNRemoved(n) = self.auto_cleanup {
+                            if self.removed.len() >= n {
+                                self.cleanup_removed();
+                            }
+                        }
+                    }
 
-    #[derive(Clone, Debug, Serialize, Deserialize)]
-    struct Position {
-        pub x: i32,
-        pub y: i32
-    }
+                    /// Mask of component `T`, usable in constants:
+                    /// `const POS: ComponentMask = SpawningPool::mask::<Position>();`
+                    #[allow(dead_code)]
+                    pub const fn mask<T>() -> $crate::mask::ComponentMask where Self: ComponentLoader<T> {
+                        $crate::mask::ComponentMask::bit(<Self as ComponentLoader<T>>::INDEX)
+                    }
 
-    #[derive(Clone, Debug, Serialize, Deserialize)]
-    struct Velocity {
-        pub x: i32,
-        pub y: i32
-    }
+                    /// Which components the entity has, empty for entities pending removal
+                    #[allow(dead_code)]
+                    pub fn component_mask(&self, id: $crate::EntityId) -> $crate::mask::ComponentMask {
+                        let mut mask = $crate::mask::ComponentMask::NONE;
+                        if !self.is_alive(id) {
+                            return mask;
+                        }
+                        $(
+                            if self.$store_name.get(id).is_some() {
+                                mask = mask | Self::mask::<$component>();
+                            }
+                        )+
+                        mask
+                    }
 
+                    /// Weak reference to the entity, for storing in other entities' components
+                    #[allow(dead_code)]
+                    pub fn downgrade(&self, id: $crate::EntityId) -> $crate::weak::EntityRefWeak {
+                        $crate::weak::EntityRefWeak::new(id, self.generations.get(id))
+                    }
 
-    #[test]
-    fn create_entity() {
-        create_spawning_pool!(
-            (Position, pos, HashMapStorage)
-        );
-        let mut pool = SpawningPool::new();
-        assert_eq!(pool.spawn_entity(), 1u64);
-        assert_eq!(pool.spawn_entity(), 2u64);
-    }
+                    /// The referenced entity's ID, or `None` once it has been removed
+                    #[allow(dead_code)]
+                    pub fn upgrade(&self, weak: $crate::weak::EntityRefWeak) -> Option<$crate::EntityId> {
+                        let id = weak.id();
+                        if self.is_alive(id) && self.generations.get(id) == weak.generation() {
+                            Some(id)
+                        } else {
+                            None
+                        }
+                    }
 
-    #[test]
-    fn test_set() {
-        create_spawning_pool!(
-            (Position, pos, HashMapStorage),
-            (Velocity, vel, HashMapStorage)
-        );
-        let mut pool = SpawningPool::new();
-        let id = pool.spawn_entity();
-        assert!(pool.get::<Position>(id).is_none());
+                    /// Makes `child` a child of `parent`, returns `false` if that would create a cycle
+                    #[allow(dead_code)]
+                    pub fn set_parent(&mut self, child: $crate::EntityId, parent: $crate::EntityId) -> bool {
+                        self.hierarchy.set_parent(child, parent)
+                    }
 
-        pool.set(id, Velocity{x: 1, y: 2});
+                    #[allow(dead_code)]
+                    pub fn remove_parent(&mut self, child: $crate::EntityId) -> Option<$crate::EntityId> {
+                        self.hierarchy.remove_parent(child)
+                    }
 
-        match pool.get::<Velocity>(id) {
-            Some(vel) => {
-                assert_eq!(vel.x, 1);
-                assert_eq!(vel.y, 2);
-            }
-            None => assert!(false)
-        }
+                    #[allow(dead_code)]
+                    pub fn parent_of(&self, id: $crate::EntityId) -> Option<$crate::EntityId> {
+                        self.hierarchy.parent(id)
+                    }
 
-        assert_eq!(pool.get_all::<Velocity>().len(), 1);
-    }
+                    #[allow(dead_code)]
+                    pub fn children_of(&self, id: $crate::EntityId) -> &[$crate::EntityId] {
+                        self.hierarchy.children(id)
+                    }
 
-    #[test]
-    fn test_remove_entity() {
-        create_spawning_pool!(
-            (Position, pos, HashMapStorage),
-            (Velocity, vel, HashMapStorage)
-        );
-        let mut pool = SpawningPool::new();
-        let id = pool.spawn_entity();
+                    /// Removes the entity and handles its children according to `policy`. A plain
+                    /// `remove_entity` detaches the children once the entity is cleaned up.
+                    #[allow(dead_code)]
+                    pub fn despawn_with(&mut self, id: $crate::EntityId, policy: $crate::hierarchy::OrphanPolicy) {
+                        match policy {
+                            $crate::hierarchy::OrphanPolicy::Despawn => {
+                                let descendants = self.hierarchy.descendants(id);
+                                self.remove_entities(Some(id).into_iter().chain(descendants));
+                            },
+                            $crate::hierarchy::OrphanPolicy::Detach => {
+                                for child in self.hierarchy.children(id).to_vec() {
+                                    self.hierarchy.remove_parent(child);
+                                }
+                                self.remove_entity(id);
+                            },
+                            $crate::hierarchy::OrphanPolicy::Reparent => {
+                                let grandparent = self.hierarchy.remove_parent(id);
+                                for child in self.hierarchy.children(id).to_vec() {
+                                    match grandparent {
+                                        Some(grandparent) => { self.hierarchy.set_parent(child, grandparent); },
+                                        None => { self.hierarchy.remove_parent(child); }
+                                    }
+                                }
+                                self.remove_entity(id);
+                            }
+                        }
+                    }
 
-        pool.set(id, Velocity{x: 1, y: 2});
+                    /// Removes the entity along with all its descendants
+                    #[allow(dead_code)]
+                    pub fn despawn_recursive(&mut self, id: $crate::EntityId) {
+                        self.despawn_with(id, $crate::hierarchy::OrphanPolicy::Despawn);
+                    }
 
-        match pool.get::<Velocity>(id) {
-            Some(vel) => {
-                assert_eq!(vel.x, 1);
-                assert_eq!(vel.y, 2);
-            }
-            None => assert!(false)
-        }
+                    /// Removes the entity, its children stay alive without a parent
+                    #[allow(dead_code)]
+                    pub fn despawn_and_detach(&mut self, id: $crate::EntityId) {
+                        self.despawn_with(id, $crate::hierarchy::OrphanPolicy::Detach);
+                    }
 
-        pool.remove_entity(id);
+                    /// Removes the entity, its children are attached to its parent
+                    #[allow(dead_code)]
+                    pub fn despawn_and_reparent(&mut self, id: $crate::EntityId) {
+                        self.despawn_with(id, $crate::hierarchy::OrphanPolicy::Reparent);
+                    }
 
-        assert!(pool.get::<Velocity>(id).is_none());
-    }
+                    /// Relates `from` to `to` with relation `R`, returns `false` if they already were
+                    #[allow(dead_code)]
+                    pub fn relate<R: $crate::relations::Relation>(&mut self, from: $crate::EntityId, to: $crate::EntityId) -> bool {
+                        self.relations.get_mut::<R>().relate(from, to)
+                    }
 
-    #[test]
-    fn test_force_get() {
-        create_spawning_pool!(
-            (Position, pos, HashMapStorage),
-            (Velocity, vel, HashMapStorage)
-        );
-        let mut pool = SpawningPool::new();
-        let id = pool.spawn_entity();
+                    #[allow(dead_code)]
+                    pub fn unrelate<R: $crate::relations::Relation>(&mut self, from: $crate::EntityId, to: $crate::EntityId) -> bool {
+                        self.relations.get_mut::<R>().unrelate(from, to)
+                    }
 
-        pool.set(id, Velocity{x: 1, y: 2});
+                    #[allow(dead_code)]
+                    pub fn is_related<R: $crate::relations::Relation>(&self, from: $crate::EntityId, to: $crate::EntityId) -> bool {
+                        self.relations.get::<R>().map(|r| r.is_related(from, to)).unwrap_or(false)
+                    }
 
-        match pool.get::<Velocity>(id) {
-            Some(vel) => {
-                assert_eq!(vel.x, 1);
-                assert_eq!(vel.y, 2);
-            }
-            None => assert!(false)
-        }
+                    /// Entities `from` is related to with `R`. Pairs with entities pending removal are
+                    /// kept until `cleanup_removed`.
+                    #[allow(dead_code)]
+                    pub fn relations<R: $crate::relations::Relation>(&self, from: $crate::EntityId) -> &[$crate::EntityId] {
+                        self.relations.get::<R>().map(|r| r.targets(from)).unwrap_or(&[])
+                    }
 
-        pool.remove_entity(id);
+                    /// Entities related to `to` with `R`
+                    #[allow(dead_code)]
+                    pub fn related_to<R: $crate::relations::Relation>(&self, to: $crate::EntityId) -> &[$crate::EntityId] {
+                        self.relations.get::<R>().map(|r| r.sources(to)).unwrap_or(&[])
+                    }
 
-        assert!(pool.get::<Velocity>(id).is_none());
-        assert!(pool.force_get::<Velocity>(id).is_some());
-        pool.cleanup_removed();
-        assert!(pool.force_get::<Velocity>(id).is_none());
-    }
+                    /// Every entity related to `id` with `R`, directly or through other entities, like
+                    /// all descendants for a `ChildOf` relation. Breadth first, cycles are safe.
+                    #[allow(dead_code)]
+                    pub fn descendants<R: $crate::relations::Relation>(&self, id: $crate::EntityId) -> Vec<$crate::EntityId> {
+                        self.relations.get::<R>().map(|r| r.walk(id, false, usize::MAX)).unwrap_or_default()
+                    }
 
-    #[test]
-    fn test_get_mut() {
-        create_spawning_pool!(
-            (Position, pos, HashMapStorage),
-            (Velocity, vel, HashMapStorage)
-        );
-        let mut pool = SpawningPool::new();
-        let id = pool.spawn_entity();
-        assert!(pool.get::<Position>(id).is_none());
+                    /// Entities `id` reaches by following `R` at most `max_depth` times, breadth first
+                    /// and without `id` itself, cycles are safe
+                    #[allow(dead_code)]
+                    pub fn reachable<R: $crate::relations::Relation>(&self, id: $crate::EntityId, max_depth: usize) -> Vec<$crate::EntityId> {
+                        self.relations.get::<R>().map(|r| r.walk(id, true, max_depth)).unwrap_or_default()
+                    }
 
-        pool.set(id, Velocity{x: 1, y: 2});
+                    /// Sends an event to every reader of `E`
+                    #[allow(dead_code)]
+                    pub fn emit<E: Send + Sync + 'static>(&mut self, event: E) {
+                        self.events.get_mut::<E>().send(event);
+                    }
 
-        match pool.get::<Velocity>(id) {
-            Some(vel) => {
-                assert_eq!(vel.x, 1);
-                assert_eq!(vel.y, 2);
-            }
-            None => assert!(false)
-        }
+                    /// Events of type `E`, read them with an `EventReader`
+                    #[allow(dead_code)]
+                    pub fn events<E: Send + Sync + 'static>(&self) -> $crate::events::Events<'_, E> {
+                        self.events.get::<E>()
+                    }
 
-        match pool.get_mut::<Velocity>(id) {
-            Some(vel) => {
-                vel.x = 3;
-                vel.y = 4;
-            }
-            None => assert!(false)
-        }
+                    #[allow(dead_code)]
+                    pub fn events_mut<E: Send + Sync + 'static>(&mut self) -> &mut $crate::events::EventChannel<E> {
+                        self.events.get_mut::<E>()
+                    }
 
-        match pool.get::<Velocity>(id) {
-            Some(vel) => {
-                assert_eq!(vel.x, 3);
-                assert_eq!(vel.y, 4);
-            }
-            None => assert!(false)
-        }
-    }
+                    /// Drops events sent before the previous call, call once per frame
+                    #[allow(dead_code)]
+                    pub fn update_events(&mut self) {
+                        self.events.update();
+                    }
 
-    #[test]
-    fn test_remove() {
-        create_spawning_pool!(
-            (Position, pos, HashMapStorage),
-            (Velocity, vel, HashMapStorage)
-        );
-        let mut pool = SpawningPool::new();
-        let id = pool.spawn_entity();
-        assert!(pool.get::<Position>(id).is_none());
+                    /// Starts keeping the set of live entities having every component of `Q`, a tuple
+                    /// of component types, up to date as components are set and removed
+                    #[allow(dead_code)]
+                    pub fn watch_query<Q>(&mut self) -> $crate::live::LiveQuery where Q: for<'a> $crate::fetch::Fetch<'a, Self> {
+                        let components = (0..Self::SCHEMA.len()).filter(|&index| Q::contains(index)).collect();
+                        let entities = self.component_entity_ids().into_iter().filter(|&id| Q::exists(self, id)).collect();
+                        self.live.watch(components, Q::exists, entities)
+                    }
 
-        pool.set(id, Velocity{x: 1, y: 2});
+                    #[allow(dead_code)]
+                    pub fn unwatch_query(&mut self, query: $crate::live::LiveQuery) {
+                        self.live.unwatch(query);
+                    }
 
-        match pool.get::<Velocity>(id) {
-            Some(vel) => {
-                assert_eq!(vel.x, 1);
-                assert_eq!(vel.y, 2);
-            }
-            None => assert!(false)
-        }
+                    /// Entities currently matching a watched query, in ascending order
+                    #[allow(dead_code)]
+                    pub fn live_entities(&self, query: &$crate::live::LiveQuery) -> impl ExactSizeIterator<Item = $crate::EntityId> + '_ {
+                        self.live.entities(query).iter().cloned()
+                    }
 
-        pool.remove::<Velocity>(id);
+                    /// Keeps the entities matched by `query_entities` and `Query::for_each` until
+                    /// their components are added or removed, see the `cache` module
+                    #[allow(dead_code)]
+                    pub fn cache_queries(&mut self, enabled: bool) {
+                        self.query_cache.set_enabled(enabled);
+                    }
 
-       assert!( pool.get::<Velocity>(id).is_none());
-    }
+                    #[allow(dead_code)]
+                    pub fn clear_query_cache(&self) {
+                        self.query_cache.clear();
+                    }
 
-    #[test]
-    fn test_get_mut_vector_storage() {
-        create_spawning_pool!(
-            (Position, pos, VectorStorage),
-            (Velocity, vel, VectorStorage)
-        );
-        let mut pool = SpawningPool::new();
-        let id = pool.spawn_entity();
-        assert!(pool.get::<Position>(id).is_none());
+                    /// Live entities having every component of `Q`, a tuple of component types, in
+                    /// ascending order
+                    #[allow(dead_code)]
+                    pub fn query_entities<Q>(&self) -> ::std::sync::Arc<Vec<$crate::EntityId>> where Q: for<'a> $crate::fetch::Fetch<'a, Self> {
+                        let components: Vec<usize> = (0..Self::SCHEMA.len()).filter(|&index| Q::contains(index)).collect();
+                        $crate::cache::CachedQueries::cached_entities(self, &components, |pool: &Self| {
+                            pool.component_entity_ids().into_iter().filter(|&id| Q::exists(pool, id)).collect()
+                        })
+                    }
 
-        pool.set(id, Velocity{x: 1, y: 2});
+                    $crate::__spawning_pool_if_async! {
+                        /// Stream of every change to component `T` from now on, needs the `async`
+                        /// feature
+                        #[allow(dead_code)]
+                        pub fn changes<T>(&mut self) -> $crate::changes::ChangeStream where Self: ComponentLoader<T> {
+                            self.observers.subscribe(<Self as ComponentLoader<T>>::INDEX)
+                        }
+                    }
 
-        match pool.get::<Velocity>(id) {
-            Some(vel) => {
-                assert_eq!(vel.x, 1);
-                assert_eq!(vel.y, 2);
-            }
+                    /// Checks a live entity again against the watched queries with component `index`,
+                    /// or all of them
+                    fn refresh_live(&mut self, id: $crate::EntityId, index: Option<usize>) {
+                        if self.live.is_empty() || self.removed.contains(&id) {
+                            return;
+                        }
+                        let mut live = ::std::mem::take(&mut self.live);
+                        match index {
+                            Some(index) => live.refresh(self, id, index),
+                            None => live.refresh_all(self, id)
+                        }
+                        self.live = live;
+                    }
+
+                    /// Checks the entity against every peer's interest, peers it became relevant to
+                    /// get all its replicated components
+                    fn refresh_interest(&mut self, id: $crate::EntityId) {
+                        if self.interests.is_empty() || self.removed.contains(&id) {
+                            return;
+                        }
+                        let mut interests = ::std::mem::take(&mut self.interests);
+                        let entered = interests.refresh(self, id);
+                        self.interests = interests;
+                        for peer in entered {
+                            self.mark_replicated_for(peer, id);
+                        }
+                    }
+
+                    fn mark_replicated_for(&mut self, peer: $crate::replication::PeerId, id: $crate::EntityId) {
+                        $(
+                            if $crate::__spawning_pool_is_replicated!($($flag),*) && self.$store_name.get(id).is_some() {
+                                self.replication.mark_dirty_for(peer, id, stringify!($component));
+                            }
+                        )+
+                    }
+
+                    /// Queues the entity for removal once `tick` has been called `ticks` times,
+                    /// replacing any earlier timer
+                    #[allow(dead_code)]
+                    pub fn despawn_after(&mut self, id: $crate::EntityId, ticks: u64) {
+                        self.despawn_timers.set(id, ticks);
+                    }
+
+                    /// Stops a `despawn_after` timer, returns the ticks it had left
+                    #[allow(dead_code)]
+                    pub fn cancel_despawn(&mut self, id: $crate::EntityId) -> Option<u64> {
+                        self.despawn_timers.remove(id)
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn despawn_timer(&self, id: $crate::EntityId) -> Option<u64> {
+                        self.despawn_timers.get(id)
+                    }
+
+                    /// Counts down the `despawn_after` timers and queues the entities whose timer ran
+                    /// out for removal, returning them sorted by id
+                    #[allow(dead_code)]
+                    pub fn tick(&mut self) -> Vec<$crate::EntityId> {
+                        if self.despawn_timers.is_empty() {
+                            return vec![];
+                        }
+                        let expired = self.despawn_timers.tick();
+                        self.remove_entities(expired.iter().cloned());
+                        expired
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn set_layers(&mut self, id: $crate::EntityId, layers: $crate::layers::Layers) {
+                        self.layers.set(id, layers);
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn layers(&self, id: $crate::EntityId) -> $crate::layers::Layers {
+                        self.layers.get(id)
+                    }
+
+                    /// Live entities on any layer of `mask`, sorted by entity id
+                    #[allow(dead_code)]
+                    pub fn entities_in_layer(&self, mask: $crate::layers::Layers) -> Vec<$crate::EntityId> {
+                        self.layers.iter_matching(mask).filter(|id| !self.removed.contains(id)).collect()
+                    }
+
+                    /// Entities removed with `remove_entity` that `cleanup_removed` hasn't destroyed yet
+                    #[allow(dead_code)]
+                    pub fn pending_removals(&self) -> impl ExactSizeIterator<Item = $crate::EntityId> + '_ {
+                        self.removed.iter()
+                    }
+
+                    /// Takes the entity back out of the removal queue, keeping all its components.
+                    /// Returns `false` if the entity wasn't pending removal.
+                    #[allow(dead_code)]
+                    pub fn cancel_removal(&mut self, id: $crate::EntityId) -> bool {
+                        if !self.removed.remove(&id) {
+                            return false;
+                        }
+                        $crate::__spawning_pool_event!(entity = id.to_raw(), "cancel_removal");
+                        self.ticks.touch_removals();
+                        self.replication.cancel_despawned(id);
+                        $(
+                            if self.$store_name.get(id).is_some() {
+                                self.removal_tracker.forget(<Self as ComponentLoader<$component>>::INDEX, id);
+                            }
+                        )+
+                        self.refresh_live(id, None);
+                        self.refresh_interest(id);
+                        true
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn set<T>(&mut self, id: $crate::EntityId, component: T) where Self: ComponentLoader<T> {
+                        $crate::__spawning_pool_if_strict! {
+                            self.assert_alive(id, "set", ::std::any::type_name::<T>());
+                        }
+                        if self.is_alive(id) {
+                            self.set_overloaded(id, component);
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn set_bundle<B>(&mut self, id: $crate::EntityId, bundle: B) where B: $crate::bundle::Bundle<Self> {
+                        bundle.insert_into(self, id);
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn get<T>(&self, id: $crate::EntityId) -> Option<&T> where Self: ComponentLoader<T> {
+                        if self.is_alive(id) {
+                            self.get_overloaded(id)
+                        } else {
+                            None
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn force_get<T>(&self, id: $crate::EntityId) -> Option<&T> where Self: ComponentLoader<T> {
+                        self.get_overloaded(id)
+                    }
+
+                    /// Component `T` of every entity in `ids`, in the same order, with `None` for
+                    /// entities that aren't alive or don't have it. Faster than calling `get` for
+                    /// each, metrics count the batch as one iteration.
+                    #[allow(dead_code)]
+                    pub fn get_many<T>(&self, ids: &[$crate::EntityId]) -> Vec<Option<&T>> where Self: ComponentLoader<T> {
+                        self.metrics.record(<Self as ComponentLoader<T>>::INDEX, $crate::metrics::Access::Iterate);
+                        let alive = self.alive_filter();
+                        ids.iter().map(|&id| if alive(id) { self.lookup_overloaded(id) } else { None }).collect()
+                    }
+
+                    /// Lazy version of `get_many`
+                    #[allow(dead_code)]
+                    pub fn get_many_iter<'a, T: 'a, I>(&'a self, ids: I) -> impl Iterator<Item = Option<&'a T>> + 'a
+                        where Self: ComponentLoader<T>, I: IntoIterator<Item = $crate::EntityId>, I::IntoIter: 'a {
+                        self.metrics.record(<Self as ComponentLoader<T>>::INDEX, $crate::metrics::Access::Iterate);
+                        let alive = self.alive_filter();
+                        ids.into_iter().map(move |id| if alive(id) { self.lookup_overloaded(id) } else { None })
+                    }
+
+                    /// Same as `is_alive`, reading the ID counter once and skipping empty sets for
+                    /// batches of IDs
+                    fn alive_filter(&self) -> impl Fn($crate::EntityId) -> bool + '_ {
+                        let end = self.next_id.load(::std::sync::atomic::Ordering::Relaxed);
+                        let (removed, destroyed) = (!self.removed.is_empty(), !self.destroyed.is_empty());
+                        move |id| id.to_raw() < end
+                            && !(removed && self.removed.contains(&id))
+                            && !(destroyed && self.destroyed.contains(&id))
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn get_mut<T>(&mut self, id: $crate::EntityId) -> Option<&mut T> where Self: ComponentLoader<T> {
+                        $crate::__spawning_pool_if_strict! {
+                            self.assert_alive(id, "get_mut", ::std::any::type_name::<T>());
+                        }
+                        if self.is_alive(id) {
+                            self.get_mut_overloaded(id)
+                        } else {
+                            None
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn remove<T>(&mut self, id: $crate::EntityId) where Self: ComponentLoader<T> {
+                        if self.is_alive(id) {
+                            self.remove_overloaded(id);
+                        }
+                    }
+
+                    /// Removes component `T` from every live entity and yields them in entity id
+                    /// order. Like `remove` except the components are handed over, so lifecycle
+                    /// `on_removed` hooks don't run. Entities pending removal keep theirs.
+                    #[allow(dead_code)]
+                    pub fn drain<T>(&mut self) -> impl Iterator<Item = ($crate::EntityId, T)> where Self: ComponentLoader<T> {
+                        self.drain_overloaded().into_iter()
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn get_all<T>(&self) -> Vec<($crate::EntityId, &T)> where Self: ComponentLoader<T> {
+                        let mut all = vec![];
+                        self.get_all_into(&mut all);
+                        all
+                    }
+
+                    /// Like `get_all`, but fills `out` so its allocation can be reused between calls,
+                    /// `out` is cleared first
+                    #[allow(dead_code)]
+                    pub fn get_all_into<'a, T>(&'a self, out: &mut Vec<($crate::EntityId, &'a T)>) where Self: ComponentLoader<T> {
+                        out.clear();
+                        self.get_all_into_overloaded(out);
+                        if !self.removed.is_empty() {
+                            out.retain(|(id, _)| !self.removed.contains(id));
+                        }
+                    }
+
+                    /// Like `get_all`, in entity id order whatever the storage
+                    #[allow(dead_code)]
+                    pub fn get_all_sorted<T>(&self) -> Vec<($crate::EntityId, &T)> where Self: ComponentLoader<T> {
+                        let mut all = self.get_all();
+                        all.sort_unstable_by_key(|(id, _)| *id);
+                        all
+                    }
+
+                    /// Like `get_all`, ordered by the key `key` gives each component, components with
+                    /// equal keys stay in entity id order
+                    #[allow(dead_code)]
+                    pub fn get_all_sorted_by<T, K: Ord>(&self, mut key: impl FnMut(&T) -> K) -> Vec<($crate::EntityId, &T)> where Self: ComponentLoader<T> {
+                        let mut all = self.get_all_sorted();
+                        all.sort_by_key(|(_, component)| key(component));
+                        all
+                    }
+
+                    /// Live entities with component `T` as a set, combine sets with `&`, `|` and `-` to
+                    /// join components
+                    #[allow(dead_code)]
+                    pub fn entity_set<T>(&self) -> $crate::bitset::EntitySet where Self: ComponentLoader<T> {
+                        self.get_all::<T>().into_iter().map(|(id, _)| id).collect()
+                    }
+
+                    /// Adds component `T` to the pool's dynamic section, for components only known at
+                    /// runtime like the ones of mods. Dynamic components are reached through `get_any`,
+                    /// `set_any` and friends, and aren't part of save files. Panics if `T` is one of
+                    /// the pool's own components.
+                    #[allow(dead_code)]
+                    pub fn register_dynamic<T: $crate::dynamic::DynamicComponent>(&mut self, name: &'static str) {
+                        self.register_dynamic_with::<T, $crate::storage::HashMapStorage<T>>(name);
+                    }
+
+                    /// Like `register_dynamic`, keeping the components in an `S`
+                    #[allow(dead_code)]
+                    pub fn register_dynamic_with<T, S>(&mut self, name: &'static str)
+                        where T: Clone + 'static, S: $crate::dynamic::DynamicStorage<T> {
+                        $(
+                            assert!(
+                                ::std::any::TypeId::of::<T>() != ::std::any::TypeId::of::<$component>(),
+                                "`{}` is a static component of the pool", stringify!($component)
+                            );
+                        )+
+                        self.dynamic.register_with::<T, S>(name);
+                    }
+
+                    /// The dynamic section, its storages don't check whether entities are alive
+                    #[allow(dead_code)]
+                    pub fn dynamic(&self) -> &$crate::dynamic::DynamicComponents {
+                        &self.dynamic
+                    }
+
+                    /// Like `get`, for any component type, falling back to the dynamic section when
+                    /// `T` isn't one of the pool's own components
+                    #[allow(dead_code)]
+                    pub fn get_any<T: Clone + 'static>(&self, id: $crate::EntityId) -> Option<&T> {
+                        if !self.is_alive(id) {
+                            return None;
+                        }
+                        $(
+                            if ::std::any::TypeId::of::<T>() == ::std::any::TypeId::of::<$component>() {
+                                let component: &dyn __SpawningPoolAny = <Self as ComponentLoader<$component>>::get_overloaded(self, id)?;
+                                return component.downcast_ref();
+                            }
+                        )+
+                        self.dynamic.get(id)
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn get_any_mut<T: Clone + 'static>(&mut self, id: $crate::EntityId) -> Option<&mut T> {
+                        if !self.is_alive(id) {
+                            return None;
+                        }
+                        $(
+                            if ::std::any::TypeId::of::<T>() == ::std::any::TypeId::of::<$component>() {
+                                let component: &mut dyn __SpawningPoolAny = self.get_mut::<$component>(id)?;
+                                return component.downcast_mut();
+                            }
+                        )+
+                        self.dynamic.get_mut(id)
+                    }
+
+                    /// Like `set`, for any component type. Panics if `T` is neither one of the pool's
+                    /// own components nor registered with `register_dynamic`.
+                    #[allow(dead_code)]
+                    pub fn set_any<T: Clone + 'static>(&mut self, id: $crate::EntityId, component: T) {
+                        $(
+                            if ::std::any::TypeId::of::<T>() == ::std::any::TypeId::of::<$component>() {
+                                let mut component = Some(component);
+                                let component = (&mut component as &mut dyn __SpawningPoolAny)
+                                    .downcast_mut::<Option<$component>>()
+                                    .and_then(Option::take)
+                                    .expect("type ids match");
+                                self.set(id, component);
+                                return;
+                            }
+                        )+
+                        if self.is_alive(id) {
+                            self.dynamic.set(id, component);
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn remove_any<T: Clone + 'static>(&mut self, id: $crate::EntityId) {
+                        $(
+                            if ::std::any::TypeId::of::<T>() == ::std::any::TypeId::of::<$component>() {
+                                self.remove::<$component>(id);
+                                return;
+                            }
+                        )+
+                        if self.is_alive(id) {
+                            self.dynamic.remove::<T>(id);
+                        }
+                    }
+
+                    /// Components `Q`, a tuple of component types, of the entity, or `None` if any of
+                    /// them is missing
+                    #[allow(dead_code)]
+                    pub fn get_components<'a, Q>(&'a self, id: $crate::EntityId) -> Option<Q::Item> where Q: $crate::fetch::Fetch<'a, Self> {
+                        Q::fetch(self, id)
+                    }
+
+                    /// Like `get_components`, with mutable access to component `M` which must not be
+                    /// part of `Q`
+                    #[allow(dead_code)]
+                    pub fn get_components_mut<'a, M, Q>(&'a mut self, id: $crate::EntityId) -> Option<(&'a mut M, Q::Item)>
+                        where Self: ComponentLoader<M>, Q: $crate::fetch::Fetch<'a, Self> {
+                        assert!(
+                            !Q::contains(<Self as ComponentLoader<M>>::INDEX),
+                            "component borrowed both mutably and immutably"
+                        );
+                        if !Q::exists(self, id) {
+                            return None;
+                        }
+                        let component: *mut M = self.get_mut::<M>(id)?;
+                        let rest = Q::fetch(self, id)?;
+                        // SAFETY: `component` lives in the storage of `M`, which `get_mut` made unique
+                        // to this pool, and `Q` only reads the storages of other components
+                        Some((unsafe { &mut *component }, rest))
+                    }
+
+                    /// Number of live entities with component `T`
+                    #[allow(dead_code)]
+                    pub fn count<T>(&self) -> usize where Self: ComponentLoader<T> {
+                        self.count_overloaded()
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn is_empty<T>(&self) -> bool where Self: ComponentLoader<T> {
+                        self.count_overloaded() == 0
+                    }
+
+                    /// Runs a system closure over a query of the components it declares
+                    #[allow(dead_code)]
+                    pub fn run<Q, R, F>(&mut self, system: F) -> R
+                        where Q: $crate::query::QueryData<Self>, F: FnOnce($crate::query::Query<'_, Self, Q>) -> R {
+                        system($crate::query::Query::new(self))
+                    }
+
+                    /// Every unordered pair of live entities having component `T`, each pair visited once
+                    #[allow(dead_code)]
+                    pub fn iter_combinations<T>(&self) -> $crate::fetch::Combinations<'_, T> where Self: ComponentLoader<T> {
+                        $crate::fetch::Combinations::new(self.get_all::<T>())
+                    }
+
+                    /// Live entities having component `T` in groups of at most `chunk_size`, for batch
+                    /// processing or splitting work between threads. Panics if `chunk_size` is 0.
+                    #[allow(dead_code)]
+                    pub fn iter_chunks<T>(&self, chunk_size: usize) -> $crate::fetch::Chunks<'_, T> where Self: ComponentLoader<T> {
+                        $crate::fetch::Chunks::new(self.get_all::<T>(), chunk_size)
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn current_tick(&self) -> $crate::ticks::Tick {
+                        self.ticks.current()
+                    }
+
+                    /// Records the current tick of entities spawned from now on, for `age_of`
+                    #[allow(dead_code)]
+                    pub fn track_spawn_ticks(&mut self, enabled: bool) {
+                        self.spawn_ticks.set_enabled(enabled);
+                    }
+
+                    /// Tick the entity was spawned at, if spawn ticks were tracked at the time
+                    #[allow(dead_code)]
+                    pub fn spawn_tick(&self, id: $crate::EntityId) -> Option<$crate::ticks::Tick> {
+                        self.spawn_ticks.get(id)
+                    }
+
+                    /// Ticks between the entity's spawn and `current_tick`
+                    #[allow(dead_code)]
+                    pub fn age_of(&self, id: $crate::EntityId, current_tick: $crate::ticks::Tick) -> Option<$crate::ticks::Tick> {
+                        self.spawn_ticks.get(id).map(|spawned| current_tick.saturating_sub(spawned))
+                    }
+
+                    /// Moves the change counter forward, components written from now on are recorded
+                    /// with the returned tick
+                    #[allow(dead_code)]
+                    pub fn advance_tick(&mut self) -> $crate::ticks::Tick {
+                        self.ticks.advance()
+                    }
+
+                    /// Components `T` written by `set` or `get_mut` at or after `tick`, sorted by
+                    /// entity id. Writes through `split` are not tracked.
+                    #[allow(dead_code)]
+                    pub fn changed_since<T>(&self, tick: $crate::ticks::Tick) -> Vec<($crate::EntityId, &T)> where Self: ComponentLoader<T> {
+                        self.ticks.changed_since(<Self as ComponentLoader<T>>::INDEX, tick)
+                            .into_iter()
+                            .filter_map(|id| self.get::<T>(id).map(|c| (id, c)))
+                            .collect()
+                    }
+
+                    /// Records the entities losing each component from now on, for `removed_this_frame`
+                    #[allow(dead_code)]
+                    pub fn track_removals(&mut self, enabled: bool) {
+                        self.removal_tracker.set_enabled(enabled);
+                    }
+
+                    /// Entities that lost component `T` through `remove`, `drain` or `remove_entity`
+                    /// since the last `clear_trackers` call, while `track_removals` is enabled. Entities
+                    /// are listed once they are queued for removal.
+                    #[allow(dead_code)]
+                    pub fn removed_this_frame<T>(&self) -> &[$crate::EntityId] where Self: ComponentLoader<T> {
+                        self.removal_tracker.removed(<Self as ComponentLoader<T>>::INDEX)
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn clear_trackers(&mut self) {
+                        self.removal_tracker.clear();
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn uuid_of(&self, id: $crate::EntityId) -> Option<$crate::uuid::Uuid> {
+                        if self.is_alive(id) {
+                            self.uuids.get(id)
+                        } else {
+                            None
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn entity_by_uuid(&self, uuid: &$crate::uuid::Uuid) -> Option<$crate::EntityId> {
+                        self.uuids.entity(uuid).filter(|id| !self.removed.contains(id))
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn set_uuid(&mut self, id: $crate::EntityId, uuid: $crate::uuid::Uuid) {
+                        if self.is_alive(id) {
+                            self.uuids.insert(id, uuid);
+                        }
+                    }
+
+                    /// UUID of the entity, generating one if it has none yet. `None` unless the entity
+                    /// is alive.
+                    #[allow(dead_code)]
+                    pub fn ensure_uuid(&mut self, id: $crate::EntityId) -> Option<$crate::uuid::Uuid> {
+                        if !self.is_alive(id) {
+                            return None;
+                        }
+                        match self.uuids.get(id) {
+                            Some(uuid) => Some(uuid),
+                            None => {
+                                let uuid = $crate::uuid::Uuid::new_v4();
+                                self.uuids.insert(id, uuid);
+                                Some(uuid)
+                            }
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn add_peer(&mut self, peer: $crate::replication::PeerId) {
+                        self.replication.add_peer(peer);
+                        $(
+                            if $crate::__spawning_pool_is_replicated!($($flag),*) {
+                                let ids: Vec<$crate::EntityId> = self.get_all::<$component>().iter().map(|(id, _)| *id).collect();
+                                for id in ids {
+                                    self.replication.mark_dirty_for(peer, id, stringify!($component));
+                                }
+                            }
+                        )+
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn remove_peer(&mut self, peer: $crate::replication::PeerId) {
+                        self.replication.remove_peer(peer);
+                        self.interests.remove(peer);
+                    }
+
+                    /// Limits the updates of `peer`, added with `add_peer`, to the entities matching
+                    /// `filter`, replacing its previous filter. Entities that become relevant are sent
+                    /// in full, the ones that stop being relevant are sent as despawned.
+                    #[allow(dead_code)]
+                    pub fn set_interest(&mut self, peer: $crate::replication::PeerId, filter: $crate::interest::InterestFilter<Self>) {
+                        let relevant = self.entities().filter(|&id| filter.matches(self, id)).collect();
+                        let entered = self.interests.set(peer, filter, relevant);
+                        for id in entered {
+                            self.mark_replicated_for(peer, id);
+                        }
+                    }
+
+                    /// Sends `peer` the changes of every entity again. Entities that weren't relevant
+                    /// to it are queued in full, already relevant entities aren't resent.
+                    #[allow(dead_code)]
+                    pub fn remove_interest(&mut self, peer: $crate::replication::PeerId) {
+                        let relevant = match self.interests.remove(peer) {
+                            Some(relevant) => relevant,
+                            None => return
+                        };
+                        let entered: Vec<$crate::EntityId> = self.entities().filter(|id| !relevant.contains(id)).collect();
+                        for id in entered {
+                            self.mark_replicated_for(peer, id);
+                        }
+                    }
+
+                    /// Entities relevant to `peer` in ascending order, `None` if it has no interest set
+                    #[allow(dead_code)]
+                    pub fn relevant_entities(&self, peer: $crate::replication::PeerId) -> Option<Vec<$crate::EntityId>> {
+                        self.interests.relevant(peer).map(|relevant| relevant.iter().cloned().collect())
+                    }
+
+                    /// Checks every entity against the peers' interests again, for changes the pool
+                    /// didn't see like positions moved through `get_mut`
+                    #[allow(dead_code)]
+                    pub fn refresh_interests(&mut self) {
+                        if self.interests.is_empty() {
+                            return;
+                        }
+                        let ids: Vec<$crate::EntityId> = self.entities().collect();
+                        for id in ids {
+                            self.refresh_interest(id);
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn collect_updates(&mut self, peer: $crate::replication::PeerId) -> $crate::replication::ReplicationPacket {
+                        let reset = self.replication.take_reset(peer);
+                        let (mut dirty, mut despawned) = self.replication.take(peer);
+                        if let Some(relevant) = self.interests.relevant(peer) {
+                            dirty.retain(|(id, _)| relevant.contains(id));
+                            despawned = self.interests.take_left(peer);
+                        }
+                        if reset {
+                            despawned.clear();
+                        }
+                        let updates = dirty.into_iter()
+                            .map(|(id, name)| $crate::replication::ComponentUpdate {
+                                entity: id,
+                                component: name.to_string(),
+                                value: self.get_value(id, name)
+                            })
+                            .collect();
+                        $crate::replication::ReplicationPacket {
+                            updates,
+                            despawned,
+                            reset
+                        }
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn apply_updates(&mut self, packet: &$crate::replication::ReplicationPacket) -> Result<(), $crate::inspect::ValueError> {
+                        if packet.reset {
+                            self.reset_entities();
+                        }
+                        for update in &packet.updates {
+                            let next_id = self.next_id.get_mut();
+                            if update.entity.to_raw() >= *next_id {
+                                *next_id = update.entity.to_raw() + 1;
+                            }
+                            match update.value {
+                                Some(ref value) => self.set_value(update.entity, &update.component, value.clone())?,
+                                None => $crate::inspect::Inspect::remove_component(self, update.entity, &update.component)?
+                            }
+                        }
+                        for id in &packet.despawned {
+                            self.remove_entity(*id);
+                        }
+                        Ok(())
+                    }
+
+                    /// Destroys every entity and hands out IDs from 1 again, generations are kept so
+                    /// weak references to the destroyed entities stay invalid
+                    fn reset_entities(&mut self) {
+                        let ids: Vec<$crate::EntityId> = self.entities().collect();
+                        for id in ids {
+                            self.queue_removal(id);
+                        }
+                        self.cleanup_removed();
+                        self.destroyed = Default::default();
+                        *self.next_id.get_mut() = 1;
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn entities_in_rect<T>(&self, min: (f32, f32), max: (f32, f32)) -> Vec<$crate::EntityId>
+                        where T: Clone + $crate::storage::HasPosition, Self: ComponentLoader<T, Storage = $crate::storage::SpatialGridStorage<T>> {
+                        let mut ids = self.storage_overloaded().in_rect(min, max);
+                        ids.retain(|id| !self.removed.contains(id));
+                        ids.sort();
+                        ids
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn entities_near<T>(&self, point: (f32, f32), radius: f32) -> Vec<$crate::EntityId>
+                        where T: Clone + $crate::storage::HasPosition, Self: ComponentLoader<T, Storage = $crate::storage::SpatialGridStorage<T>> {
+                        let mut ids = self.storage_overloaded().near(point, radius);
+                        ids.retain(|id| !self.removed.contains(id));
+                        ids.sort();
+                        ids
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn find_by<T, K>(&self, key: &K) -> Vec<$crate::EntityId>
+                        where T: Clone + $crate::storage::IndexKey<Key = K>, Self: ComponentLoader<T, Storage = $crate::storage::IndexedStorage<T>> {
+                        let mut ids = self.storage_overloaded().find(key);
+                        ids.retain(|id| !self.removed.contains(id));
+                        ids.sort();
+                        ids
+                    }
+
+                    /// Last values of component `T` set on the entity by a `HistoryStorage`, oldest first
+                    /// and ending with the current one
+                    #[allow(dead_code)]
+                    pub fn history<T>(&self, id: $crate::EntityId) -> Option<&::std::collections::VecDeque<T>>
+                        where T: Clone, Self: ComponentLoader<T, Storage = $crate::storage::HistoryStorage<T>> {
+                        if self.is_alive(id) {
+                            self.storage_overloaded().history(id)
+                        } else {
+                            None
+                        }
+                    }
+
+                    /// The storage of component `T`. Entities pending removal are not filtered out.
+                    #[allow(dead_code)]
+                    pub fn storage<T>(&self) -> &<Self as ComponentLoader<T>>::Storage where Self: ComponentLoader<T> {
+                        self.storage_overloaded()
+                    }
+
+                    /// Makes room for `additional` more components `T`, so they can be added without
+                    /// the storage growing
+                    #[allow(dead_code)]
+                    pub fn reserve<T>(&mut self, additional: usize) where Self: ComponentLoader<T> {
+                        self.reserve_overloaded(additional);
+                    }
+
+                    /// Reserves room for `additional` more components in every storage, for pre-sizing
+                    /// the pool during level load
+                    #[allow(dead_code)]
+                    pub fn reserve_all(&mut self, additional: usize) {
+                        $(
+                            ::std::sync::Arc::make_mut(&mut self.$store_name).reserve(additional);
+                        )+
+                    }
+
+                    /// Mutable access to the storage of component `T`, writes through it bypass
+                    /// replication, change ticks and lifecycle hooks
+                    #[allow(dead_code)]
+                    pub fn storage_mut<T>(&mut self) -> &mut <Self as ComponentLoader<T>>::Storage where Self: ComponentLoader<T> {
+                        self.storage_mut_overloaded()
+                    }
+
+                    /// Runtime checked shared borrow of a `CellStorage`, entities pending removal are not
+                    /// filtered out
+                    #[allow(dead_code)]
+                    pub fn borrow_storage<'a, T, S>(&'a self) -> ::std::cell::Ref<'a, S>
+                        where T: Clone + 'a, S: $crate::storage::Storage<T>, Self: ComponentLoader<T, Storage = $crate::storage::CellStorage<T, S>> {
+                        self.storage_overloaded().borrow()
+                    }
+
+                    /// Runtime checked mutable borrow of a `CellStorage` through a shared pool
+                    /// reference. Writes bypass replication, change ticks and lifecycle hooks.
+                    ///
+                    /// Panics if the storage is borrowed, or still shared with a fork, in which case
+                    /// a mutable pool access like `storage_mut` makes it unique again.
+                    #[allow(dead_code)]
+                    pub fn borrow_storage_mut<'a, T, S>(&'a self) -> ::std::cell::RefMut<'a, S>
+                        where T: Clone + 'a, S: $crate::storage::Storage<T>, Self: ComponentLoader<T, Storage = $crate::storage::CellStorage<T, S>> {
+                        assert!(!self.storage_is_shared_overloaded(), "component storage is shared with a fork");
+                        self.storage_overloaded().borrow_mut()
+                    }
+
+                    /// Lets `borrow_storage_mut` run again after components `T` were read through the
+                    /// pool, see `CellStorage::release_borrows`
+                    #[allow(dead_code)]
+                    pub fn release_borrows<T, S>(&mut self)
+                        where T: Clone, S: $crate::storage::Storage<T>, Self: ComponentLoader<T, Storage = $crate::storage::CellStorage<T, S>> {
+                        if !self.storage_is_shared_overloaded() {
+                            self.storage_mut_overloaded().release_borrows();
+                        }
+                    }
+
+                    /// Mutable borrows of every storage at once, in declaration order. Storages are
+                    /// accessed directly, so entities pending removal are not filtered out.
+                    #[allow(dead_code)]
+                    pub fn split(&mut self) -> ($(&mut $storage,)+) {
+                        ($(::std::sync::Arc::make_mut(&mut self.$store_name),)+)
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn diff(a: &Self, b: &Self) -> $crate::diff::PoolDiff {
+                        $crate::diff::PoolDiff::between(a, b)
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn state_hash(&self) -> u64 {
+                        $crate::checksum::state_hash(self)
+                    }
+
+                    /// Starts counting component accesses and timing cleanup passes
+                    #[allow(dead_code)]
+                    pub fn enable_metrics(&mut self) {
+                        let count = $crate::inspect::Inspect::component_names(self).len();
+                        self.metrics.enable(count);
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn disable_metrics(&mut self) {
+                        self.metrics.disable();
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn metrics(&self) -> $crate::metrics::PoolMetrics {
+                        self.metrics.snapshot($crate::inspect::Inspect::component_names(self))
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn reset_metrics(&self) {
+                        self.metrics.reset();
+                    }
+
+                    /// Component type, storage field and storage type names, in declaration order
+                    const SCHEMA: &'static [(&'static str, &'static str, &'static str)] =
+                        &[$((stringify!($component), stringify!($store_name), $storage_name)),+];
+
+                    /// Hash of the registered components and their storages, stored in save headers
+                    #[allow(dead_code)]
+                    pub fn schema_hash() -> u64 {
+                        $crate::save::schema_hash(Self::SCHEMA)
+                    }
+
+                    /// Writes a save file header followed by the pool as JSON
+                    #[allow(dead_code)]
+                    pub fn save<W: ::std::io::Write>(&self, mut writer: W) -> Result<(), $crate::save::SaveError> {
+                        let _span = $crate::__spawning_pool_span!("save", entities = self.component_entity_ids().len());
+                        let header = $crate::save::SaveHeader {
+                            version: $crate::save::FORMAT_VERSION,
+                            schema: Self::schema_hash()
+                        };
+                        header.write(&mut writer)?;
+                        self.write_json(writer)
+                    }
+
+                    /// The pool as JSON, with any preserved data
+                    fn write_json<W: ::std::io::Write>(&self, mut writer: W) -> Result<(), $crate::save::SaveError> {
+                        if self.preserved.is_empty() {
+                            $crate::serde_json::to_writer(&mut writer, self)?;
+                        } else {
+                            let mut value = $crate::serde_json::to_value(self)?;
+                            self.preserved.merge_into(&mut value);
+                            $crate::serde_json::to_writer(&mut writer, &value)?;
+                        }
+                        Ok(())
+                    }
+
+                    /// Like `save`, with the JSON passed through `transform` before it's written
+                    #[allow(dead_code)]
+                    pub fn save_transformed<W: ::std::io::Write, T: $crate::save::SaveTransform + ?Sized>(&self, mut writer: W, transform: &T) -> Result<(), $crate::save::SaveError> {
+                        let _span = $crate::__spawning_pool_span!("save_transformed", schema = Self::schema_hash());
+                        let header = $crate::save::SaveHeader {
+                            version: $crate::save::FORMAT_VERSION,
+                            schema: Self::schema_hash()
+                        };
+                        header.write(&mut writer)?;
+                        let mut json = vec![];
+                        self.write_json(&mut json)?;
+                        writer.write_all(&transform.encode(json)?)?;
+                        Ok(())
+                    }
+
+                    /// Reads a pool written by `save_transformed` with the same transform, failing if
+                    /// it was saved with a different schema
+                    #[allow(dead_code)]
+                    pub fn load_transformed<R: ::std::io::Read, T: $crate::save::SaveTransform + ?Sized>(mut reader: R, transform: &T) -> Result<Self, $crate::save::SaveError> {
+                        let _span = $crate::__spawning_pool_span!("load_transformed", schema = Self::schema_hash());
+                        let header = $crate::save::SaveHeader::read(&mut reader)?;
+                        if header.schema != Self::schema_hash() {
+                            return Err($crate::save::SaveError::SchemaMismatch {
+                                expected: Self::schema_hash(),
+                                found: header.schema
+                            });
+                        }
+                        let mut data = vec![];
+                        reader.read_to_end(&mut data)?;
+                        Ok($crate::serde_json::from_slice(&transform.decode(data)?)?)
+                    }
+
+                    /// Reads a pool written by `save`, failing if it was saved with a different schema
+                    #[allow(dead_code)]
+                    pub fn load<R: ::std::io::Read>(mut reader: R) -> Result<Self, $crate::save::SaveError> {
+                        let _span = $crate::__spawning_pool_span!("load", schema = Self::schema_hash());
+                        let header = $crate::save::SaveHeader::read(&mut reader)?;
+                        if header.schema != Self::schema_hash() {
+                            return Err($crate::save::SaveError::SchemaMismatch {
+                                expected: Self::schema_hash(),
+                                found: header.schema
+                            });
+                        }
+                        Ok($crate::serde_json::from_reader(reader)?)
+                    }
+
+                    /// Like `save`, but writes the storages in batches of at most `batch_size` entities,
+                    /// bounding memory use for very large pools. Read the result with `load_streaming`.
+                    #[allow(dead_code)]
+                    pub fn save_streaming<W: ::std::io::Write>(&self, mut writer: W, batch_size: usize) -> Result<(), $crate::save::SaveError> {
+                        assert!(batch_size != 0, "batch size must be non-zero");
+                        let _span = $crate::__spawning_pool_span!("save_streaming", batch_size = batch_size);
+                        let header = $crate::save::SaveHeader {
+                            version: $crate::save::FORMAT_VERSION,
+                            schema: Self::schema_hash()
+                        };
+                        header.write(&mut writer)?;
+                        $crate::serde_json::to_writer(&mut writer, &self.to_value_without_storages()?)?;
+                        writer.write_all(b"\n")?;
+                        $(
+                            let mut entities = Vec::new();
+                            self.$store_name.get_all_into(&mut entities);
+                            for batch in entities.chunks(batch_size) {
+                                $crate::save::write_batch(&mut writer, stringify!($store_name), batch)?;
+                            }
+                        )+
+                        Ok(())
+                    }
+
+                    /// Reads a pool written by `save_streaming` one line at a time, failing if it was
+                    /// saved with a different schema
+                    #[allow(dead_code)]
+                    pub fn load_streaming<R: ::std::io::BufRead>(mut reader: R) -> Result<Self, $crate::save::SaveError> {
+                        let _span = $crate::__spawning_pool_span!("load_streaming", schema = Self::schema_hash());
+                        let header = $crate::save::SaveHeader::read(&mut reader)?;
+                        if header.schema != Self::schema_hash() {
+                            return Err($crate::save::SaveError::SchemaMismatch {
+                                expected: Self::schema_hash(),
+                                found: header.schema
+                            });
+                        }
+                        let mut line = String::new();
+                        reader.read_line(&mut line)?;
+                        let mut pool = Self::from_value_without_storages($crate::serde_json::from_str(&line)?)?;
+                        loop {
+                            line.clear();
+                            if reader.read_line(&mut line)? == 0 {
+                                break;
+                            }
+                            let (name, batch) = $crate::save::split_batch(line.trim_end())?;
+                            $(
+                                if name == stringify!($store_name) {
+                                    let entities: Vec<($crate::EntityId, $component)> = $crate::serde_json::from_str(batch)?;
+                                    let storage = ::std::sync::Arc::make_mut(&mut pool.$store_name);
+                                    for (id, component) in entities {
+                                        storage.set(id, component);
+                                    }
+                                    continue;
+                                }
+                            )+
+                            return Err($crate::save::unknown_storage(name));
+                        }
+                        Ok(pool)
+                    }
+
+                    /// Like `save`, but writes every storage as a separate length-prefixed blob that
+                    /// `load_blobs` can deserialize in parallel
+                    #[allow(dead_code)]
+                    pub fn save_blobs<W: ::std::io::Write>(&self, mut writer: W) -> Result<(), $crate::save::SaveError> {
+                        let _span = $crate::__spawning_pool_span!("save_blobs", schema = Self::schema_hash());
+                        let header = $crate::save::SaveHeader {
+                            version: $crate::save::FORMAT_VERSION,
+                            schema: Self::schema_hash()
+                        };
+                        header.write(&mut writer)?;
+                        let pool = $crate::serde_json::to_vec(&self.to_value_without_storages()?)?;
+                        $crate::save::write_blob(&mut writer, "", &pool)?;
+                        $(
+                            let storage = $crate::serde_json::to_vec(&*self.$store_name)?;
+                            $crate::save::write_blob(&mut writer, stringify!($store_name), &storage)?;
+                        )+
+                        Ok(())
+                    }
+
+                    /// Reads a pool written by `save_blobs`, failing if it was saved with a different
+                    /// schema. With the `rayon` feature the storages are deserialized in parallel, so
+                    /// it's then only available when every storage is `Send`.
+                    #[allow(dead_code)]
+                    pub fn load_blobs<R: ::std::io::Read>(mut reader: R) -> Result<Self, $crate::save::SaveError>
+                        where $(for<'s> $storage: $crate::save::BlobStorage),+ {
+                        let _span = $crate::__spawning_pool_span!("load_blobs", schema = Self::schema_hash());
+                        let header = $crate::save::SaveHeader::read(&mut reader)?;
+                        if header.schema != Self::schema_hash() {
+                            return Err($crate::save::SaveError::SchemaMismatch {
+                                expected: Self::schema_hash(),
+                                found: header.schema
+                            });
+                        }
+                        let blobs = $crate::save::read_blobs(&mut reader)?;
+                        let mut pool = None;
+                        $(
+                            let mut $store_name: Option<&[u8]> = None;
+                        )+
+                        for (name, bytes) in &blobs {
+                            $(
+                                if name == stringify!($store_name) {
+                                    $store_name = Some(&bytes[..]);
+                                    continue;
+                                }
+                            )+
+                            if name.is_empty() {
+                                pool = Some(bytes);
+                                continue;
+                            }
+                            return Err($crate::save::unknown_storage(name));
+                        }
+                        let mut pool = match pool {
+                            Some(bytes) => Self::from_value_without_storages($crate::serde_json::from_slice(bytes)?)?,
+                            None => return Err($crate::save::SaveError::NotASave)
+                        };
+                        $(
+                            let mut $store_name: (Option<&[u8]>, Option<Result<$storage, $crate::serde_json::Error>>) = ($store_name, None);
+                        )+
+                        {
+                            let mut tasks: Vec<$crate::save::BlobTask<'_>> = vec![];
+                            $(
+                                let (bytes, ref mut slot) = $store_name;
+                                if let Some(bytes) = bytes {
+                                    tasks.push(Box::new(move || *slot = Some($crate::serde_json::from_slice(bytes))));
+                                }
+                            )+
+                            $crate::save::run_all(tasks);
+                        }
+                        $(
+                            if let (_, Some(storage)) = $store_name {
+                                pool.$store_name = ::std::sync::Arc::new(storage?);
+                            }
+                        )+
+                        Ok(pool)
+                    }
+
+                    /// The pool as JSON without its storages, with any preserved data
+                    fn to_value_without_storages(&self) -> Result<$crate::serde_json::Value, $crate::serde_json::Error> {
+                        let mut fields = $crate::serde_json::Map::new();
+                        fields.insert("next_id".to_string(), $crate::serde_json::to_value(&self.next_id)?);
+                        fields.insert("removed".to_string(), $crate::serde_json::to_value(&self.removed)?);
+                        fields.insert("destroyed".to_string(), $crate::serde_json::to_value(&self.destroyed)?);
+                        fields.insert("uuids".to_string(), $crate::serde_json::to_value(&self.uuids)?);
+                        fields.insert("layers".to_string(), $crate::serde_json::to_value(&self.layers)?);
+                        fields.insert("despawn_timers".to_string(), $crate::serde_json::to_value(&self.despawn_timers)?);
+                        fields.insert("generations".to_string(), $crate::serde_json::to_value(&self.generations)?);
+                        fields.insert("hierarchy".to_string(), $crate::serde_json::to_value(&self.hierarchy)?);
+                        fields.insert("relations".to_string(), $crate::serde_json::to_value(&self.relations)?);
+                        let mut value = $crate::serde_json::Value::Object(fields);
+                        self.preserved.merge_into(&mut value);
+                        Ok(value)
+                    }
+
+                    /// Pool from `to_value_without_storages` output, with empty storages
+                    fn from_value_without_storages(mut value: $crate::serde_json::Value) -> Result<Self, $crate::serde_json::Error> {
+                        if let $crate::serde_json::Value::Object(ref mut fields) = value {
+                            $(
+                                let empty: $storage = $crate::storage::Storage::new();
+                                fields.insert(stringify!($store_name).to_string(), $crate::serde_json::to_value(&empty)?);
+                            )+
+                        }
+                        $crate::serde_json::from_value(value)
+                    }
+
+                    /// Like `load`, with options to load files saved with other components
+                    #[allow(dead_code)]
+                    pub fn load_with<R: ::std::io::Read>(mut reader: R, options: &$crate::save::LoadOptions) -> Result<(Self, $crate::save::LoadReport), $crate::save::SaveError> {
+                        let _span = $crate::__spawning_pool_span!("load", schema = Self::schema_hash());
+                        let header = $crate::save::SaveHeader::read(&mut reader)?;
+                        let tolerant = options.skip_unknown || options.preserve_unknown;
+                        let renamed_schema = options.renames.original_schema_hash(Self::SCHEMA);
+                        if header.schema != Self::schema_hash() && header.schema != renamed_schema && !tolerant {
+                            return Err($crate::save::SaveError::SchemaMismatch {
+                                expected: Self::schema_hash(),
+                                found: header.schema
+                            });
+                        }
+                        let mut value: $crate::serde_json::Value = $crate::serde_json::from_reader(reader)?;
+                        let mut report = $crate::save::LoadReport::default();
+                        report.renamed = options.renames.apply(&mut value);
+                        let mut preserved = $crate::save::PreservedData::default();
+                        if tolerant {
+                            let known = ["next_id", "removed", "destroyed", "uuids", "layers", "despawn_timers", "generations", "hierarchy", "relations", $(stringify!($store_name)),+];
+                            let unknown = $crate::save::remove_unknown_fields(&mut value, &known);
+                            report.unknown = unknown.keys().cloned().collect();
+                            if options.preserve_unknown {
+                                preserved = $crate::save::PreservedData::new(unknown);
+                            }
+                            if let $crate::serde_json::Value::Object(ref mut fields) = value {
+                                $(
+                                    if !fields.contains_key(stringify!($store_name)) {
+                                        let empty: $storage = $crate::storage::Storage::new();
+                                        fields.insert(stringify!($store_name).to_string(), $crate::serde_json::to_value(&empty)?);
+                                        report.missing.push(stringify!($store_name));
+                                    }
+                                )+
+                            }
+                        }
+                        let mut pool: Self = $crate::serde_json::from_value(value)?;
+                        pool.preserved = preserved;
+                        Ok((pool, report))
+                    }
+
+                    /// Like `load`, but only deserializes the storages of the named components and
+                    /// leaves the others empty, for tools that need a subset of a save
+                    #[allow(dead_code)]
+                    pub fn load_partial<R: ::std::io::Read>(mut reader: R, components: &[&str]) -> Result<Self, $crate::save::SaveError> {
+                        let _span = $crate::__spawning_pool_span!("load_partial", schema = Self::schema_hash());
+                        if let Some(unknown) = components.iter().find(|name| !Self::SCHEMA.iter().any(|(component, _, _)| component == *name)) {
+                            return Err($crate::save::SaveError::UnknownComponent(unknown.to_string()));
+                        }
+                        let header = $crate::save::SaveHeader::read(&mut reader)?;
+                        if header.schema != Self::schema_hash() {
+                            return Err($crate::save::SaveError::SchemaMismatch {
+                                expected: Self::schema_hash(),
+                                found: header.schema
+                            });
+                        }
+                        let skipped: Vec<&str> = Self::SCHEMA.iter()
+                            .filter(|(component, _, _)| !components.contains(component))
+                            .map(|(_, store_name, _)| *store_name)
+                            .collect();
+                        let mut fields = $crate::save::read_fields(reader, |name| !skipped.contains(&name))?;
+                        $(
+                            if !fields.contains_key(stringify!($store_name)) {
+                                let empty: $storage = $crate::storage::Storage::new();
+                                fields.insert(stringify!($store_name).to_string(), $crate::serde_json::to_value(&empty)?);
+                            }
+                        )+
+                        Ok($crate::serde_json::from_value($crate::serde_json::Value::Object(fields))?)
+                    }
+
+                    /// Storages kept from the last `load_with` with `preserve_unknown`
+                    #[allow(dead_code)]
+                    pub fn preserved_data(&self) -> &$crate::save::PreservedData {
+                        &self.preserved
+                    }
+
+                    /// Writes every live component `T` as a CSV row, sorted by entity id, with the
+                    /// component's fields flattened into columns after an `entity_id` column
+                    #[allow(dead_code)]
+                    pub fn export_csv<T, W: ::std::io::Write>(&self, writer: W) -> ::std::io::Result<()> where Self: ComponentLoader<T> {
+                        let mut all = self.get_all::<T>();
+                        all.sort_by_key(|(id, _)| *id);
+                        let mut rows = Vec::with_capacity(all.len());
+                        for (id, component) in all {
+                            rows.push((id, <Self as ComponentLoader<T>>::to_value_overloaded(component)?));
+                        }
+                        $crate::export::write_csv(writer, &rows)
+                    }
+
+                    /// Moves every live entity of `other` into this pool under new IDs, with their layers,
+                    /// UUIDs, hierarchy and relations, and returns the map from their old IDs to the new
+                    /// ones. Components flagged `map_entities` have their entity references remapped.
+                    #[allow(dead_code)]
+                    pub fn merge(&mut self, mut other: Self) -> $crate::map::EntityMap {
+                        use $crate::map::MapEntities;
+
+                        let mut map = $crate::map::EntityMap::new();
+                        let ids: Vec<$crate::EntityId> = other.entities().collect();
+                        for id in ids {
+                            let new = self.spawn_entity();
+                            map.insert_weak(other.downgrade(id), self.downgrade(new));
+                            self.layers.set(new, other.layers.get(id));
+                            if let Some(uuid) = other.uuids.remove(id) {
+                                self.uuids.insert(new, uuid);
+                            }
+                        }
+                        for (old, new) in map.pairs() {
+                            self.move_components(&mut other, old, new, &map);
+                        }
+                        other.hierarchy.map_entities(&map);
+                        self.hierarchy.merge(::std::mem::take(&mut other.hierarchy));
+                        other.relations.map_entities(&map);
+                        self.relations.merge(::std::mem::take(&mut other.relations));
+                        map
+                    }
+
+                    /// Loads a save into this pool next to its own entities. Loaded entities keep their
+                    /// IDs, with their components, layers and UUIDs, and `policy` decides what happens
+                    /// to the ones whose ID is alive or pending removal here.
+                    #[allow(dead_code)]
+                    pub fn load_into<R: ::std::io::Read>(&mut self, reader: R, policy: $crate::save::CollisionPolicy) -> Result<$crate::save::CollisionReport, $crate::save::SaveError> {
+                        let mut other = Self::load(reader)?;
+                        let loaded: Vec<$crate::EntityId> = other.entities().collect();
+                        let (collisions, free): (Vec<_>, Vec<_>) = loaded.into_iter()
+                            .partition(|id| self.is_alive(*id) || self.removed.contains(id));
+                        if policy == $crate::save::CollisionPolicy::Error && !collisions.is_empty() {
+                            return Err($crate::save::SaveError::IdCollision(collisions));
+                        }
+                        let mut map = $crate::map::EntityMap::new();
+                        for id in free {
+                            self.claim_id(id);
+                            map.insert_weak(other.downgrade(id), self.downgrade(id));
+                        }
+                        let mut remapped = $crate::map::EntityMap::new();
+                        for &id in &collisions {
+                            if policy == $crate::save::CollisionPolicy::Remap {
+                                let new = self.spawn_entity();
+                                remapped.insert_weak(other.downgrade(id), self.downgrade(new));
+                                map.insert_weak(other.downgrade(id), self.downgrade(new));
+                            } else {
+                                self.queue_removal(id);
+                                self.removed.remove(&id);
+                                self.destroy_entities(vec![id]);
+                                self.claim_id(id);
+                                map.insert_weak(other.downgrade(id), self.downgrade(id));
+                            }
+                        }
+                        for (old, new) in map.pairs() {
+                            self.move_components(&mut other, old, new, &map);
+                            self.layers.set(new, other.layers.get(old));
+                            if let Some(uuid) = other.uuids.remove(old) {
+                                self.uuids.insert(new, uuid);
+                            }
+                        }
+                        Ok($crate::save::CollisionReport { collisions, remapped })
+                    }
+
+                    /// Makes an unused ID alive, IDs skipped on the way count as destroyed and are
+                    /// recorded as a single run, so a large jump costs nothing
+                    fn claim_id(&mut self, id: $crate::EntityId) {
+                        let next_id = self.next_id.get_mut();
+                        if id.to_raw() < *next_id {
+                            self.destroyed.remove(&id);
+                            return;
+                        }
+                        if let (Some(first), Some(last)) = ($crate::EntityId::from_raw(*next_id), $crate::EntityId::from_raw(id.to_raw() - 1)) {
+                            self.destroyed.insert_range(first, last);
+                        }
+                        *next_id = id.to_raw() + 1;
+                    }
+
+                    /// Moves the entity with its components, layers and UUID into `target`, and
+                    /// returns its ID there. References to the entity itself in components flagged
+                    /// `map_entities` are remapped, the entity is removed from this pool. Returns `None`
+                    /// and leaves both pools untouched unless the entity is alive.
+                    #[allow(dead_code)]
+                    pub fn transfer_entity(&mut self, id: $crate::EntityId, target: &mut Self) -> Option<$crate::EntityId> {
+                        if !self.is_alive(id) {
+                            return None;
+                        }
+                        let new = target.spawn_entity();
+                        let mut map = $crate::map::EntityMap::new();
+                        map.insert_weak(self.downgrade(id), target.downgrade(new));
+                        target.move_components(self, id, new, &map);
+                        target.layers.set(new, self.layers.get(id));
+                        if let Some(uuid) = self.uuids.remove(id) {
+                            target.uuids.insert(new, uuid);
+                        }
+                        self.remove_entity(id);
+                        Some(new)
+                    }
+
+                    /// Clones every component of `from`, dynamic ones included, onto `to`, replacing the
+                    /// components `to` already has. Does nothing unless both entities are alive.
+                    #[allow(dead_code)]
+                    pub fn copy_components(&mut self, from: $crate::EntityId, to: $crate::EntityId) {
+                        if from == to || !self.is_alive(from) || !self.is_alive(to) {
+                            return;
+                        }
+                        $(
+                            if let Some(component) = self.$store_name.get(from).cloned() {
+                                self.set(to, component);
+                            }
+                        )+
+                        self.dynamic.copy_entity(from, to);
+                    }
+
+                    /// Clones component `T` of `from` onto `to`, if `from` has one
+                    #[allow(dead_code)]
+                    pub fn copy_component<T: Clone>(&mut self, from: $crate::EntityId, to: $crate::EntityId) where Self: ComponentLoader<T> {
+                        if let Some(component) = self.get::<T>(from).cloned() {
+                            self.set(to, component);
+                        }
+                    }
+
+                    fn move_components(&mut self, from: &mut Self, old: $crate::EntityId, new: $crate::EntityId, map: &$crate::map::EntityMap) {
+                        let _ = map;
+                        $(
+                            if let Some(mut component) = ::std::sync::Arc::make_mut(&mut from.$store_name).take(old) {
+                                $crate::__spawning_pool_if_map_entities!([$($flag),*] {
+                                    $crate::map::MapEntities::map_entities(&mut component, map);
+                                } else {
+                                    let _ = &mut component;
+                                });
+                                self.set(new, component);
+                            }
+                        )+
+                    }
+
+                    /// Renumbers the live entities into `1..=n`, keeping their order, and returns the map
+                    /// from old to new IDs, so storages indexed by ID stop filling up with holes. Pending
+                    /// removals are cleaned up first. Components flagged `map_entities`, UUIDs, layers,
+                    /// hierarchy, relations, timers, live queries and interests follow their entities,
+                    /// while IDs and weak references held outside the pool have to be remapped by the
+                    /// caller. IDs that change occupant get a new generation, and replication peers are
+                    /// sent a reset.
+                    #[allow(dead_code)]
+                    pub fn compact_ids(&mut self) -> $crate::map::EntityMap {
+                        use $crate::map::MapEntities;
+
+                        self.cleanup_removed();
+                        let live: Vec<$crate::EntityId> = self.entities().collect();
+                        let renumbered: Vec<($crate::EntityId, $crate::EntityId)> = live.iter().enumerate()
+                            .map(|(index, old)| (*old, $crate::EntityId::from_raw((index + 1) as $crate::RawEntityId).expect("entity ids start at 1")))
+                            .collect();
+                        let mut generations = self.generations.clone();
+                        let reused: ::std::collections::BTreeSet<$crate::EntityId> = renumbered.iter()
+                            .filter(|(old, new)| old != new)
+                            .flat_map(|(old, new)| vec![*old, *new])
+                            .collect();
+                        for id in reused {
+                            generations.bump(id);
+                        }
+                        let mut map = $crate::map::EntityMap::new();
+                        for (old, new) in renumbered {
+                            map.insert_weak(self.downgrade(old), $crate::weak::EntityRefWeak::new(new, generations.get(new)));
+                        }
+                        for (old, new) in map.pairs() {
+                            if old == new {
+                                continue;
+                            }
+                            $(
+                                if self.$store_name.get(old).is_some() {
+                                    let storage = ::std::sync::Arc::make_mut(&mut self.$store_name);
+                                    if let Some(component) = storage.take(old) {
+                                        storage.set(new, component);
+                                    }
+                                }
+                            )+
+                            self.dynamic.copy_entity(old, new);
+                            self.dynamic.remove_entity(old);
+                        }
+                        *self.next_id.get_mut() = live.len() as $crate::RawEntityId + 1;
+                        self.destroyed = Default::default();
+                        self.generations = generations;
+                        self.map_entities(&map);
+                        self.uuids.map_entities(&map);
+                        self.layers.map_entities(&map);
+                        self.despawn_timers.map_entities(&map);
+                        self.hierarchy.map_entities(&map);
+                        self.relations.map_entities(&map);
+                        self.ticks.map_entities(&map);
+                        self.spawn_ticks.map_entities(&map);
+                        self.live.map_entities(&map);
+                        self.interests.map_entities(&map);
+                        self.replication.reset();
+                        for peer in self.replication.peers() {
+                            for id in live.iter().filter_map(|id| map.get(*id)) {
+                                self.mark_replicated_for(peer, id);
+                            }
+                        }
+                        map
+                    }
+
+                    /// Remaps the entity references of every component flagged `map_entities`, for
+                    /// when IDs were changed outside of `merge`
+                    #[allow(dead_code)]
+                    pub fn map_entities(&mut self, map: &$crate::map::EntityMap) {
+                        let _ = map;
+                        $(
+                            $crate::__spawning_pool_if_map_entities!([$($flag),*] {
+                                let ids: Vec<$crate::EntityId> = self.$store_name.get_all().iter().map(|(id, _)| *id).collect();
+                                for id in ids {
+                                    if let Some(component) = self.get_mut::<$component>(id) {
+                                        $crate::map::MapEntities::map_entities(component, map);
+                                    }
+                                }
+                            } else {});
+                        )+
+                    }
+
+                    /// Component counts, components per entity and pending removals, printable with
+                    /// `Display`
+                    #[allow(dead_code)]
+                    pub fn summary(&self) -> $crate::summary::WorldSummary {
+                        let ids = self.component_entity_ids();
+                        let mut summary = $crate::summary::WorldSummary {
+                            entities: ids.len(),
+                            components: vec![$((stringify!($component), self.count::<$component>())),+],
+                            pending_removals: self.removed.len(),
+                            ..Default::default()
+                        };
+                        summary.histogram = vec![0; summary.components.len() + 1];
+                        for id in ids {
+                            let count = self.component_names_of(id).len();
+                            summary.histogram[count] += 1;
+                            if summary.largest.map(|(_, most)| count > most).unwrap_or(true) {
+                                summary.largest = Some((id, count));
+                            }
+                        }
+                        summary
+                    }
+
+                    /// Live entities with components grouped by their component set, most common first.
+                    /// Limited to pools of at most 64 components, like `component_mask`.
+                    #[allow(dead_code)]
+                    pub fn archetype_report(&self) -> $crate::summary::ArchetypeReport {
+                        let masks = self.component_entity_ids().into_iter().map(|id| self.component_mask(id));
+                        $crate::summary::ArchetypeReport::from_masks($crate::inspect::Inspect::component_names(self), masks)
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn debug_entity(&self, id: $crate::EntityId) -> String {
+                        let mut out = format!("Entity {}", id);
+                        $(
+                            if let Some(c) = self.get::<$component>(id) {
+                                out.push_str(&format!("\n    {}: {:?}", stringify!($component), c));
+                            }
+                        )+
+                        out
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn to_dot<F>(&self, edges: F) -> String where F: Fn(&Self, $crate::EntityId) -> Vec<($crate::EntityId, String)> {
+                        let ids = self.component_entity_ids();
+                        let mut out = format!("digraph {} {{\n", stringify!($name));
+                        for id in &ids {
+                            let names = self.component_names_of(*id).join(", ");
+                            out.push_str(&format!("    {} [label=\"{}\\n{}\"];\n", id, id, names));
+                        }
+                        for id in &ids {
+                            for (target, label) in edges(self, *id) {
+                                if label.is_empty() {
+                                    out.push_str(&format!("    {} -> {};\n", id, target));
+                                } else {
+                                    out.push_str(&format!("    {} -> {} [label=\"{}\"];\n", id, target, label.replace('\\', "\\\\").replace('"', "\\\"")));
+                                }
+                            }
+                        }
+                        out.push_str("}\n");
+                        out
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn inspect_json(&self) -> $crate::serde_json::Value {
+                        let _span = $crate::__spawning_pool_span!("inspect_json", entities = self.component_entity_ids().len());
+                        let mut entities = $crate::serde_json::Map::new();
+                        for id in self.component_entity_ids() {
+                            let mut components = $crate::serde_json::Map::new();
+                            $(
+                                if let Some(c) = self.$store_name.get(id) {
+                                    let value = $crate::serde_json::to_value(c).unwrap_or($crate::serde_json::Value::Null);
+                                    components.insert(stringify!($component).to_string(), value);
+                                }
+                            )+
+                            entities.insert(id.to_string(), $crate::serde_json::Value::Object(components));
+                        }
+                        $crate::serde_json::Value::Object(entities)
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn get_value(&self, id: $crate::EntityId, name: &str) -> Option<$crate::serde_json::Value> {
+                        $crate::inspect::Inspect::component_value(self, id, name)
+                    }
+
+                    #[allow(dead_code)]
+                    pub fn set_value(&mut self, id: $crate::EntityId, name: &str, value: $crate::serde_json::Value) -> Result<(), $crate::inspect::ValueError> {
+                        $crate::inspect::Inspect::set_component_value(self, id, name, value)
+                    }
+
+                    #[allow(dead_code)]
+                    fn component_entity_ids(&self) -> Vec<$crate::EntityId> {
+                        let mut ids: Vec<$crate::EntityId> = vec![];
+                        $(
+                            ids.extend(self.$store_name.get_all().iter().map(|(id, _)| *id));
+                        )+
+                        ids.sort();
+                        ids.dedup();
+                        ids.retain(|id| !self.removed.contains(id));
+                        ids
+                    }
+
+                    #[allow(dead_code)]
+                    fn component_names_of(&self, id: $crate::EntityId) -> Vec<&'static str> {
+                        let mut names = vec![];
+                        $(
+                            if self.$store_name.get(id).is_some() {
+                                names.push(stringify!($component));
+                            }
+                        )+
+                        names
+                    }
+                }
+
+                impl Default for $name {
+                    fn default() -> Self {
+                        $name::new()
+                    }
+                }
+
+                impl $crate::inspect::Inspect for $name {
+                    fn component_names(&self) -> &'static [&'static str] {
+                        &[$(stringify!($component)),+]
+                    }
+
+                    fn entity_ids(&self) -> Vec<$crate::EntityId> {
+                        self.component_entity_ids()
+                    }
+
+                    fn component_value(&self, id: $crate::EntityId, name: &str) -> Option<$crate::serde_json::Value> {
+                        $(
+                            if name == stringify!($component) {
+                                return self.get::<$component>(id)
+                                    .and_then(|c| $crate::serde_json::to_value(c).ok());
+                            }
+                        )+
+                        None
+                    }
+
+                    fn set_component_value(&mut self, id: $crate::EntityId, name: &str, value: $crate::serde_json::Value) -> Result<(), $crate::inspect::ValueError> {
+                        $(
+                            if name == stringify!($component) {
+                                let component: $component = $crate::serde_json::from_value(value)?;
+                                self.set(id, component);
+                                return Ok(());
+                            }
+                        )+
+                        Err($crate::inspect::ValueError::UnknownComponent(name.to_string()))
+                    }
+
+                    fn remove_component(&mut self, id: $crate::EntityId, name: &str) -> Result<(), $crate::inspect::ValueError> {
+                        $(
+                            if name == stringify!($component) {
+                                self.remove::<$component>(id);
+                                return Ok(());
+                            }
+                        )+
+                        Err($crate::inspect::ValueError::UnknownComponent(name.to_string()))
+                    }
+                }
+
+                impl $crate::inspect::ErasedPool for $name {
+                    fn spawn_entity(&mut self) -> $crate::EntityId {
+                        $name::spawn_entity(self)
+                    }
+
+                    fn try_spawn_entity(&mut self) -> Result<$crate::EntityId, $crate::SpawnError> {
+                        $name::try_spawn_entity(self)
+                    }
+
+                    fn is_alive(&self, id: $crate::EntityId) -> bool {
+                        $name::is_alive(self, id)
+                    }
+
+                    fn live_entity_ids(&self) -> Vec<$crate::EntityId> {
+                        self.entities().collect()
+                    }
+
+                    fn remove_entity(&mut self, id: $crate::EntityId) {
+                        $name::remove_entity(self, id)
+                    }
+
+                    fn cleanup_removed(&mut self) -> Vec<$crate::cleanup::Destroyed> {
+                        $name::cleanup_removed(self)
+                    }
+
+                    fn to_json_bytes(&self) -> Result<Vec<u8>, $crate::serde_json::Error> {
+                        let _span = $crate::__spawning_pool_span!("serialize", entities = self.component_entity_ids().len());
+                        $crate::serde_json::to_vec(self)
+                    }
+                }
+
+                impl $crate::cache::CachedQueries for $name {
+                    fn query_cache(&self) -> &$crate::cache::QueryCache {
+                        &self.query_cache
+                    }
+
+                    fn structure(&self, index: usize) -> u64 {
+                        self.ticks.structure(index)
+                    }
+                }
+
+                impl $crate::pool::Pool for $name {
+                    fn spawn_entity(&mut self) -> $crate::EntityId {
+                        $name::spawn_entity(self)
+                    }
+
+                    fn remove_entity(&mut self, id: $crate::EntityId) {
+                        $name::remove_entity(self, id)
+                    }
+
+                    fn cleanup_removed(&mut self) -> Vec<$crate::cleanup::Destroyed> {
+                        $name::cleanup_removed(self)
+                    }
+
+                    fn is_alive(&self, id: $crate::EntityId) -> bool {
+                        $name::is_alive(self, id)
+                    }
+
+                    fn entity_count(&self) -> usize {
+                        $name::entity_count(self)
+                    }
+
+                    fn fork(&self) -> Self {
+                        $name::fork(self)
+                    }
+                }
+
+                pub trait ComponentLoader<T> {
+                    /// Position of the component in the macro invocation
+                    const INDEX: usize;
+                    type Storage;
+                    fn storage_overloaded(&self) -> &Self::Storage;
+                    fn storage_mut_overloaded(&mut self) -> &mut Self::Storage;
+                    fn storage_is_shared_overloaded(&self) -> bool;
+                    fn get_overloaded(&self, id: $crate::EntityId) -> Option<&T>;
+                    /// Like `get_overloaded`, without recording metrics
+                    fn lookup_overloaded(&self, id: $crate::EntityId) -> Option<&T>;
+                    fn get_all_into_overloaded<'a>(&'a self, out: &mut Vec<($crate::EntityId, &'a T)>);
+                    fn reserve_overloaded(&mut self, additional: usize);
+                    fn to_value_overloaded(component: &T) -> Result<$crate::serde_json::Value, $crate::serde_json::Error>;
+                    fn count_overloaded(&self) -> usize;
+                    fn get_mut_overloaded(&mut self, id: $crate::EntityId) -> Option<&mut T>;
+                    fn set_overloaded(&mut self, id: $crate::EntityId, component: T);
+                    fn remove_overloaded(&mut self, id: $crate::EntityId);
+                    fn drain_overloaded(&mut self) -> Vec<($crate::EntityId, T)>;
+                }
+
+                $(
+                impl $crate::bundle::SetComponent<$component> for $name {
+                    fn set_component(&mut self, id: $crate::EntityId, component: $component) {
+                        self.set(id, component);
+                    }
+                }
+
+                impl $crate::pool::HasComponent<$component> for $name {
+                    fn component(&self, id: $crate::EntityId) -> Option<&$component> {
+                        self.get(id)
+                    }
+                    fn component_mut(&mut self, id: $crate::EntityId) -> Option<&mut $component> {
+                        self.get_mut(id)
+                    }
+                    fn insert_component(&mut self, id: $crate::EntityId, component: $component) {
+                        self.set(id, component);
+                    }
+                    fn clear_component(&mut self, id: $crate::EntityId) {
+                        self.remove::<$component>(id);
+                    }
+                }
+
+                impl $crate::fetch::GetComponent<$component> for $name {
+                    const INDEX: usize = <Self as ComponentLoader<$component>>::INDEX;
+                    fn get_component(&self, id: $crate::EntityId) -> Option<&$component> {
+                        self.get(id)
+                    }
+                }
+
+                impl $crate::query::ComponentAccess<$component> for $name {
+                    const INDEX: usize = <Self as ComponentLoader<$component>>::INDEX;
+                    fn entities(pool: &Self) -> Vec<$crate::EntityId> {
+                        pool.get_all::<$component>().iter().map(|(id, _)| *id).collect()
+                    }
+                    unsafe fn read<'b>(pool: *const Self, id: $crate::EntityId) -> Option<&'b $component> {
+                        if (*pool).removed.contains(&id) {
+                            return None;
+                        }
+                        (*pool).metrics.record(<Self as ComponentLoader<$component>>::INDEX, $crate::metrics::Access::Get);
+                        (*pool).$store_name.get(id).map(|c| &*(c as *const $component))
+                    }
+                    unsafe fn write<'b>(pool: *mut Self, id: $crate::EntityId) -> Option<&'b mut $component> {
+                        if (*pool).removed.contains(&id) || (*pool).$store_name.get(id).is_none() {
+                            return None;
+                        }
+                        (*pool).metrics.record(<Self as ComponentLoader<$component>>::INDEX, $crate::metrics::Access::Get);
+                        if $crate::__spawning_pool_is_replicated!($($flag),*) {
+                            (*pool).replication.mark_dirty(id, stringify!($component));
+                        }
+                        (*pool).ticks.mark(<Self as ComponentLoader<$component>>::INDEX, id);
+                        ::std::sync::Arc::make_mut(&mut (*pool).$store_name).get_mut(id).map(|c| &mut *(c as *mut $component))
+                    }
+                }
+
+                impl ComponentLoader<$component> for $name {
+                    const INDEX: usize = __SpawningPoolComponent::$store_name as usize;
+                    type Storage = $storage;
+                    fn storage_overloaded(&self) -> &$storage {
+                        &self.$store_name
+                    }
+                    fn storage_mut_overloaded(&mut self) -> &mut $storage {
+                        ::std::sync::Arc::make_mut(&mut self.$store_name)
+                    }
+                    fn storage_is_shared_overloaded(&self) -> bool {
+                        ::std::sync::Arc::strong_count(&self.$store_name) > 1
+                    }
+                    fn get_overloaded(&self, id: $crate::EntityId) -> Option<&$component> {
+                        self.metrics.record(<Self as ComponentLoader<$component>>::INDEX, $crate::metrics::Access::Get);
+                        self.$store_name.get(id)
+                    }
+                    fn lookup_overloaded(&self, id: $crate::EntityId) -> Option<&$component> {
+                        self.$store_name.get(id)
+                    }
+                    fn get_all_into_overloaded<'a>(&'a self, out: &mut Vec<($crate::EntityId, &'a $component)>) {
+                        self.metrics.record(<Self as ComponentLoader<$component>>::INDEX, $crate::metrics::Access::Iterate);
+                        out.reserve(self.$store_name.len());
+                        self.$store_name.get_all_into(out);
+                    }
+                    fn reserve_overloaded(&mut self, additional: usize) {
+                        ::std::sync::Arc::make_mut(&mut self.$store_name).reserve(additional);
+                    }
+                    fn to_value_overloaded(component: &$component) -> Result<$crate::serde_json::Value, $crate::serde_json::Error> {
+                        $crate::serde_json::to_value(component)
+                    }
+                    fn count_overloaded(&self) -> usize {
+                        let pending = self.removed.iter().filter(|id| self.$store_name.get(*id).is_some()).count();
+                        self.$store_name.len() - pending
+                    }
+                    fn get_mut_overloaded(&mut self, id: $crate::EntityId) -> Option<&mut $component> {
+                        self.metrics.record(<Self as ComponentLoader<$component>>::INDEX, $crate::metrics::Access::Get);
+                        if self.$store_name.get(id).is_some() {
+                            if $crate::__spawning_pool_is_replicated!($($flag),*) {
+                                self.replication.mark_dirty(id, stringify!($component));
+                            }
+                            self.ticks.mark(<Self as ComponentLoader<$component>>::INDEX, id);
+                            ::std::sync::Arc::make_mut(&mut self.$store_name).get_mut(id)
+                        } else {
+                            None
+                        }
+                    }
+                    fn set_overloaded(&mut self, id: $crate::EntityId, component: $component) {
+                        self.metrics.record(<Self as ComponentLoader<$component>>::INDEX, $crate::metrics::Access::Set);
+                        if $crate::__spawning_pool_is_replicated!($($flag),*) {
+                            self.replication.mark_dirty(id, stringify!($component));
+                        }
+                        self.ticks.mark(<Self as ComponentLoader<$component>>::INDEX, id);
+                        self.ticks.touch(<Self as ComponentLoader<$component>>::INDEX);
+                        let storage = ::std::sync::Arc::make_mut(&mut self.$store_name);
+                        $crate::__spawning_pool_if_lifecycle!([$($flag),*] {
+                            let mut component = component;
+                            if let Some(old) = storage.take(id) {
+                                $crate::lifecycle::ComponentLifecycle::on_removed(old, id);
+                            }
+                            $crate::lifecycle::ComponentLifecycle::on_added(&mut component, id);
+                            storage.set(id, component);
+                        } else {
+                            storage.set(id, component);
+                        });
+                        self.refresh_live(id, Some(<Self as ComponentLoader<$component>>::INDEX));
+                        self.refresh_interest(id);
+                        self.observers.notify(<Self as ComponentLoader<$component>>::INDEX, $crate::changes::Change::Set(id));
+                    }
+                    fn remove_overloaded(&mut self, id: $crate::EntityId) {
+                        self.metrics.record(<Self as ComponentLoader<$component>>::INDEX, $crate::metrics::Access::Remove);
+                        if $crate::__spawning_pool_is_replicated!($($flag),*) {
+                            self.replication.mark_dirty(id, stringify!($component));
+                        }
+                        self.ticks.remove(<Self as ComponentLoader<$component>>::INDEX, id);
+                        if self.$store_name.get(id).is_some() {
+                            self.removal_tracker.record(<Self as ComponentLoader<$component>>::INDEX, id);
+                            self.ticks.touch(<Self as ComponentLoader<$component>>::INDEX);
+                            let storage = ::std::sync::Arc::make_mut(&mut self.$store_name);
+                            $crate::__spawning_pool_if_lifecycle!([$($flag),*] {
+                                if let Some(old) = storage.take(id) {
+                                    $crate::lifecycle::ComponentLifecycle::on_removed(old, id);
+                                }
+                            } else {
+                                storage.remove(id);
+                            });
+                            self.refresh_live(id, Some(<Self as ComponentLoader<$component>>::INDEX));
+                            self.refresh_interest(id);
+                            self.observers.notify(<Self as ComponentLoader<$component>>::INDEX, $crate::changes::Change::Removed(id));
+                        }
+                    }
+                    fn drain_overloaded(&mut self) -> Vec<($crate::EntityId, $component)> {
+                        self.metrics.record(<Self as ComponentLoader<$component>>::INDEX, $crate::metrics::Access::Remove);
+                        if self.$store_name.is_empty() {
+                            return vec![];
+                        }
+                        let storage = ::std::sync::Arc::make_mut(&mut self.$store_name);
+                        let removed = &self.removed;
+                        let (pending, mut drained): (Vec<_>, Vec<_>) = storage.drain().into_iter().partition(|(id, _)| removed.contains(id));
+                        for (id, component) in pending {
+                            storage.set(id, component);
+                        }
+                        drained.sort_by_key(|(id, _)| *id);
+                        self.ticks.touch(<Self as ComponentLoader<$component>>::INDEX);
+                        for (id, _) in &drained {
+                            let id = *id;
+                            if $crate::__spawning_pool_is_replicated!($($flag),*) {
+                                self.replication.mark_dirty(id, stringify!($component));
+                            }
+                            self.ticks.remove(<Self as ComponentLoader<$component>>::INDEX, id);
+                            self.removal_tracker.record(<Self as ComponentLoader<$component>>::INDEX, id);
+                            self.refresh_live(id, Some(<Self as ComponentLoader<$component>>::INDEX));
+                            self.refresh_interest(id);
+                            self.observers.notify(<Self as ComponentLoader<$component>>::INDEX, $crate::changes::Change::Removed(id));
+                        }
+                        drained
+                    }
+                }
+                )+
+            };
+    );
+    (
+        $(#[$pool_meta:meta])*
+        $(($component:ty, $store_name: ident, $($storage: tt)*)), +)
+        => (
+            $crate::create_spawning_pool!(
+                $(#[$pool_meta])*
+                pub SpawningPool;
+                $(($component, $store_name, $($storage)*)),+
+            );
+    );
+    (
+        // optional attributes for the pool struct, like `#[serde(bound = "...")]`
+        $(#[$pool_meta:meta])*
+        // optional visibility and name of the pool struct, `pub SpawningPool` by default
+        $vis:vis $name:ident;
+        $((
+        // component type
+        $component:ty,
+        // internal storage container name
+        $store_name: ident,
+        // storage type implementing storage::Storage, a path that gets the component as its
+        // only parameter or a path with every generic parameter given
+        $($storage: ident)::+ $(<$($storage_arg: ty),*>)?
+        // optional flags: `replicated`, `lifecycle`, `map_entities`, `default`, `accessors`
+        $(, $flag: ident)*
+        )), +)
+        => (
+            $crate::create_spawning_pool!(
+                @pool [$(#[$pool_meta])*] $vis $name;
+                $((
+                    $component,
+                    $store_name,
+                    [$crate::__spawning_pool_storage!($component; $($storage)::+ $(<$($storage_arg),*>)?)],
+                    stringify!($($storage)::+ $(<$($storage_arg),*>)?)
+                    $(, $flag)*
+                )),+
+            );
+    );
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spawning_pool_storage {
+    ($component:ty; $($storage:ident)::+) => { $($storage)::+<$component> };
+    ($component:ty; $($storage:ident)::+ <$($arg:ty),*>) => { $($storage)::+<$($arg),*> };
+}
+
+///
+/// Struct-like front-end of `create_spawning_pool!`, declaring one field per component with its
+/// storage and flags in a `#[component(...)]` attribute
+///
+/// Attributes and doc comments on the struct are kept, doc comments on fields are allowed but
+/// dropped. The struct has the same API as one made by `create_spawning_pool!`.
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// # #[macro_use] extern crate spawning_pool;
+/// # fn main() {
+/// use spawning_pool::storage::{HashMapStorage, VectorStorage};
+///
+/// #[derive(Clone, Debug, Serialize, Deserialize)]
+/// struct Pos(i32, i32);
+/// #[derive(Clone, Debug, Serialize, Deserialize)]
+/// struct Health(u32);
+///
+/// spawning_pool! {
+///     /// Everything in the level
+///     pub struct World {
+///         #[component(storage = VectorStorage)]
+///         pos: Pos,
+///         /// Only living things have health
+///         #[component(storage = HashMapStorage, replicated)]
+///         health: Health,
+///     }
+/// }
+///
+/// let mut world = World::new();
+/// let id = world.spawn_entity();
+/// world.set(id, Health(3));
+/// assert_eq!(world.get::<Health>(id).unwrap().0, 3);
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! spawning_pool {
+    (
+        $(#[$pool_meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[doc = $doc:expr])*
+                #[component(storage = $($storage:ident)::+ $(<$($storage_arg:ty),*>)? $(, $flag:ident)*)]
+                $store_name:ident : $component:ty
+            ),+ $(,)?
+        }
+    ) => {
+        $crate::create_spawning_pool!(
+            $(#[$pool_meta])*
+            $vis $name;
+            $(($component, $store_name, $($storage)::+ $(<$($storage_arg),*>)? $(, $flag)*)),+
+        );
+    };
+}
+
+///
+/// Spawns an entity in the pool with all the listed components attached, evaluates to its id
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// # #[macro_use] extern crate spawning_pool;
+/// # fn main() {
+/// # use spawning_pool::EntityId;
+/// # use spawning_pool::storage::{Storage, VectorStorage};
+/// # #[derive(Clone, Debug, Serialize, Deserialize)]
+/// # struct Pos { x: i32, y: i32 }
+/// # #[derive(Clone, Debug, Serialize, Deserialize)]
+/// # struct Vel { x: i32, y: i32 }
+/// # create_spawning_pool!((Pos, pos, VectorStorage), (Vel, vel, VectorStorage));
+/// # let mut pool = SpawningPool::new();
+/// let id = spawn_with!(pool, Pos{x: 1, y: 2}, Vel{x: 0, y: 1});
+/// # assert!(pool.get::<Vel>(id).is_some());
+/// # }
+/// ```
+///
+#[macro_export]
+macro_rules! spawn_with {
+    ($pool:expr, $($component:expr),+ $(,)*) => {{
+        let pool = &mut $pool;
+        let id = pool.spawn_entity();
+        $(
+            pool.set(id, $component);
+        )+
+        id
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "tracing")]
+macro_rules! __spawning_pool_span {
+    ($name:expr, $($fields:tt)*) => {
+        $crate::tracing::debug_span!(target: "spawning_pool", $name, $($fields)*).entered()
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "tracing"))]
+macro_rules! __spawning_pool_span {
+    ($($args:tt)*) => { () };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "tracing")]
+macro_rules! __spawning_pool_event {
+    ($($args:tt)*) => {
+        $crate::tracing::trace!(target: "spawning_pool", $($args)*)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "tracing"))]
+macro_rules! __spawning_pool_event {
+    ($($args:tt)*) => { () };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spawning_pool_accessors {
+    ([] $store_name:ident, $component:ty) => {};
+    ([accessors $(, $rest:ident)*] $store_name:ident, $component:ty) => {
+        $crate::paste::paste! {
+            /// The entity's component, like `get`
+            #[allow(dead_code)]
+            pub fn $store_name(&self, id: $crate::EntityId) -> Option<&$component> {
+                self.get::<$component>(id)
+            }
+
+            /// Like `get_mut`
+            #[allow(dead_code)]
+            pub fn [<$store_name _mut>](&mut self, id: $crate::EntityId) -> Option<&mut $component> {
+                self.get_mut::<$component>(id)
+            }
+
+            /// Like `set`
+            #[allow(dead_code)]
+            pub fn [<set_ $store_name>](&mut self, id: $crate::EntityId, component: $component) {
+                self.set::<$component>(id, component);
+            }
+        }
+    };
+    ([$other:ident $(, $rest:ident)*] $store_name:ident, $component:ty) => {
+        $crate::__spawning_pool_accessors!([$($rest),*] $store_name, $component);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "strict")]
+macro_rules! __spawning_pool_if_strict {
+    ($($tokens:tt)*) => { $($tokens)* };
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "strict"))]
+macro_rules! __spawning_pool_if_strict {
+    ($($tokens:tt)*) => {};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spawning_pool_check_flag {
+    (replicated) => {};
+    (lifecycle) => {};
+    (map_entities) => {};
+    (default) => {};
+    (accessors) => {};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spawning_pool_if_default {
+    ([] $then:block else $otherwise:block) => { $otherwise };
+    ([default $(, $rest:ident)*] $then:block else $otherwise:block) => { $then };
+    ([$other:ident $(, $rest:ident)*] $then:block else $otherwise:block) => {
+        $crate::__spawning_pool_if_default!([$($rest),*] $then else $otherwise)
+    };
+}
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::{EntityId};
+    use storage::*;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Position {
+        pub x: i32,
+        pub y: i32
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Velocity {
+        pub x: i32,
+        pub y: i32
+    }
+
+
+    #[test]
+    fn create_entity() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        assert_eq!(pool.spawn_entity().to_raw(), 1);
+        assert_eq!(pool.spawn_entity().to_raw(), 2);
+    }
+
+    #[test]
+    fn test_set() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        assert!(pool.get::<Position>(id).is_none());
+
+        pool.set(id, Velocity{x: 1, y: 2});
+
+        match pool.get::<Velocity>(id) {
+            Some(vel) => {
+                assert_eq!(vel.x, 1);
+                assert_eq!(vel.y, 2);
+            }
+            None => assert!(false)
+        }
+
+        assert_eq!(pool.get_all::<Velocity>().len(), 1);
+    }
+
+    #[test]
+    fn test_remove_entity() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+
+        pool.set(id, Velocity{x: 1, y: 2});
+
+        match pool.get::<Velocity>(id) {
+            Some(vel) => {
+                assert_eq!(vel.x, 1);
+                assert_eq!(vel.y, 2);
+            }
+            None => assert!(false)
+        }
+
+        pool.remove_entity(id);
+
+        assert!(pool.get::<Velocity>(id).is_none());
+    }
+
+    #[test]
+    fn test_force_get() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+
+        pool.set(id, Velocity{x: 1, y: 2});
+
+        match pool.get::<Velocity>(id) {
+            Some(vel) => {
+                assert_eq!(vel.x, 1);
+                assert_eq!(vel.y, 2);
+            }
+            None => assert!(false)
+        }
+
+        pool.remove_entity(id);
+
+        assert!(pool.get::<Velocity>(id).is_none());
+        assert!(pool.force_get::<Velocity>(id).is_some());
+        pool.cleanup_removed();
+        assert!(pool.force_get::<Velocity>(id).is_none());
+    }
+
+    #[test]
+    fn test_get_mut() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        assert!(pool.get::<Position>(id).is_none());
+
+        pool.set(id, Velocity{x: 1, y: 2});
+
+        match pool.get::<Velocity>(id) {
+            Some(vel) => {
+                assert_eq!(vel.x, 1);
+                assert_eq!(vel.y, 2);
+            }
+            None => assert!(false)
+        }
+
+        match pool.get_mut::<Velocity>(id) {
+            Some(vel) => {
+                vel.x = 3;
+                vel.y = 4;
+            }
+            None => assert!(false)
+        }
+
+        match pool.get::<Velocity>(id) {
+            Some(vel) => {
+                assert_eq!(vel.x, 3);
+                assert_eq!(vel.y, 4);
+            }
+            None => assert!(false)
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        assert!(pool.get::<Position>(id).is_none());
+
+        pool.set(id, Velocity{x: 1, y: 2});
+
+        match pool.get::<Velocity>(id) {
+            Some(vel) => {
+                assert_eq!(vel.x, 1);
+                assert_eq!(vel.y, 2);
+            }
+            None => assert!(false)
+        }
+
+        pool.remove::<Velocity>(id);
+
+       assert!( pool.get::<Velocity>(id).is_none());
+    }
+
+    #[test]
+    fn test_get_mut_vector_storage() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        assert!(pool.get::<Position>(id).is_none());
+
+        pool.set(id, Velocity{x: 1, y: 2});
+
+        match pool.get::<Velocity>(id) {
+            Some(vel) => {
+                assert_eq!(vel.x, 1);
+                assert_eq!(vel.y, 2);
+            }
+            None => assert!(false)
+        }
+
+        match pool.get_mut::<Velocity>(id) {
+            Some(vel) => {
+                vel.x = 3;
+                vel.y = 4;
+            }
+            None => assert!(false)
+        }
+
+        match pool.get::<Velocity>(id) {
+            Some(vel) => {
+                assert_eq!(vel.x, 3);
+                assert_eq!(vel.y, 4);
+            }
             None => assert!(false)
         }
+    }
+
+    #[test]
+    fn test_debug_entity() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        pool.set(id, Position{x: 1, y: 2});
+        pool.set(id, Velocity{x: 3, y: 4});
+
+        assert_eq!(
+            pool.debug_entity(id),
+            "Entity 1\n    Position: Position { x: 1, y: 2 }\n    Velocity: Velocity { x: 3, y: 4 }"
+        );
+
+        pool.remove_entity(id);
+        assert_eq!(pool.debug_entity(id), "Entity 1");
+    }
+
+    #[test]
+    fn test_to_dot() {
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Parent(EntityId);
+
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Parent, parent, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let root = pool.spawn_entity();
+        let child = pool.spawn_entity();
+        pool.set(root, Position{x: 0, y: 0});
+        pool.set(child, Parent(root));
+
+        let dot = pool.to_dot(|pool, id| {
+            match pool.get::<Parent>(id) {
+                Some(parent) => vec![(parent.0, "parent".to_string())],
+                None => vec![]
+            }
+        });
+        assert_eq!(
+            dot,
+            "digraph SpawningPool {\n    1 [label=\"1\\nPosition\"];\n    2 [label=\"2\\nParent\"];\n    2 -> 1 [label=\"parent\"];\n}\n"
+        );
+
+        let dot = pool.to_dot(|_, id| if id == child { vec![(root, "\"C:\\root\"".to_string())] } else { vec![] });
+        assert!(dot.contains("    2 -> 1 [label=\"\\\"C:\\\\root\\\"\"];\n"));
+    }
+
+    #[test]
+    fn test_inspect_json() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        let b = pool.spawn_entity();
+        let c = pool.spawn_entity();
+        pool.set(a, Position{x: 1, y: 2});
+        pool.set(a, Velocity{x: 3, y: 4});
+        pool.set(b, Velocity{x: 5, y: 6});
+        pool.set(c, Velocity{x: 7, y: 8});
+        pool.remove_entity(c);
+
+        let json = pool.inspect_json();
+        assert_eq!(json["1"]["Position"]["x"], 1);
+        assert_eq!(json["1"]["Velocity"]["y"], 4);
+        assert_eq!(json["2"]["Velocity"]["x"], 5);
+        assert!(json["2"].get("Position").is_none());
+        assert!(json.get("3").is_none());
+    }
+
+    #[test]
+    fn test_inspect_trait() {
+        use inspect::{Inspect, ValueError};
+
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        pool.set(id, Position{x: 1, y: 2});
+
+        assert_eq!(pool.component_names(), &["Position", "Velocity"]);
+        assert_eq!(pool.entity_ids(), vec![id]);
+        assert_eq!(pool.component_value(id, "Position").unwrap()["x"], 1);
+        assert!(pool.component_value(id, "Velocity").is_none());
+
+        let value = pool.component_value(id, "Position").unwrap();
+        pool.set_component_value(id, "Velocity", value).unwrap();
+        assert_eq!(pool.get::<Velocity>(id).unwrap().y, 2);
+
+        match pool.set_component_value(id, "Health", ::serde_json::Value::Null) {
+            Err(ValueError::UnknownComponent(name)) => assert_eq!(name, "Health"),
+            _ => panic!()
+        }
+        assert!(pool.set_component_value(id, "Position", ::serde_json::Value::Null).is_err());
+    }
+
+    #[test]
+    fn test_get_set_value() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        assert!(pool.get_value(id, "Position").is_none());
+
+        let mut value = ::serde_json::Map::new();
+        value.insert("x".to_string(), 4.into());
+        value.insert("y".to_string(), 2.into());
+        pool.set_value(id, "Position", ::serde_json::Value::Object(value)).unwrap();
+
+        assert_eq!(pool.get::<Position>(id).unwrap().x, 4);
+        assert_eq!(pool.get_value(id, "Position").unwrap()["y"], 2);
+        assert!(pool.get_value(id, "Health").is_none());
+        assert!(pool.set_value(id, "Health", ::serde_json::Value::Null).is_err());
+
+        pool.remove_entity(id);
+        assert!(pool.get_value(id, "Position").is_none());
+    }
+
+    #[test]
+    fn test_entity_id_width() {
+        #[cfg(not(feature = "u32-ids"))]
+        assert_eq!(::std::mem::size_of::<EntityId>(), 8);
+        #[cfg(feature = "u32-ids")]
+        assert_eq!(::std::mem::size_of::<EntityId>(), 4);
+        assert_eq!(::std::mem::size_of::<Option<EntityId>>(), ::std::mem::size_of::<EntityId>());
+    }
+
+    #[test]
+    fn test_entity_id_raw() {
+        assert!(EntityId::from_raw(0).is_none());
+        let id = EntityId::from_raw(42).unwrap();
+        assert_eq!(id.to_raw(), 42);
+        assert_eq!(id.to_string(), "42");
+    }
+
+    #[test]
+    fn test_serialize_roundtrip() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        pool.set(id, Position{x: 1, y: 2});
+        pool.set(id, Velocity{x: 3, y: 4});
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let mut loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get::<Position>(id).unwrap().y, 2);
+        assert_eq!(loaded.get::<Velocity>(id).unwrap().x, 3);
+        assert_eq!(loaded.spawn_entity().to_raw(), 2);
+    }
+
+    #[test]
+    fn test_uuids() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        let b = pool.spawn_entity();
+        assert!(pool.uuid_of(a).is_none());
+
+        let uuid = pool.ensure_uuid(a).unwrap();
+        assert_eq!(pool.ensure_uuid(a), Some(uuid));
+        assert_eq!(pool.uuid_of(a), Some(uuid));
+        assert_eq!(pool.entity_by_uuid(&uuid), Some(a));
+
+        pool.set_uuid(b, uuid);
+        assert!(pool.uuid_of(a).is_none());
+        assert_eq!(pool.entity_by_uuid(&uuid), Some(b));
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.entity_by_uuid(&uuid), Some(b));
+
+        pool.remove_entity(b);
+        assert!(pool.entity_by_uuid(&uuid).is_none());
+        assert!(pool.ensure_uuid(b).is_none());
+        pool.cleanup_removed();
+        assert!(pool.ensure_uuid(b).is_none());
+        assert!(pool.force_get::<Position>(b).is_none());
+        assert!(pool.uuids.is_empty());
+    }
+
+    #[test]
+    fn test_replication() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage, replicated),
+            (Velocity, vel, VectorStorage)
+        );
+        let mut server = SpawningPool::new();
+        let mut client = SpawningPool::new();
+        let a = server.spawn_entity();
+        server.set(a, Position{x: 1, y: 2});
+        server.set(a, Velocity{x: 3, y: 4});
+
+        server.add_peer(7);
+        let packet = server.collect_updates(7);
+        assert_eq!(packet.updates.len(), 1);
+        client.apply_updates(&packet).unwrap();
+        assert_eq!(client.get::<Position>(a).unwrap().x, 1);
+        assert!(client.get::<Velocity>(a).is_none());
+        assert!(server.collect_updates(7).is_empty());
+
+        server.get_mut::<Position>(a).unwrap().x = 5;
+        server.get_mut::<Velocity>(a).unwrap().x = 6;
+        let b = server.spawn_entity();
+        server.set(b, Position{x: 8, y: 9});
+        client.apply_updates(&server.collect_updates(7)).unwrap();
+        assert_eq!(client.get::<Position>(a).unwrap().x, 5);
+        assert_eq!(client.get::<Position>(b).unwrap().y, 9);
+        assert!(client.spawn_entity() > b);
+
+        let c = server.spawn_entity();
+        assert!(server.get_mut::<Position>(c).is_none());
+        assert!(server.collect_updates(7).is_empty());
+
+        server.remove::<Position>(b);
+        server.remove_entity(a);
+        let packet = server.collect_updates(7);
+        assert_eq!(packet.despawned, vec![a]);
+        client.apply_updates(&packet).unwrap();
+        assert!(client.get::<Position>(a).is_none());
+        assert!(client.get::<Position>(b).is_none());
+    }
+
+    #[test]
+    fn test_spatial_grid_storage() {
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Spot {
+            pub x: f32,
+            pub y: f32
+        }
+
+        impl HasPosition for Spot {
+            fn position(&self) -> (f32, f32) {
+                (self.x, self.y)
+            }
+        }
+
+        create_spawning_pool!(
+            (Spot, spot, SpatialGridStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        let b = pool.spawn_entity();
+        let c = pool.spawn_entity();
+        pool.set(a, Spot{x: 1.0, y: 1.0});
+        pool.set(b, Spot{x: 20.0, y: 5.0});
+        pool.set(c, Spot{x: -40.0, y: 100.0});
+
+        assert_eq!(pool.entities_in_rect::<Spot>((0.0, 0.0), (30.0, 30.0)), vec![a, b]);
+        assert_eq!(pool.entities_near::<Spot>((0.0, 0.0), 2.0), vec![a]);
+
+        pool.get_mut::<Spot>(a).unwrap().x = -39.0;
+        assert_eq!(pool.entities_near::<Spot>((-40.0, 100.0), 1.0), vec![c]);
+        pool.get_mut::<Spot>(a).unwrap().y = 99.0;
+        assert_eq!(pool.entities_near::<Spot>((-40.0, 100.0), 2.0), vec![a, c]);
+        assert_eq!(pool.entities_in_rect::<Spot>((0.0, 0.0), (30.0, 30.0)), vec![b]);
+
+        pool.remove_entity(c);
+        assert_eq!(pool.entities_near::<Spot>((-40.0, 100.0), 2.0), vec![a]);
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.entities_in_rect::<Spot>((-50.0, 90.0), (0.0, 110.0)), vec![a]);
+    }
+
+    #[test]
+    fn test_indexed_storage() {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        enum Faction {
+            Orcs,
+            Elves
+        }
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Member {
+            pub faction: Faction
+        }
+
+        impl IndexKey for Member {
+            type Key = Faction;
+            fn index_key(&self) -> Faction {
+                self.faction
+            }
+        }
+
+        create_spawning_pool!(
+            (Member, member, IndexedStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        let b = pool.spawn_entity();
+        let c = pool.spawn_entity();
+        pool.set(a, Member{faction: Faction::Orcs});
+        pool.set(b, Member{faction: Faction::Elves});
+        pool.set(c, Member{faction: Faction::Orcs});
+
+        assert_eq!(pool.find_by::<Member, _>(&Faction::Orcs), vec![a, c]);
+        assert_eq!(pool.find_by::<Member, _>(&Faction::Elves), vec![b]);
+
+        pool.get_mut::<Member>(a).unwrap().faction = Faction::Elves;
+        assert_eq!(pool.find_by::<Member, _>(&Faction::Elves), vec![a, b]);
+        pool.remove::<Member>(b);
+        assert_eq!(pool.find_by::<Member, _>(&Faction::Elves), vec![a]);
+        pool.remove_entity(c);
+        assert!(pool.find_by::<Member, _>(&Faction::Orcs).is_empty());
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.find_by::<Member, _>(&Faction::Elves), vec![a]);
+    }
+
+    #[test]
+    fn test_reserve_entity() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let first = pool.spawn_entity();
+
+        let shared = &pool;
+        let reserved: Vec<EntityId> = ::std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4).map(|_| scope.spawn(move || shared.reserve_entity())).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let mut unique = reserved.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 4);
+        assert!(reserved.iter().all(|id| *id > first));
+
+        let next = pool.spawn_entity();
+        assert!(reserved.iter().all(|id| *id < next));
+        pool.set(reserved[0], Position{x: 1, y: 2});
+        assert_eq!(pool.get::<Position>(reserved[0]).unwrap().x, 1);
+    }
+
+    #[test]
+    fn test_split() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        let b = pool.spawn_entity();
+        pool.set(a, Position{x: 0, y: 0});
+        pool.set(a, Velocity{x: 1, y: 2});
+        pool.set(b, Position{x: 5, y: 5});
+
+        {
+            let (positions, velocities) = pool.split();
+            for (id, vel) in velocities.get_all() {
+                if let Some(pos) = positions.get_mut(id) {
+                    pos.x += vel.x;
+                    pos.y += vel.y;
+                }
+            }
+        }
+        assert_eq!(pool.get::<Position>(a).unwrap().y, 2);
+        assert_eq!(pool.get::<Position>(b).unwrap().y, 5);
+    }
+
+    #[test]
+    fn test_fork() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        pool.set(a, Position{x: 1, y: 1});
+        pool.set(a, Velocity{x: 2, y: 2});
+
+        let mut fork = pool.fork();
+        assert!(::std::sync::Arc::ptr_eq(&pool.pos, &fork.pos));
+
+        fork.get_mut::<Position>(a).unwrap().x = 10;
+        let b = fork.spawn_entity();
+        fork.set(b, Position{x: 3, y: 3});
+
+        assert!(!::std::sync::Arc::ptr_eq(&pool.pos, &fork.pos));
+        assert!(::std::sync::Arc::ptr_eq(&pool.vel, &fork.vel));
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 1);
+        assert_eq!(fork.get::<Position>(a).unwrap().x, 10);
+        assert!(pool.get::<Position>(b).is_none());
+        assert_eq!(pool.spawn_entity(), b);
+
+        fork.remove_entity(a);
+        fork.cleanup_removed();
+        assert!(pool.get::<Velocity>(a).is_some());
+    }
+
+    #[test]
+    fn test_diff() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut a = SpawningPool::new();
+        let e1 = a.spawn_entity();
+        let e2 = a.spawn_entity();
+        a.set(e1, Position{x: 1, y: 1});
+        a.set(e2, Velocity{x: 2, y: 2});
+
+        let mut b = a.fork();
+        assert!(SpawningPool::diff(&a, &b).is_empty());
+
+        b.get_mut::<Position>(e1).unwrap().x = 5;
+        b.set(e1, Velocity{x: 0, y: 0});
+        b.remove_entity(e2);
+        let e3 = b.spawn_entity();
+        b.set(e3, Position{x: 3, y: 3});
+
+        let diff = SpawningPool::diff(&a, &b);
+        assert_eq!(diff.added_entities, vec![e3]);
+        assert_eq!(diff.removed_entities, vec![e2]);
+        assert_eq!(diff.changes.len(), 4);
+        assert_eq!(format!("{:?}", diff), "PoolDiff {
+    + entity 3
+    - entity 2
+    ~ 1.Position: {\"x\":1,\"y\":1} -> {\"x\":5,\"y\":1}
+    + 1.Velocity = {\"x\":0,\"y\":0}
+    - 2.Velocity = {\"x\":2,\"y\":2}
+    + 3.Position = {\"x\":3,\"y\":3}
+}");
+    }
+
+    #[test]
+    fn test_state_hash() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut a = SpawningPool::new();
+        let mut b = SpawningPool::new();
+        let ids: Vec<EntityId> = (0..20).map(|_| a.spawn_entity()).collect();
+        for id in &ids {
+            b.spawn_entity();
+            a.set(*id, Position{x: id.to_raw() as i32, y: 0});
+        }
+        for id in ids.iter().rev() {
+            b.set(*id, Position{x: id.to_raw() as i32, y: 0});
+        }
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        b.get_mut::<Position>(ids[3]).unwrap().y = 1;
+        assert!(a.state_hash() != b.state_hash());
+        b.get_mut::<Position>(ids[3]).unwrap().y = 0;
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        a.remove_entity(ids[0]);
+        assert!(a.state_hash() != b.state_hash());
+        a.cleanup_removed();
+        b.remove::<Position>(ids[0]);
+        assert_eq!(a.state_hash(), b.state_hash());
+    }
+
+    #[test]
+    fn test_metrics() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        pool.set(id, Position{x: 1, y: 1});
+        assert_eq!(pool.metrics().component("Position"), None);
+
+        pool.enable_metrics();
+        pool.set(id, Position{x: 1, y: 1});
+        pool.set(id, Velocity{x: 1, y: 1});
+        pool.get::<Position>(id);
+        pool.get_mut::<Position>(id);
+        pool.get_all::<Velocity>();
+        pool.remove::<Velocity>(id);
+        pool.remove_entity(id);
+        pool.cleanup_removed();
+
+        let metrics = pool.metrics();
+        assert_eq!(metrics.component("Position"), Some(&::metrics::ComponentMetrics {
+            gets: 2,
+            sets: 1,
+            removes: 0,
+            iterations: 0
+        }));
+        assert_eq!(metrics.component("Velocity"), Some(&::metrics::ComponentMetrics {
+            gets: 0,
+            sets: 1,
+            removes: 1,
+            iterations: 1
+        }));
+        assert_eq!(metrics.cleanups, 1);
+
+        pool.reset_metrics();
+        assert_eq!(pool.metrics().component("Position").unwrap().gets, 0);
+        assert_eq!(pool.metrics().cleanups, 0);
+    }
+
+    #[test]
+    fn test_component_lifecycle() {
+        use lifecycle::ComponentLifecycle;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static ADDED: AtomicUsize = AtomicUsize::new(0);
+        static REMOVED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Sound {
+            pub handle: Option<usize>
+        }
+
+        impl ComponentLifecycle for Sound {
+            fn on_added(&mut self, _id: EntityId) {
+                self.handle = Some(ADDED.fetch_add(1, Ordering::SeqCst));
+            }
+
+            fn on_removed(self, _id: EntityId) {
+                assert!(self.handle.is_some());
+                REMOVED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        create_spawning_pool!(
+            (Sound, sound, HashMapStorage, lifecycle),
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        let b = pool.spawn_entity();
+        pool.set(a, Sound{handle: None});
+        pool.set(b, Sound{handle: None});
+        pool.set(b, Position{x: 0, y: 0});
+        assert_eq!(pool.get::<Sound>(a).unwrap().handle, Some(0));
+        assert_eq!(ADDED.load(Ordering::SeqCst), 2);
+
+        pool.set(a, Sound{handle: None});
+        assert_eq!(REMOVED.load(Ordering::SeqCst), 1);
+        pool.remove::<Sound>(a);
+        assert_eq!(REMOVED.load(Ordering::SeqCst), 2);
+
+        pool.remove_entity(b);
+        assert_eq!(REMOVED.load(Ordering::SeqCst), 2);
+        pool.cleanup_removed();
+        assert_eq!(REMOVED.load(Ordering::SeqCst), 3);
+        assert_eq!(ADDED.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_set_bundle() {
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Health(u32);
+
+        bundle! {
+            #[derive(Clone)]
+            struct Mover {
+                pub pos: Position,
+                pub vel: Velocity
+            }
+        }
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage),
+            (Health, health, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        pool.set_bundle(a, (Position{x: 1, y: 2}, Velocity{x: 3, y: 4}, Health(5)));
+        assert_eq!(pool.get::<Position>(a).unwrap().y, 2);
+        assert_eq!(pool.get::<Velocity>(a).unwrap().x, 3);
+        assert_eq!(pool.get::<Health>(a).unwrap().0, 5);
+
+        let b = pool.spawn_entity();
+        pool.set_bundle(b, Mover{pos: Position{x: 6, y: 7}, vel: Velocity{x: 8, y: 9}});
+        assert_eq!(pool.get::<Position>(b).unwrap().x, 6);
+        assert_eq!(pool.get::<Velocity>(b).unwrap().y, 9);
+        assert!(pool.get::<Health>(b).is_none());
+
+        pool.remove_entity(b);
+        #[cfg(not(feature = "strict"))]
+        {
+            pool.set_bundle(b, (Health(1),));
+            assert!(pool.force_get::<Health>(b).is_none());
+        }
+    }
+
+    #[test]
+    fn test_spawn_with() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+        let b = spawn_with!(&mut pool, Position{x: 5, y: 6},);
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 1);
+        assert_eq!(pool.get::<Velocity>(a).unwrap().y, 4);
+        assert_eq!(pool.get::<Position>(b).unwrap().x, 5);
+        assert!(pool.get::<Velocity>(b).is_none());
+    }
+
+    #[test]
+    fn test_get_components() {
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Health(u32);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage),
+            (Health, health, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+        let b = spawn_with!(pool, Position{x: 5, y: 6});
+
+        match pool.get_components::<(Position, Velocity)>(a) {
+            Some((pos, vel)) => {
+                assert_eq!(pos.x, 1);
+                assert_eq!(vel.y, 4);
+            }
+            None => panic!()
+        }
+        assert!(pool.get_components::<(Position, Velocity)>(b).is_none());
+
+        match pool.get_components_mut::<Position, (Velocity,)>(a) {
+            Some((pos, (vel,))) => {
+                pos.x += vel.x;
+            }
+            None => panic!()
+        }
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 4);
+        assert!(pool.get_components_mut::<Position, (Velocity, Health)>(a).is_none());
+
+        pool.remove_entity(a);
+        assert!(pool.get_components::<(Position,)>(a).is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_get_components_mut_aliasing() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+        pool.get_components_mut::<Position, (Velocity, Position)>(a);
+    }
+
+    #[test]
+    fn test_iter_combinations() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        for x in 0..4 {
+            spawn_with!(pool, Position{x, y: 0});
+        }
+        let gone = spawn_with!(pool, Position{x: 10, y: 0});
+        pool.remove_entity(gone);
+
+        let pairs: Vec<_> = pool.iter_combinations::<Position>().collect();
+        assert_eq!(pairs.len(), 6);
+        assert_eq!(pool.iter_combinations::<Position>().size_hint(), (6, Some(6)));
+        let mut combinations = pool.iter_combinations::<Position>();
+        combinations.next();
+        assert_eq!(combinations.len(), 5);
+        let mut sums: Vec<i32> = pairs.iter().map(|((_, a), (_, b))| a.x + b.x).collect();
+        sums.sort();
+        assert_eq!(sums, vec![1, 2, 3, 3, 4, 5]);
+        assert!(pairs.iter().all(|((a, _), (b, _))| a != b));
+    }
+
+    #[test]
+    fn test_changed_since() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 0, y: 0});
+        let b = spawn_with!(pool, Position{x: 3, y: 4});
+        assert_eq!(pool.changed_since::<Position>(0).len(), 2);
+
+        let tick = pool.advance_tick();
+        assert_eq!(tick, 1);
+        assert!(pool.changed_since::<Position>(tick).is_empty());
+
+        pool.get_mut::<Position>(b).unwrap().x = 5;
+        pool.set(a, Velocity{x: 1, y: 1});
+        let changed = pool.changed_since::<Position>(tick);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0, b);
+        assert_eq!(changed[0].1.x, 5);
+        assert_eq!(pool.changed_since::<Velocity>(tick).len(), 1);
+
+        pool.remove::<Velocity>(a);
+        pool.remove_entity(b);
+        assert!(pool.changed_since::<Velocity>(0).is_empty());
+        assert_eq!(pool.changed_since::<Position>(0).len(), 1);
+    }
+
+    #[test]
+    fn test_removed_this_frame() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 0, y: 0});
+        pool.remove::<Velocity>(a);
+        assert!(pool.removed_this_frame::<Velocity>().is_empty());
+        pool.set(a, Velocity{x: 0, y: 0});
+        pool.track_removals(true);
+        let b = spawn_with!(pool, Position{x: 3, y: 4});
+        assert!(pool.removed_this_frame::<Position>().is_empty());
+
+        pool.remove::<Velocity>(a);
+        pool.remove_entity(b);
+        assert_eq!(pool.removed_this_frame::<Velocity>(), &[a]);
+        assert_eq!(pool.removed_this_frame::<Position>(), &[b]);
+
+        pool.cleanup_removed();
+        assert_eq!(pool.removed_this_frame::<Position>(), &[b]);
+
+        pool.clear_trackers();
+        assert!(pool.removed_this_frame::<Position>().is_empty());
+        assert!(pool.removed_this_frame::<Velocity>().is_empty());
+    }
+
+    #[test]
+    fn test_cancel_removal() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        pool.track_removals(true);
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 0, y: 0});
+        let b = spawn_with!(pool, Position{x: 3, y: 4});
+        pool.remove_entity(a);
+        pool.remove_entity(b);
+
+        let mut pending: Vec<EntityId> = pool.pending_removals().collect();
+        pending.sort();
+        assert_eq!(pending, vec![a, b]);
+
+        assert!(pool.cancel_removal(a));
+        assert!(!pool.cancel_removal(a));
+        assert_eq!(pool.pending_removals().collect::<Vec<_>>(), vec![b]);
+        assert_eq!(pool.pending_removals().len(), 1);
+        assert_eq!(pool.removed_this_frame::<Position>(), &[b]);
+
+        pool.cleanup_removed();
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 1);
+        assert!(pool.get::<Velocity>(a).is_some());
+        assert!(pool.force_get::<Position>(b).is_none());
+    }
+
+    #[test]
+    fn test_cleanup_report() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 0, y: 0});
+        let b = spawn_with!(pool, Velocity{x: 3, y: 4});
+        let c = pool.spawn_entity();
+        pool.remove_entity(c);
+        pool.remove_entity(b);
+        pool.remove_entity(a);
+
+        let destroyed = pool.cleanup_removed();
+        assert_eq!(destroyed.iter().map(|d| d.id).collect::<Vec<_>>(), vec![a, b, c]);
+        assert_eq!(destroyed[0].components, vec!["Position", "Velocity"]);
+        assert_eq!(destroyed[1].components, vec!["Velocity"]);
+        assert!(destroyed[2].components.is_empty());
+        assert!(pool.cleanup_removed().is_empty());
+    }
+
+    #[test]
+    fn test_cleanup_removed_budgeted() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let ids: Vec<EntityId> = (0..5).map(|x| spawn_with!(pool, Position{x, y: 0})).collect();
+        for id in &ids {
+            pool.remove_entity(*id);
+        }
+
+        assert_eq!(pool.cleanup_removed_budgeted(2).len(), 2);
+        assert_eq!(pool.pending_removals().count(), 3);
+        assert!(ids.iter().all(|id| pool.get::<Position>(*id).is_none()));
+        assert_eq!(pool.cleanup_removed_budgeted(2).len(), 2);
+        assert_eq!(pool.cleanup_removed_budgeted(2).len(), 1);
+        assert!(pool.cleanup_removed_budgeted(2).is_empty());
+        assert!(ids.iter().all(|id| pool.force_get::<Position>(*id).is_none()));
+    }
+
+    #[test]
+    fn test_auto_cleanup() {
+        use cleanup::AutoCleanup;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        pool.set_auto_cleanup(AutoCleanup::AfterNRemoved(3));
+        let ids: Vec<EntityId> = (0..3).map(|x| spawn_with!(pool, Position{x, y: 0})).collect();
+        pool.remove_entity(ids[0]);
+        pool.remove_entity(ids[1]);
+        assert_eq!(pool.pending_removals().count(), 2);
+        pool.remove_entity(ids[2]);
+        assert_eq!(pool.pending_removals().count(), 0);
+        assert!(pool.force_get::<Position>(ids[0]).is_none());
+
+        pool.set_auto_cleanup(AutoCleanup::OnSpawn);
+        let a = spawn_with!(pool, Position{x: 1, y: 1});
+        pool.remove_entity(a);
+        assert!(pool.force_get::<Position>(a).is_some());
+        pool.spawn_entity();
+        assert!(pool.force_get::<Position>(a).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "indexmap")]
+    fn test_index_map_storage() {
+        create_spawning_pool!(
+            (Position, pos, IndexMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let ids: Vec<EntityId> = (0..4).map(|_| pool.spawn_entity()).collect();
+        for (x, id) in ids.iter().rev().enumerate() {
+            pool.set(*id, Position{x: x as i32, y: 0});
+        }
+        pool.remove::<Position>(ids[2]);
+        pool.set(ids[3], Position{x: 10, y: 0});
+
+        let order: Vec<EntityId> = pool.get_all::<Position>().iter().map(|(id, _)| *id).collect();
+        assert_eq!(order, vec![ids[3], ids[1], ids[0]]);
+        assert_eq!(pool.get::<Position>(ids[3]).unwrap().x, 10);
+    }
+
+    #[test]
+    fn test_slab_storage() {
+        create_spawning_pool!(
+            (Position, pos, SlabStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 0});
+        let b = spawn_with!(pool, Position{x: 2, y: 0});
+        pool.remove_entity(a);
+        pool.cleanup_removed();
+        assert!(pool.force_get::<Position>(a).is_none());
+
+        let c = spawn_with!(pool, Position{x: 3, y: 0});
+        pool.get_mut::<Position>(b).unwrap().y = 5;
+        assert_eq!(pool.get::<Position>(b).unwrap().y, 5);
+        assert_eq!(pool.get::<Position>(c).unwrap().x, 3);
+        assert_eq!(pool.get_all::<Position>().len(), 2);
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get::<Position>(c).unwrap().x, 3);
+    }
+
+    #[test]
+    fn test_vector_storage_growth() {
+        let mut storage: VectorStorage<Position> = Storage::new();
+        let far = EntityId::from_raw(100).unwrap();
+        storage.set(far, Position { x: 1, y: 0 });
+        assert_eq!(storage.get(far).unwrap().x, 1);
+        let further = EntityId::from_raw(1000).unwrap();
+        storage.set(further, Position { x: 2, y: 0 });
+        assert_eq!(storage.get_all().len(), 2);
+        storage.remove(further);
+        storage.shrink_to_fit();
+        assert!(storage.get(further).is_none());
+        assert_eq!(storage.get(far).unwrap().x, 1);
+
+        let mut bounded: VectorStorage<Position> = VectorStorage::with_max_capacity(50);
+        let id = EntityId::from_raw(49).unwrap();
+        bounded.set(id, Position { x: 3, y: 0 });
+        let error = bounded.try_set(EntityId::from_raw(50).unwrap(), Position { x: 4, y: 0 }).unwrap_err();
+        assert_eq!(error.max_capacity, 50);
+        assert_eq!(bounded.len(), 1);
+
+        let json = ::serde_json::to_string(&bounded).unwrap();
+        let loaded: VectorStorage<Position> = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.max_capacity(), Some(50));
+        let old: VectorStorage<Position> = ::serde_json::from_str(r#"{"size":2,"storage":[null,{"x":5,"y":6}]}"#).unwrap();
+        assert_eq!(old.get(EntityId::from_raw(1).unwrap()).unwrap().y, 6);
+    }
+
+    #[test]
+    fn test_entity_set() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 0, y: 0}, Velocity{x: 1, y: 1});
+        spawn_with!(pool, Position{x: 0, y: 0});
+        let c = spawn_with!(pool, Position{x: 0, y: 0}, Velocity{x: 1, y: 1});
+        pool.remove_entity(c);
+
+        let moving = &pool.entity_set::<Position>() & &pool.entity_set::<Velocity>();
+        assert_eq!(moving.iter().collect::<Vec<_>>(), vec![a]);
+    }
+
+    #[test]
+    fn test_try_spawn_entity() {
+        use super::{RawEntityId, SpawnError};
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 0});
+        let weak = pool.downgrade(a);
+        *pool.next_id.get_mut() = RawEntityId::MAX - 1;
+        let last = pool.try_spawn_entity().unwrap();
+        assert_eq!(last.to_raw(), RawEntityId::MAX - 1);
+        assert_eq!(pool.try_spawn_entity(), Err(SpawnError::Exhausted));
+
+        pool.remove_entity(a);
+        pool.cleanup_removed();
+        let reused = pool.try_spawn_entity().unwrap();
+        assert_eq!(reused, a);
+        assert!(pool.is_alive(reused));
+        assert!(pool.get::<Position>(reused).is_none());
+        assert!(pool.upgrade(weak).is_none());
+        assert_eq!(pool.try_spawn_entity(), Err(SpawnError::Exhausted));
+    }
+
+    #[test]
+    fn test_destroyed_ids_stay_compact() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let ids: Vec<EntityId> = (0..1000).map(|_| pool.spawn_entity()).collect();
+        for &id in &ids {
+            if id != ids[500] {
+                pool.remove_entity(id);
+            }
+        }
+        pool.cleanup_removed();
+        assert_eq!(pool.destroyed.runs(), 2);
+        assert_eq!(pool.entity_count(), 1);
+        assert!(pool.is_alive(ids[500]) && !pool.is_alive(ids[499]));
+
+        let value = ::serde_json::to_value(&pool).unwrap();
+        assert_eq!(value["destroyed"], ::serde_json::json!([[1, 500], [502, 1000]]));
+        let loaded: SpawningPool = ::serde_json::from_value(value).unwrap();
+        assert_eq!(loaded.entities().collect::<Vec<_>>(), vec![ids[500]]);
+    }
+
+    #[test]
+    fn test_compact_ids() {
+        use map::{EntityMap, MapEntities};
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Target(Option<EntityId>);
+
+        impl MapEntities for Target {
+            fn map_entities(&mut self, map: &EntityMap) {
+                self.0.map_entities(map);
+            }
+        }
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Target, target, HashMapStorage, map_entities)
+        );
+        let mut pool = SpawningPool::new();
+        let ids: Vec<EntityId> = (0..6).map(|x| spawn_with!(pool, Position{x, y: 0})).collect();
+        pool.set(ids[5], Target(Some(ids[3])));
+        pool.set_parent(ids[3], ids[5]);
+        let (stale, weak) = (pool.downgrade(ids[2]), pool.downgrade(ids[3]));
+        pool.remove_entities(vec![ids[0], ids[2], ids[4]]);
+
+        let map = pool.compact_ids();
+        assert_eq!(map.len(), 3);
+        let (b, d, f) = (map.get(ids[1]).unwrap(), map.get(ids[3]).unwrap(), map.get(ids[5]).unwrap());
+        assert_eq!([b.to_raw(), d.to_raw(), f.to_raw()], [1, 2, 3]);
+        assert_eq!(pool.get::<Position>(d).unwrap().x, 3);
+        assert_eq!(pool.get::<Position>(f).unwrap().x, 5);
+        assert_eq!(pool.get::<Target>(f).unwrap().0, Some(d));
+        assert_eq!(pool.parent_of(d), Some(f));
+        assert_eq!(pool.entity_count(), 3);
+        assert_eq!(pool.get_all::<Position>().len(), 3);
+        assert_eq!(pool.upgrade(stale), None);
+        assert_eq!(pool.upgrade(map.get_weak(weak).unwrap()), Some(d));
+        assert_eq!(pool.spawn_entity().to_raw(), 4);
+        assert_eq!(pool.upgrade(weak), None);
+        assert!(map.get_weak(stale).is_none());
+    }
+
+    #[test]
+    fn test_compact_ids_replication() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage, replicated)
+        );
+        let mut server = SpawningPool::new();
+        let mut client = SpawningPool::new();
+        server.add_peer(1);
+        let a = spawn_with!(server, Position{x: 1, y: 0});
+        let b = spawn_with!(server, Position{x: 2, y: 0});
+        client.apply_updates(&server.collect_updates(1)).unwrap();
+
+        server.remove_entity(a);
+        server.set(b, Position{x: 3, y: 0});
+        let map = server.compact_ids();
+        assert_eq!(map.get(b), Some(a));
+        let packet = server.collect_updates(1);
+        assert!(packet.reset && packet.despawned.is_empty());
+        client.apply_updates(&packet).unwrap();
+        assert_eq!(client.get::<Position>(a).unwrap().x, 3);
+        assert!(!client.is_alive(b));
+        assert_eq!(client.entity_count(), 1);
+        assert!(server.collect_updates(1).is_empty());
+    }
+
+    #[test]
+    fn test_compact_ids_interests() {
+        use interest::InterestFilter;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage, replicated)
+        );
+        let mut server = SpawningPool::new();
+        let mut client = SpawningPool::new();
+        let a = spawn_with!(server, Position{x: -1, y: 0});
+        let b = spawn_with!(server, Position{x: 1, y: 0});
+        let c = spawn_with!(server, Position{x: -2, y: 0});
+        server.add_peer(1);
+        server.set_interest(1, InterestFilter::predicate(|pool: &SpawningPool, id| {
+            pool.get::<Position>(id).map(|pos| pos.x > 0).unwrap_or(false)
+        }));
+        client.apply_updates(&server.collect_updates(1)).unwrap();
+        assert_eq!(server.relevant_entities(1), Some(vec![b]));
+
+        server.remove_entity(a);
+        let map = server.compact_ids();
+        assert_eq!(map.get(b), Some(a));
+        assert_eq!(map.get(c), Some(b));
+        assert_eq!(server.relevant_entities(1), Some(vec![a]));
+        client.apply_updates(&server.collect_updates(1)).unwrap();
+        assert_eq!(client.get::<Position>(a).unwrap().x, 1);
+        assert!(client.get::<Position>(b).is_none());
+
+        server.set(b, Position{x: 2, y: 0});
+        assert_eq!(server.relevant_entities(1), Some(vec![a, b]));
+    }
+
+    #[test]
+    fn test_count() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, SlabStorage)
+        );
+        let mut pool = SpawningPool::new();
+        assert!(pool.is_empty::<Position>());
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 0, y: 0});
+        spawn_with!(pool, Position{x: 3, y: 4});
+        assert_eq!(pool.count::<Position>(), 2);
+        assert_eq!(pool.count::<Velocity>(), 1);
+
+        pool.remove_entity(a);
+        assert_eq!(pool.count::<Position>(), 1);
+        assert!(pool.is_empty::<Velocity>());
+        pool.cleanup_removed();
+        assert_eq!(pool.count::<Position>(), 1);
+        assert!(pool.is_empty::<Velocity>());
+    }
+
+    #[test]
+    fn test_pool_attributes() {
+        use std::borrow::Cow;
+        use std::sync::Arc;
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Name(Cow<'static, str>);
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Shared<T>(Arc<T>);
+
+        create_spawning_pool!(
+            #[serde(bound = "")]
+            #[serde(rename = "World")]
+            (Name, name, HashMapStorage),
+            (Shared<Vec<u8>>, mesh, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = spawn_with!(pool, Name(Cow::Borrowed("orc")), Shared(Arc::new(vec![1, 2, 3])));
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get::<Name>(id).unwrap().0, "orc");
+        assert_eq!(*loaded.get::<Shared<Vec<u8>>>(id).unwrap().0, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_storage_access() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+        let fork = pool.fork();
+
+        let storage: &VectorStorage<Position> = pool.storage::<Position>();
+        assert_eq!(storage.get(a).unwrap().x, 1);
+
+        let storage: &mut HashMapStorage<Velocity> = pool.storage_mut::<Velocity>();
+        storage.get_mut(a).unwrap().x = 7;
+        assert_eq!(pool.get::<Velocity>(a).unwrap().x, 7);
+        assert_eq!(fork.get::<Velocity>(a).unwrap().x, 3);
+    }
+
+    #[test]
+    fn test_cell_storage() {
+        type VectorCell<T> = CellStorage<T, VectorStorage<T>>;
+
+        create_spawning_pool!(
+            (Position, pos, CellStorage),
+            (Velocity, vel, VectorCell)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+        let b = spawn_with!(pool, Position{x: 5, y: 6}, Velocity{x: 7, y: 8});
+
+        {
+            let shared = &pool;
+            let mut positions = shared.borrow_storage_mut::<Position, _>();
+            let mut velocities = shared.borrow_storage_mut::<Velocity, _>();
+            for (id, vel) in velocities.get_all().iter().map(|(id, v)| (*id, (*v).clone())) {
+                let pos = positions.get_mut(id).unwrap();
+                pos.x += vel.x;
+                pos.y += vel.y;
+            }
+            velocities.get_mut(a).unwrap().x = 0;
+        }
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 4);
+        assert_eq!(pool.get::<Position>(b).unwrap().y, 14);
+        assert_eq!(pool.get::<Velocity>(a).unwrap().x, 0);
+        assert!(::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            pool.borrow_storage_mut::<Velocity, _>();
+        })).is_err());
+        pool.release_borrows::<Velocity, _>();
+        pool.borrow_storage_mut::<Velocity, _>().get_mut(a).unwrap().x = 1;
+
+        let fork = pool.fork();
+        assert!(::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            pool.borrow_storage_mut::<Position, _>();
+        })).is_err());
+        pool.storage_mut::<Position>();
+        pool.borrow_storage_mut::<Position, _>().get_mut(a).unwrap().x = 9;
+        assert_eq!(pool.borrow_storage::<Position, _>().get(a).unwrap().x, 9);
+        assert_eq!(fork.get::<Position>(a).unwrap().x, 4);
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get::<Position>(a).unwrap().x, 9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_cell_storage_conflict() {
+        create_spawning_pool!(
+            (Position, pos, CellStorage)
+        );
+        let mut pool = SpawningPool::new();
+        spawn_with!(pool, Position{x: 1, y: 2});
+        let _a = pool.borrow_storage_mut::<Position, _>();
+        let _b = pool.borrow_storage::<Position, _>();
+    }
+
+    #[test]
+    fn test_run_query() {
+        use query::Query;
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Health(u32);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage),
+            (Health, health, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4}, Health(1));
+        let b = spawn_with!(pool, Position{x: 5, y: 6}, Velocity{x: 7, y: 8});
+        spawn_with!(pool, Position{x: 9, y: 9});
+        let c = spawn_with!(pool, Position{x: 0, y: 0}, Velocity{x: 1, y: 1});
+        pool.remove_entity(c);
+
+        let visited = pool.run(|mut q: Query<SpawningPool, (&mut Position, &Velocity)>| {
+            let mut visited = vec![];
+            q.for_each(|id, (pos, vel)| {
+                pos.x += vel.x;
+                pos.y += vel.y;
+                visited.push(id);
+            });
+            visited
+        });
+        assert_eq!(visited, vec![a, b]);
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 4);
+        assert_eq!(pool.get::<Position>(b).unwrap().y, 14);
+
+        pool.run(|mut q: Query<SpawningPool, (&mut Health, &mut Velocity)>| {
+            let (health, vel) = q.get(a).unwrap();
+            health.0 += 1;
+            vel.x = 0;
+            assert!(q.get(b).is_none());
+        });
+        assert_eq!(pool.get::<Health>(a).unwrap().0, 2);
+        assert_eq!(pool.get::<Velocity>(a).unwrap().x, 0);
+
+        let movement = pool.run(|q: Query<SpawningPool, (&mut Position, &Velocity)>| q.access().clone());
+        let healing = pool.run(|q: Query<SpawningPool, (&mut Health,)>| q.access().clone());
+        let physics = pool.run(|q: Query<SpawningPool, (&Position,)>| q.access().clone());
+        assert!(!movement.conflicts_with(&healing));
+        assert!(movement.conflicts_with(&physics));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_run_query_aliasing() {
+        use query::Query;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        pool.run(|_: Query<SpawningPool, (&mut Position, &Position)>| {});
+    }
+
+    #[test]
+    fn test_dyn_query() {
+        use inspect::{DynQuery, ValueError};
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Dead;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage),
+            (Dead, dead, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+        spawn_with!(pool, Position{x: 5, y: 6}, Velocity{x: 7, y: 8}, Dead);
+        let c = spawn_with!(pool, Position{x: 9, y: 9});
+
+        let rows = DynQuery::new().with("Position").with("Velocity").without("Dead").run(&pool).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].entity, a);
+        assert_eq!(rows[0].components[0].0, "Position");
+        assert_eq!(rows[0].components[1].1["y"], 4);
+
+        let rows = DynQuery::new().without("Velocity").run(&pool).unwrap();
+        assert_eq!(rows.iter().map(|r| r.entity).collect::<Vec<_>>(), vec![c]);
+        assert!(rows[0].components.is_empty());
+
+        match DynQuery::new().with("Health").run(&pool) {
+            Err(ValueError::UnknownComponent(name)) => assert_eq!(name, "Health"),
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn test_console() {
+        use console::{execute, ConsoleError};
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+        spawn_with!(pool, Velocity{x: 5, y: 6});
+
+        assert_eq!(execute(&mut pool, "list").unwrap(), "1: Position, Velocity\n2: Velocity");
+        assert_eq!(
+            execute(&mut pool, "inspect 1").unwrap(),
+            "Entity 1\n    Position: {\"x\":1,\"y\":2}\n    Velocity: {\"x\":3,\"y\":4}"
+        );
+        assert_eq!(execute(&mut pool, "set 1  Position { \"x\": 7, \"y\": 8 }").unwrap(), "set Position on 1");
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 7);
+        assert_eq!(execute(&mut pool, "  despawn 2").unwrap(), "despawned 2");
+        assert_eq!(execute(&mut pool, "list").unwrap(), "1: Position, Velocity");
+
+        match execute(&mut pool, "inspect x") {
+            Err(ConsoleError::InvalidId(id)) => assert_eq!(id, "x"),
+            _ => panic!()
+        }
+        match execute(&mut pool, "set 1 Position") {
+            Err(e) => assert_eq!(e.to_string(), "usage: set <id> <component> <json>"),
+            _ => panic!()
+        }
+        assert!(execute(&mut pool, "set 1 Health 5").is_err());
+        assert!(execute(&mut pool, "fly").is_err());
+    }
+
+    #[test]
+    fn test_testing_helpers() {
+        use testing::*;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let (mut pool, ids) = TestPool::<SpawningPool>::new()
+            .entity().with(Position{x: 1, y: 2}).with(Velocity{x: 3, y: 4}).done()
+            .entity().with(Position{x: 5, y: 6}).done()
+            .build_with_ids();
+        assert_entity_has::<Position, _>(&pool, ids[0]);
+        assert_entity_has::<Velocity, _>(&pool, ids[0]);
+        assert_entity_lacks::<Velocity, _>(&pool, ids[1]);
+
+        let copy = pool.fork();
+        assert_pools_eq(&pool, &copy);
+        pool.get_mut::<Position>(ids[1]).unwrap().x = 0;
+        let result = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| assert_pools_eq(&pool, &copy)));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_save_load() {
+        use save::SaveError;
+
+        let bytes = {
+            create_spawning_pool!(
+                (Position, pos, VectorStorage),
+                (Velocity, vel, HashMapStorage)
+            );
+            let mut pool = SpawningPool::new();
+            let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+            let mut bytes = vec![];
+            pool.save(&mut bytes).unwrap();
+            assert_eq!(&bytes[..4], b"SPWN");
+
+            let loaded = SpawningPool::load(&bytes[..]).unwrap();
+            assert_eq!(loaded.get::<Velocity>(a).unwrap().y, 4);
+
+            match SpawningPool::load(&b"{}"[..]) {
+                Err(SaveError::NotASave) => {}
+                _ => panic!()
+            }
+            let mut newer = bytes.clone();
+            newer[4] = 99;
+            match SpawningPool::load(&newer[..]) {
+                Err(SaveError::UnsupportedVersion(99)) => {}
+                _ => panic!()
+            }
+            bytes
+        };
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, VectorStorage)
+        );
+        match SpawningPool::load(&bytes[..]) {
+            Err(SaveError::SchemaMismatch { found, .. }) => assert_ne!(found, SpawningPool::schema_hash()),
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn test_save_streaming() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let ids: Vec<EntityId> = (0..5).map(|i| spawn_with!(pool, Position{x: i, y: 0})).collect();
+        pool.set(ids[2], Velocity{x: 1, y: 1});
+        pool.remove_entity(ids[4]);
+
+        let mut bytes = vec![];
+        pool.save_streaming(&mut bytes, 2).unwrap();
+        assert_eq!(bytes[16..].iter().filter(|&&b| b == b'\n').count(), 1 + 3 + 1);
+
+        let mut loaded = SpawningPool::load_streaming(&bytes[..]).unwrap();
+        assert_eq!(loaded.get::<Position>(ids[3]).unwrap().x, 3);
+        assert_eq!(loaded.get::<Velocity>(ids[2]).unwrap().y, 1);
+        assert!(loaded.get::<Position>(ids[4]).is_none());
+        assert_eq!(loaded.spawn_entity().to_raw(), 6);
+        assert!(SpawningPool::load(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_save_blobs() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let ids: Vec<EntityId> = (0..5).map(|i| spawn_with!(pool, Position{x: i, y: 0})).collect();
+        pool.set(ids[2], Velocity{x: 1, y: 1});
+        pool.remove_entity(ids[4]);
+
+        let mut bytes = vec![];
+        pool.save_blobs(&mut bytes).unwrap();
+        let mut loaded = SpawningPool::load_blobs(&bytes[..]).unwrap();
+        assert_eq!(loaded.get::<Position>(ids[3]).unwrap().x, 3);
+        assert_eq!(loaded.get::<Velocity>(ids[2]).unwrap().y, 1);
+        assert!(loaded.get::<Position>(ids[4]).is_none());
+        assert_eq!(loaded.spawn_entity().to_raw(), 6);
+        assert!(SpawningPool::load_blobs(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    #[test]
+    fn test_load_blobs_without_send() {
+        #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+        struct Local(i32, ::std::marker::PhantomData<*const ()>);
+
+        create_spawning_pool!(
+            (Local, local, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = spawn_with!(pool, Local::default());
+        let mut bytes = vec![];
+        pool.save_blobs(&mut bytes).unwrap();
+        assert_eq!(SpawningPool::load_blobs(&bytes[..]).unwrap().get::<Local>(id).unwrap().0, 0);
+    }
+
+    #[test]
+    fn test_load_partial() {
+        use save::SaveError;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+        let mut bytes = vec![];
+        pool.save(&mut bytes).unwrap();
+
+        let mut loaded = SpawningPool::load_partial(&bytes[..], &["Position"]).unwrap();
+        assert_eq!(loaded.get::<Position>(a).unwrap().y, 2);
+        assert!(loaded.get::<Velocity>(a).is_none());
+        assert_eq!(loaded.spawn_entity().to_raw(), 2);
+        match SpawningPool::load_partial(&bytes[..], &["Health"]) {
+            Err(SaveError::UnknownComponent(ref name)) if name == "Health" => {},
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn test_save_transformed() {
+        use std::io;
+        use save::{SaveError, SaveTransform};
+
+        struct Checked;
+
+        impl SaveTransform for Checked {
+            fn encode(&self, mut data: Vec<u8>) -> io::Result<Vec<u8>> {
+                let sum = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+                data.reverse();
+                data.push(sum);
+                Ok(data)
+            }
+
+            fn decode(&self, mut data: Vec<u8>) -> io::Result<Vec<u8>> {
+                let sum = data.pop().ok_or(io::ErrorKind::InvalidData)?;
+                data.reverse();
+                if data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != sum {
+                    return Err(io::ErrorKind::InvalidData.into());
+                }
+                Ok(data)
+            }
+        }
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2});
+        let mut bytes = vec![];
+        pool.save_transformed(&mut bytes, &Checked).unwrap();
+        assert!(SpawningPool::load(&bytes[..]).is_err());
+
+        let loaded = SpawningPool::load_transformed(&bytes[..], &Checked).unwrap();
+        assert_eq!(loaded.get::<Position>(a).unwrap().y, 2);
+        bytes[20] ^= 1;
+        match SpawningPool::load_transformed(&bytes[..], &Checked) {
+            Err(SaveError::Io(ref e)) if e.kind() == io::ErrorKind::InvalidData => {},
+            _ => panic!()
+        }
+    }
+
+    #[test]
+    fn test_load_skip_unknown() {
+        use save::{LoadOptions, SaveError};
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Health(u32);
+
+        let (bytes, a) = {
+            create_spawning_pool!(
+                (Position, pos, VectorStorage),
+                (Velocity, vel, HashMapStorage)
+            );
+            let mut pool = SpawningPool::new();
+            let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+            let mut bytes = vec![];
+            pool.save(&mut bytes).unwrap();
+            (bytes, a)
+        };
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Health, health, HashMapStorage)
+        );
+        match SpawningPool::load_with(&bytes[..], &LoadOptions::default()) {
+            Err(SaveError::SchemaMismatch { .. }) => {}
+            _ => panic!()
+        }
+        let options = LoadOptions { skip_unknown: true, ..Default::default() };
+        let (mut pool, report) = SpawningPool::load_with(&bytes[..], &options).unwrap();
+        assert_eq!(report.unknown, vec!["vel".to_string()]);
+        assert_eq!(report.missing, vec!["health"]);
+        assert_eq!(pool.get::<Position>(a).unwrap().y, 2);
+        assert!(pool.get::<Health>(a).is_none());
+        pool.set(a, Health(5));
+        assert_eq!(pool.spawn_entity().to_raw(), 2);
+    }
+
+    #[test]
+    fn test_load_preserve_unknown() {
+        use save::LoadOptions;
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Health(u32);
+
+        let bytes = {
+            create_spawning_pool!(
+                (Position, pos, VectorStorage),
+                (Health, health, HashMapStorage)
+            );
+            let mut pool = SpawningPool::new();
+            spawn_with!(pool, Position{x: 1, y: 2}, Health(7));
+            let mut bytes = vec![];
+            pool.save(&mut bytes).unwrap();
+            bytes
+        };
+
+        let resaved = {
+            create_spawning_pool!(
+                (Position, pos, VectorStorage)
+            );
+            let options = LoadOptions { preserve_unknown: true, ..Default::default() };
+            let (pool, report) = SpawningPool::load_with(&bytes[..], &options).unwrap();
+            assert_eq!(report.unknown, vec!["health".to_string()]);
+            assert_eq!(pool.preserved_data().names(), vec!["health"]);
+            let mut resaved = vec![];
+            pool.fork().save(&mut resaved).unwrap();
+            resaved
+        };
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Health, health, HashMapStorage)
+        );
+        let options = LoadOptions { skip_unknown: true, ..Default::default() };
+        let (pool, report) = SpawningPool::load_with(&resaved[..], &options).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(pool.get::<Health>(EntityId::from_raw(1).unwrap()).unwrap().0, 7);
+    }
+
+    #[test]
+    fn test_load_renames() {
+        use save::{LoadOptions, Renames};
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Hp(u32);
+
+        let bytes = {
+            create_spawning_pool!(
+                (Position, pos, VectorStorage),
+                (Hp, hp, HashMapStorage)
+            );
+            let mut pool = SpawningPool::new();
+            spawn_with!(pool, Position{x: 1, y: 2}, Hp(7));
+            let mut bytes = vec![];
+            pool.save(&mut bytes).unwrap();
+            bytes
+        };
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Health(u32);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Health, health, HashMapStorage)
+        );
+        assert!(SpawningPool::load(&bytes[..]).is_err());
+        let options = LoadOptions {
+            renames: Renames::new().rename("Hp", "Health").rename("hp", "health"),
+            ..Default::default()
+        };
+        let (pool, report) = SpawningPool::load_with(&bytes[..], &options).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.renamed, vec![("hp".to_string(), "health".to_string())]);
+        assert_eq!(pool.get::<Health>(EntityId::from_raw(1).unwrap()).unwrap().0, 7);
+    }
+
+    #[test]
+    fn test_layers() {
+        use layers::Layers;
+
+        const RENDER: Layers = Layers(1);
+        const PHYSICS: Layers = Layers(2);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        let b = pool.spawn_entity();
+        let c = pool.spawn_entity();
+        pool.set_layers(a, RENDER | PHYSICS);
+        pool.set_layers(b, PHYSICS);
+        pool.set_layers(c, RENDER);
+        assert_eq!(pool.layers(a), RENDER | PHYSICS);
+        assert_eq!(pool.entities_in_layer(RENDER), vec![a, c]);
+        assert_eq!(pool.entities_in_layer(PHYSICS), vec![a, b]);
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.entities_in_layer(Layers::ALL), vec![a, b, c]);
+
+        pool.remove_entity(a);
+        assert_eq!(pool.entities_in_layer(RENDER), vec![c]);
+        pool.cleanup_removed();
+        assert_eq!(pool.layers(a), Layers::NONE);
+    }
+
+    #[test]
+    fn test_get_all_into() {
+        use fetch::ScratchBuffer;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 1}, Velocity{x: 0, y: 0});
+        let b = spawn_with!(pool, Position{x: 2, y: 2});
+        let mut scratch = ScratchBuffer::new();
+
+        let mut all = scratch.take();
+        pool.get_all_into::<Position>(&mut all);
+        assert_eq!(all.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![a, b]);
+        scratch.give_back(all);
+        let capacity = scratch.capacity();
+        assert!(capacity >= 2);
+
+        pool.remove_entity(a);
+        let mut all = scratch.take();
+        pool.get_all_into::<Position>(&mut all);
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].0, b);
+        assert_eq!(all.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_iter_chunks() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        for x in 0..7 {
+            spawn_with!(pool, Position{x, y: 0});
+        }
+        let first = pool.get_all::<Position>()[0].0;
+        pool.remove_entity(first);
+
+        let chunks = pool.iter_chunks::<Position>(4);
+        assert_eq!(chunks.len(), 2);
+        let sizes: Vec<usize> = chunks.map(|c| c.len()).collect();
+        assert_eq!(sizes, vec![4, 2]);
+        let sum: i32 = pool.iter_chunks::<Position>(3)
+            .map(|c| c.iter().map(|(_, p)| p.x).sum::<i32>())
+            .sum();
+        assert_eq!(sum, 21);
+    }
+
+    #[test]
+    fn test_soa_storage() {
+        soa_component! {
+            #[derive(Clone, Debug, PartialEq)]
+            struct Body {
+                x: i32,
+                y: i32
+            }
+            storage BodySoa, columns BodyColumns, proxy BodyMut;
+        }
+
+        let id = |raw| EntityId::from_raw(raw).unwrap();
+        let mut bodies = BodySoa::new();
+        bodies.set(id(1), Body { x: 1, y: 10 });
+        bodies.set(id(2), Body { x: 2, y: 20 });
+        bodies.set(id(3), Body { x: 3, y: 30 });
+        assert_eq!(bodies.columns().x, vec![1, 2, 3]);
+
+        *bodies.get_mut(id(2)).unwrap().y += 1;
+        assert_eq!(bodies.remove(id(1)), Some(Body { x: 1, y: 10 }));
+        assert_eq!(bodies.ids(), &[id(3), id(2)]);
+        assert_eq!(bodies.get(id(2)), Some(Body { x: 2, y: 21 }));
+        assert_eq!(bodies.get(id(3)), Some(Body { x: 3, y: 30 }));
+        assert_eq!(bodies.remove(id(1)), None);
+
+        bodies.set(id(3), Body { x: 0, y: 0 });
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies.columns().y, vec![0, 21]);
+    }
+
+    #[test]
+    fn test_arena_storage() {
+        create_spawning_pool!(
+            (Position, pos, ArenaStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 1});
+        let b = spawn_with!(pool, Position{x: 2, y: 2});
+        pool.remove_entity(a);
+        pool.cleanup_removed();
+        let c = spawn_with!(pool, Position{x: 3, y: 3});
+        assert_eq!(pool.count::<Position>(), 2);
+        match pool.get::<Position>(c) {
+            Some(p) => assert_eq!(p.x, 3),
+            None => panic!()
+        }
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let mut loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get::<Position>(b).unwrap().x, 2);
+
+        let mut arena = ArenaStorage::with_capacity(2);
+        for raw in 1..6 {
+            arena.set(EntityId::from_raw(raw).unwrap(), Position{x: raw as i32, y: 0});
+        }
+        assert_eq!(arena.blocks(), 3);
+        assert_eq!(arena.get(EntityId::from_raw(5).unwrap()).unwrap().x, 5);
+
+        loaded.storage_mut::<Position>().free_all();
+        assert!(loaded.get::<Position>(b).is_none());
+    }
+
+    #[test]
+    fn test_reserve() {
+        create_spawning_pool!(
+            (Position, pos, ArenaStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        pool.reserve_all(2000);
+        pool.reserve::<Velocity>(5000);
+        assert_eq!(pool.storage::<Position>().blocks(), 2);
+        for x in 0..2000 {
+            spawn_with!(pool, Position{x, y: 0}, Velocity{x: 1, y: 1});
+        }
+        assert_eq!(pool.storage::<Position>().blocks(), 2);
+        assert_eq!(pool.count::<Velocity>(), 2000);
+
+        let mut arena = ArenaStorage::with_capacity(4);
+        let id = |raw| EntityId::from_raw(raw).unwrap();
+        arena.set(id(1), Position{x: 1, y: 0});
+        arena.reserve(10);
+        assert_eq!(arena.blocks(), 3);
+        for raw in 2..12 {
+            arena.set(id(raw), Position{x: raw as i32, y: 0});
+        }
+        assert_eq!(arena.blocks(), 3);
+        assert_eq!(arena.get(id(4)).unwrap().x, 4);
+        assert_eq!(arena.get(id(11)).unwrap().x, 11);
+    }
+
+    #[test]
+    fn test_remove_entities() {
+        use cleanup::AutoCleanup;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        pool.set_auto_cleanup(AutoCleanup::AfterNRemoved(3));
+        pool.track_removals(true);
+        let ids: Vec<EntityId> = (0..5).map(|x| spawn_with!(pool, Position{x, y: 0})).collect();
+        pool.set(ids[1], Velocity{x: 1, y: 1});
+
+        pool.remove_entities(vec![ids[3], ids[1]]);
+        assert_eq!(pool.pending_removals().len(), 2);
+        pool.remove_entities(ids[4..].iter().cloned());
+        assert_eq!(pool.pending_removals().len(), 0);
+        assert_eq!(pool.count::<Position>(), 2);
+        assert_eq!(pool.count::<Velocity>(), 0);
+        assert_eq!(pool.removed_this_frame::<Velocity>(), &[ids[1]]);
+    }
+
+    #[test]
+    fn test_component_mask() {
+        use mask::ComponentMask;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        const POS: ComponentMask = SpawningPool::mask::<Position>();
+        const VEL: ComponentMask = SpawningPool::mask::<Velocity>();
+
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 0, y: 0}, Velocity{x: 1, y: 1});
+        let b = spawn_with!(pool, Velocity{x: 1, y: 1});
+        assert_eq!(pool.component_mask(a), POS | VEL);
+        assert!(pool.component_mask(a).contains(POS | VEL));
+        assert!(!pool.component_mask(b).contains(POS | VEL));
+        assert!(pool.component_mask(b).intersects(POS | VEL));
+        pool.remove_entity(a);
+        assert!(pool.component_mask(a).is_empty());
+    }
+
+    #[test]
+    fn test_export_csv() {
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Unit {
+            name: String,
+            pos: Position,
+            target: Option<u32>
+        }
+
+        create_spawning_pool!(
+            (Unit, units, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        spawn_with!(pool, Unit { name: "orc".to_string(), pos: Position{x: 1, y: 2}, target: None });
+        spawn_with!(pool, Unit { name: "elf, archer".to_string(), pos: Position{x: 3, y: 4}, target: Some(1) });
+
+        let mut out = vec![];
+        pool.export_csv::<Unit, _>(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "entity_id,name,pos.x,pos.y,target\n1,orc,1,2,\n2,\"elf, archer\",3,4,1\n");
+    }
+
+    #[test]
+    fn test_summary() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        spawn_with!(pool, Position{x: 0, y: 0});
+        let b = spawn_with!(pool, Position{x: 0, y: 0}, Velocity{x: 1, y: 1});
+        let c = spawn_with!(pool, Velocity{x: 1, y: 1});
+        pool.remove_entity(c);
+
+        let summary = pool.summary();
+        assert_eq!(summary.entities, 2);
+        assert_eq!(summary.components, vec![("Position", 2), ("Velocity", 1)]);
+        assert_eq!(summary.histogram, vec![0, 1, 1]);
+        assert_eq!(summary.largest, Some((b, 2)));
+        assert_eq!(summary.pending_removals, 1);
+        assert_eq!(summary.to_string(), "2 entities, 1 pending removal\n    Position: 2\n    Velocity: 1\n    components per entity: 1 with 1, 1 with 2\n    largest entity: 2 (2 components)\n");
+    }
+
+    #[test]
+    fn test_archetype_report() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        spawn_with!(pool, Position{x: 0, y: 0});
+        spawn_with!(pool, Position{x: 0, y: 0}, Velocity{x: 1, y: 1});
+        spawn_with!(pool, Position{x: 1, y: 0}, Velocity{x: 1, y: 1});
+        let d = spawn_with!(pool, Velocity{x: 1, y: 1});
+        pool.spawn_entity();
+        pool.remove_entity(d);
+
+        let report = pool.archetype_report();
+        assert_eq!(report.archetypes.len(), 2);
+        assert_eq!(report.archetypes[0].components, vec!["Position", "Velocity"]);
+        assert_eq!(report.archetypes[0].entities, 2);
+        assert_eq!(report.archetypes[1].mask, SpawningPool::mask::<Position>());
+        assert_eq!(report.together("Position", "Velocity"), 2);
+        assert_eq!(report.to_string(), "2: Position, Velocity\n1: Position\n");
+    }
+
+    #[test]
+    fn test_named_pools() {
+        create_spawning_pool!(
+            WorldPool;
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        create_spawning_pool!(
+            pub(crate) UiPool;
+            (Position, pos, HashMapStorage)
+        );
+        let mut world = WorldPool::new();
+        let mut ui = UiPool::default();
+        let a = spawn_with!(world, Position{x: 1, y: 2}, Velocity{x: 0, y: 0});
+        let b = spawn_with!(ui, Position{x: 3, y: 4});
+        assert_eq!(world.get::<Position>(a).unwrap().y, 2);
+        assert_eq!(ui.get::<Position>(b).unwrap().x, 3);
+        assert!(world.to_dot(|_, _| vec![]).starts_with("digraph WorldPool {"));
+    }
+
+    mod hygiene {
+        use storage::VectorStorage;
+        use super::Position;
+
+        #[allow(dead_code)]
+        struct EntityId;
+        #[allow(dead_code)]
+        trait ComponentLoader {}
+
+        create_spawning_pool!(
+            pub(crate) HygienicPool;
+            (Position, pos, VectorStorage)
+        );
+
+        #[test]
+        fn test_macro_hygiene() {
+            let mut pool = HygienicPool::new();
+            let id = pool.spawn_entity();
+            pool.set(id, Position{x: 1, y: 2});
+            assert_eq!(pool.get::<Position>(id).unwrap().x, 1);
+        }
+    }
+
+    #[test]
+    fn test_storage_paths() {
+        create_spawning_pool!(
+            (Position, pos, super::storage::VectorStorage),
+            (Velocity, vel, CellStorage<Velocity, VectorStorage<Velocity>>)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 2}, Velocity{x: 3, y: 4});
+        assert_eq!(pool.get::<Velocity>(a).unwrap().x, 3);
+        let _: &CellStorage<Velocity, VectorStorage<Velocity>> = pool.storage::<Velocity>();
+        assert!(SpawningPool::SCHEMA[0].2.ends_with("VectorStorage"));
+        assert!(SpawningPool::SCHEMA[1].2.starts_with("CellStorage <"));
+    }
+
+    #[test]
+    fn test_is_alive() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        let b = pool.spawn_entity();
+        let reserved = pool.reserve_entity();
+        let never = EntityId::from_raw(10).unwrap();
+        assert!(pool.is_alive(a) && pool.is_alive(reserved));
+        assert!(!pool.is_alive(never));
+        #[cfg(not(feature = "strict"))]
+        pool.set(never, Position{x: 0, y: 0});
+        assert!(pool.get::<Position>(never).is_none());
+
+        pool.remove_entity(b);
+        assert!(!pool.is_alive(b));
+        assert_eq!(pool.entities().collect::<Vec<_>>(), vec![a, reserved]);
+        assert_eq!(pool.entity_count(), 2);
+        pool.cleanup_removed();
+        pool.cancel_removal(b);
+        assert!(!pool.is_alive(b));
+        pool.remove_entity(never);
+        assert_eq!(pool.entity_count(), 2);
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.entities().collect::<Vec<_>>(), vec![a, reserved]);
+    }
+
+    #[test]
+    fn test_age_of() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let untracked = pool.spawn_entity();
+        pool.track_spawn_ticks(true);
+        pool.advance_tick();
+        let a = pool.spawn_entity();
+        pool.advance_tick();
+        pool.advance_tick();
+        let b = pool.spawn_entity();
+
+        let now = pool.current_tick();
+        assert_eq!(pool.age_of(untracked, now), None);
+        assert_eq!(pool.spawn_tick(a), Some(1));
+        assert_eq!(pool.age_of(a, now), Some(2));
+        assert_eq!(pool.age_of(b, now), Some(0));
+
+        pool.remove_entity(a);
+        pool.cleanup_removed();
+        assert_eq!(pool.age_of(a, now), None);
+    }
+
+    #[test]
+    fn test_despawn_after() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let bullet = spawn_with!(pool, Position{x: 0, y: 0});
+        let spark = spawn_with!(pool, Position{x: 0, y: 0});
+        let smoke = spawn_with!(pool, Position{x: 0, y: 0});
+        pool.despawn_after(bullet, 2);
+        pool.despawn_after(spark, 1);
+        pool.despawn_after(smoke, 1);
+        assert_eq!(pool.cancel_despawn(smoke), Some(1));
+
+        assert_eq!(pool.tick(), vec![spark]);
+        assert!(pool.get::<Position>(spark).is_none());
+        assert_eq!(pool.despawn_timer(bullet), Some(1));
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let mut loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.tick(), vec![bullet]);
+        assert!(loaded.get::<Position>(bullet).is_none());
+        assert!(loaded.get::<Position>(smoke).is_some());
+        assert!(loaded.tick().is_empty());
+    }
+
+    #[test]
+    fn test_weak_references() {
+        use weak::EntityRefWeak;
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Target(EntityRefWeak);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Target, target, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let enemy = spawn_with!(pool, Position{x: 0, y: 0});
+        let weak = pool.downgrade(enemy);
+        let hunter = spawn_with!(pool, Target(weak));
+        assert_eq!(pool.upgrade(pool.get::<Target>(hunter).unwrap().0), Some(enemy));
+
+        pool.remove_entity(enemy);
+        assert_eq!(pool.upgrade(weak), None);
+        pool.cleanup_removed();
+        assert_eq!(pool.upgrade(weak), None);
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.upgrade(loaded.get::<Target>(hunter).unwrap().0), None);
+        assert_eq!(loaded.upgrade(loaded.downgrade(hunter)), Some(hunter));
+        assert_eq!(loaded.upgrade(EntityRefWeak::new(EntityId::from_raw(100).unwrap(), 0)), None);
+    }
+
+    #[test]
+    fn test_merge_map_entities() {
+        use map::{EntityMap, MapEntities};
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Follows(EntityId);
+
+        impl MapEntities for Follows {
+            fn map_entities(&mut self, map: &EntityMap) {
+                self.0.map_entities(map);
+            }
+        }
 
-        match pool.get_mut::<Velocity>(id) {
-            Some(vel) => {
-                vel.x = 3;
-                vel.y = 4;
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Follows, follows, HashMapStorage, map_entities)
+        );
+        let mut world = SpawningPool::new();
+        let existing = spawn_with!(world, Position{x: 0, y: 0});
+
+        let mut prefab = SpawningPool::new();
+        let leader = spawn_with!(prefab, Position{x: 5, y: 5});
+        let follower = spawn_with!(prefab, Position{x: 6, y: 5}, Follows(leader));
+        let gone = spawn_with!(prefab, Position{x: 7, y: 5});
+        prefab.remove_entity(gone);
+        let uuid = prefab.ensure_uuid(leader).unwrap();
+
+        let map = world.merge(prefab);
+        assert_eq!(map.len(), 2);
+        let new_leader = map.get(leader).unwrap();
+        let new_follower = map.get(follower).unwrap();
+        assert!(new_leader != existing && new_follower != existing);
+        assert_eq!(world.entity_by_uuid(&uuid), Some(new_leader));
+        assert_eq!(world.get::<Position>(new_leader).unwrap().x, 5);
+        assert_eq!(world.get::<Follows>(new_follower).unwrap().0, new_leader);
+        assert_eq!(world.count::<Position>(), 3);
+
+        let mut swap = EntityMap::new();
+        swap.insert(new_leader, existing);
+        world.map_entities(&swap);
+        assert_eq!(world.get::<Follows>(new_follower).unwrap().0, existing);
+    }
+
+    #[test]
+    fn test_merge_hierarchy() {
+        relation!(struct Owns);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut world = SpawningPool::new();
+        let existing = world.spawn_entity();
+        let other_root = world.spawn_entity();
+        world.set_parent(existing, other_root);
+
+        let mut prefab = SpawningPool::new();
+        let root = prefab.spawn_entity();
+        let child = spawn_with!(prefab, Position{x: 1, y: 0});
+        let gone = prefab.spawn_entity();
+        prefab.remove_entity(gone);
+        prefab.set_parent(child, root);
+        prefab.relate::<Owns>(root, child);
+
+        let map = world.merge(prefab);
+        assert_eq!(map.len(), 2);
+        let (new_root, new_child) = (map.get(root).unwrap(), map.get(child).unwrap());
+        assert!(world.is_alive(new_root));
+        assert_eq!(world.parent_of(new_child), Some(new_root));
+        assert_eq!(world.parent_of(existing), Some(other_root));
+        assert_eq!(world.relations::<Owns>(new_root), &[new_child]);
+        assert_eq!(world.get::<Position>(new_child).unwrap().x, 1);
+    }
+
+    #[test]
+    fn test_merge_weak_references() {
+        use map::{EntityMap, MapEntities};
+        use save::CollisionPolicy;
+        use weak::EntityRefWeak;
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Target(EntityRefWeak);
+
+        impl MapEntities for Target {
+            fn map_entities(&mut self, map: &EntityMap) {
+                self.0.map_entities(map);
             }
-            None => assert!(false)
         }
 
-        match pool.get::<Velocity>(id) {
-            Some(vel) => {
-                assert_eq!(vel.x, 3);
-                assert_eq!(vel.y, 4);
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Target, target, HashMapStorage, map_entities)
+        );
+        let mut prefab = SpawningPool::new();
+        let enemy = spawn_with!(prefab, Position{x: 0, y: 0});
+        let weak = prefab.downgrade(enemy);
+        let hunter = spawn_with!(prefab, Target(weak));
+        let stale = spawn_with!(prefab, Target(EntityRefWeak::new(enemy, 3)));
+        let mut bytes = vec![];
+        prefab.save(&mut bytes).unwrap();
+
+        let mut world = SpawningPool::new();
+        spawn_with!(world, Position{x: 9, y: 9});
+        let map = world.merge(prefab);
+        let (new_hunter, new_stale) = (map.get(hunter).unwrap(), map.get(stale).unwrap());
+        assert_eq!(world.upgrade(world.get::<Target>(new_hunter).unwrap().0), map.get(enemy));
+        assert_eq!(world.upgrade(world.get::<Target>(new_stale).unwrap().0), None);
+
+        let mut world = SpawningPool::new();
+        let dead = world.spawn_entity();
+        world.remove_entity(dead);
+        world.cleanup_removed();
+        assert_eq!(dead, enemy);
+        world.load_into(&bytes[..], CollisionPolicy::Error).unwrap();
+        assert!(world.downgrade(enemy).generation() > 0);
+        assert_eq!(world.upgrade(world.get::<Target>(hunter).unwrap().0), Some(enemy));
+        assert_eq!(world.upgrade(world.get::<Target>(stale).unwrap().0), None);
+    }
+
+    #[test]
+    fn test_transfer_entity() {
+        use map::{EntityMap, MapEntities};
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Home(EntityId);
+
+        impl MapEntities for Home {
+            fn map_entities(&mut self, map: &EntityMap) {
+                self.0.map_entities(map);
             }
-            None => assert!(false)
         }
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Home, home, HashMapStorage, map_entities)
+        );
+        let mut overworld = SpawningPool::new();
+        let mut dungeon = SpawningPool::new();
+        spawn_with!(dungeon, Position{x: 9, y: 9});
+
+        let hero = spawn_with!(overworld, Position{x: 1, y: 2});
+        overworld.set(hero, Home(hero));
+        let uuid = overworld.ensure_uuid(hero).unwrap();
+
+        let new = overworld.transfer_entity(hero, &mut dungeon).unwrap();
+        assert!(overworld.get::<Position>(hero).is_none());
+        assert!(overworld.transfer_entity(hero, &mut dungeon).is_none());
+        overworld.cleanup_removed();
+        assert_eq!(overworld.count::<Position>(), 0);
+        assert_eq!(dungeon.get::<Position>(new).unwrap().x, 1);
+        assert_eq!(dungeon.get::<Home>(new).unwrap().0, new);
+        assert_eq!(dungeon.entity_by_uuid(&uuid), Some(new));
+
+        let count = dungeon.entity_count();
+        assert!(overworld.transfer_entity(hero, &mut dungeon).is_none());
+        assert!(overworld.transfer_entity(EntityId::from_raw(100).unwrap(), &mut dungeon).is_none());
+        assert_eq!(dungeon.entity_count(), count);
+    }
+
+    #[test]
+    fn test_hierarchy_despawn() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let root = pool.spawn_entity();
+        let arm = pool.spawn_entity();
+        let hand = pool.spawn_entity();
+        let leg = pool.spawn_entity();
+        assert!(pool.set_parent(arm, root));
+        assert!(pool.set_parent(hand, arm));
+        assert!(pool.set_parent(leg, root));
+        assert!(!pool.set_parent(root, hand));
+        assert_eq!(pool.children_of(root), &[arm, leg]);
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let mut copy: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(copy.parent_of(hand), Some(arm));
+
+        pool.despawn_and_reparent(arm);
+        assert_eq!(pool.parent_of(hand), Some(root));
+        assert_eq!(pool.children_of(root), &[leg, hand]);
+
+        pool.despawn_and_detach(root);
+        pool.cleanup_removed();
+        assert_eq!(pool.parent_of(leg), None);
+        assert_eq!(pool.parent_of(hand), None);
+
+        copy.despawn_recursive(arm);
+        assert_eq!(copy.pending_removals().collect::<Vec<_>>(), vec![arm, hand]);
+        copy.cleanup_removed();
+        assert_eq!(copy.children_of(root), &[leg]);
+    }
+
+    #[test]
+    fn test_relations() {
+        relation!(struct Owns);
+        relation!(struct Targets);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let player = pool.spawn_entity();
+        let sword = pool.spawn_entity();
+        let shield = pool.spawn_entity();
+        let orc = pool.spawn_entity();
+        assert!(pool.relate::<Owns>(player, sword));
+        assert!(!pool.relate::<Owns>(player, sword));
+        pool.relate::<Owns>(player, shield);
+        pool.relate::<Targets>(orc, player);
+        assert_eq!(pool.relations::<Owns>(player), &[sword, shield]);
+        assert_eq!(pool.related_to::<Targets>(player), &[orc]);
+        assert!(pool.relations::<Targets>(player).is_empty());
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert!(loaded.is_related::<Owns>(player, shield));
+        assert_eq!(loaded.related_to::<Owns>(sword), &[player]);
+
+        assert!(pool.unrelate::<Owns>(player, sword));
+        pool.remove_entity(player);
+        pool.cleanup_removed();
+        assert!(pool.related_to::<Owns>(shield).is_empty());
+        assert!(pool.relations::<Targets>(orc).is_empty());
+    }
+
+    #[test]
+    fn test_relation_traversal() {
+        relation!(struct ChildOf);
+        relation!(struct ConnectedTo);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let ids: Vec<EntityId> = (0..5).map(|_| pool.spawn_entity()).collect();
+        pool.relate::<ChildOf>(ids[1], ids[0]);
+        pool.relate::<ChildOf>(ids[2], ids[0]);
+        pool.relate::<ChildOf>(ids[3], ids[1]);
+        assert_eq!(pool.descendants::<ChildOf>(ids[0]), vec![ids[1], ids[2], ids[3]]);
+        assert!(pool.descendants::<ChildOf>(ids[3]).is_empty());
+
+        for i in 0..5 {
+            pool.relate::<ConnectedTo>(ids[i], ids[(i + 1) % 5]);
+        }
+        assert_eq!(pool.reachable::<ConnectedTo>(ids[0], 2), vec![ids[1], ids[2]]);
+        assert_eq!(pool.reachable::<ConnectedTo>(ids[0], 100), vec![ids[1], ids[2], ids[3], ids[4]]);
+    }
+
+    #[test]
+    fn test_events() {
+        use events::EventReader;
+
+        #[derive(Debug, PartialEq)]
+        struct Hit(u32);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let mut early = EventReader::default();
+        assert_eq!(pool.events::<Hit>().read(&mut early).count(), 0);
+
+        pool.emit(Hit(1));
+        let mut late = pool.events::<Hit>().reader();
+        pool.emit(Hit(2));
+        assert_eq!(pool.events::<Hit>().read(&mut late).collect::<Vec<_>>(), vec![&Hit(2)]);
+
+        pool.update_events();
+        pool.emit(Hit(3));
+        assert_eq!(pool.events::<Hit>().read(&mut early).collect::<Vec<_>>(), vec![&Hit(1), &Hit(2), &Hit(3)]);
+
+        pool.update_events();
+        assert_eq!(pool.events::<Hit>().len(), 1);
+        let mut fresh = EventReader::default();
+        assert_eq!(pool.events::<Hit>().read(&mut fresh).collect::<Vec<_>>(), vec![&Hit(3)]);
+        assert_eq!(pool.events::<Hit>().read(&mut late).collect::<Vec<_>>(), vec![&Hit(3)]);
+    }
+
+    #[test]
+    fn test_watch_query() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        pool.set(a, Position { x: 0, y: 0 });
+        pool.set(a, Velocity { x: 1, y: 0 });
+        let b = pool.spawn_entity();
+        pool.set(b, Position { x: 0, y: 0 });
+
+        let moving = pool.watch_query::<(Position, Velocity)>();
+        assert_eq!(pool.live_entities(&moving).collect::<Vec<_>>(), vec![a]);
+
+        pool.set(b, Velocity { x: 0, y: 1 });
+        assert_eq!(pool.live_entities(&moving).collect::<Vec<_>>(), vec![a, b]);
+        pool.remove::<Velocity>(a);
+        assert_eq!(pool.live_entities(&moving).collect::<Vec<_>>(), vec![b]);
+
+        pool.remove_entity(b);
+        assert_eq!(pool.live_entities(&moving).len(), 0);
+        pool.cancel_removal(b);
+        assert_eq!(pool.live_entities(&moving).collect::<Vec<_>>(), vec![b]);
+
+        pool.unwatch_query(moving);
+        let placed = pool.watch_query::<(Position,)>();
+        assert_eq!(pool.live_entities(&placed).collect::<Vec<_>>(), vec![a, b]);
+    }
+
+    #[test]
+    fn test_dynamic_pool() {
+        use dynamic::DynamicPool;
+        use save::SaveError;
+
+        let mut pool = DynamicPool::new();
+        pool.register_with::<Position, VectorStorage<Position>>("Position");
+        pool.register::<Velocity>("Velocity");
+        let a = pool.spawn_entity();
+        pool.set(a, Position { x: 1, y: 2 });
+        pool.set(a, Velocity { x: 3, y: 4 });
+        let b = pool.spawn_entity();
+        pool.set(b, Position { x: 0, y: 0 });
+        pool.get_mut::<Position>(b).unwrap().x = 5;
+        assert_eq!(pool.get::<Position>(b).unwrap().x, 5);
+        assert_eq!(pool.count::<Velocity>(), 1);
+
+        pool.remove_entity(a);
+        assert!(pool.get::<Position>(a).is_none());
+        let destroyed = pool.cleanup_removed();
+        assert_eq!(destroyed[0].components, vec!["Position", "Velocity"]);
+        assert_eq!(pool.entity_count(), 1);
+
+        let mut bytes = vec![];
+        pool.save(&mut bytes).unwrap();
+        let mut loaded = DynamicPool::new();
+        loaded.register::<Velocity>("Velocity");
+        loaded.register_with::<Position, VectorStorage<Position>>("Position");
+        loaded.load(&bytes[..]).unwrap();
+        assert_eq!(loaded.get::<Position>(b).unwrap().x, 5);
+        assert!(!loaded.is_alive(a));
+        assert_eq!(loaded.spawn_entity().to_raw(), 3);
+
+        let mut other = DynamicPool::new();
+        other.register::<Position>("Position");
+        match other.load(&bytes[..]) {
+            Err(SaveError::SchemaMismatch { .. }) => {},
+            result => panic!("expected a schema mismatch, got {:?}", result)
+        }
+    }
+
+    #[test]
+    fn test_hybrid_pool() {
+        #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+        struct Mana(u32);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        pool.register_dynamic::<Mana>("Mana");
+        let a = pool.spawn_entity();
+        pool.set_any(a, Position { x: 1, y: 2 });
+        pool.set_any(a, Mana(10));
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 1);
+        pool.get_any_mut::<Position>(a).unwrap().x = 4;
+        pool.get_any_mut::<Mana>(a).unwrap().0 -= 3;
+        assert_eq!(pool.get_any::<Position>(a).unwrap().x, 4);
+        assert_eq!(pool.get_any::<Mana>(a), Some(&Mana(7)));
+        assert_eq!(pool.dynamic().count::<Mana>(), 1);
+
+        let fork = pool.fork();
+        pool.remove_any::<Mana>(a);
+        assert!(pool.get_any::<Mana>(a).is_none());
+        assert_eq!(fork.get_any::<Mana>(a), Some(&Mana(7)));
+
+        let b = pool.spawn_entity();
+        pool.set_any(b, Mana(1));
+        pool.remove_entity(b);
+        assert!(pool.get_any::<Mana>(b).is_none());
+        let destroyed = pool.cleanup_removed();
+        assert_eq!(destroyed[0].components, vec!["Mana"]);
+        assert_eq!(pool.dynamic().count::<Mana>(), 0);
+    }
+
+    #[test]
+    fn test_copy_components() {
+        #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+        struct Mana(u32);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        pool.register_dynamic::<Mana>("Mana");
+        let template = pool.spawn_entity();
+        pool.set(template, Position { x: 1, y: 2 });
+        pool.set(template, Velocity { x: 3, y: 4 });
+        pool.set_any(template, Mana(5));
+
+        let copy = pool.spawn_entity();
+        pool.set(copy, Position { x: 9, y: 9 });
+        pool.copy_components(template, copy);
+        assert_eq!(pool.get::<Position>(copy).unwrap().x, 1);
+        assert_eq!(pool.get::<Velocity>(copy).unwrap().y, 4);
+        assert_eq!(pool.get_any::<Mana>(copy), Some(&Mana(5)));
+
+        let corpse = pool.spawn_entity();
+        pool.copy_component::<Position>(template, corpse);
+        assert_eq!(pool.get::<Position>(corpse).unwrap().y, 2);
+        assert!(pool.get::<Velocity>(corpse).is_none());
+
+        pool.remove_entity(template);
+        pool.copy_component::<Velocity>(template, corpse);
+        assert!(pool.get::<Velocity>(corpse).is_none());
+    }
+
+    #[test]
+    fn test_double_buffered() {
+        use buffered::DoubleBuffered;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 0, y: 0}, Velocity{x: 1, y: 2});
+        let mut frames = DoubleBuffered::new(pool);
+
+        for _ in 0..2 {
+            let (current, next) = frames.current_and_next_mut();
+            for (id, vel) in current.get_all::<Velocity>() {
+                let pos = current.get::<Position>(id).unwrap();
+                next.set(id, Position { x: pos.x + vel.x, y: pos.y + vel.y });
+            }
+            frames.swap();
+        }
+        assert_eq!(frames.current().get::<Position>(a).unwrap().y, 4);
+
+        let b = frames.next_mut().spawn_entity();
+        frames.next_mut().remove_entity(a);
+        assert!(frames.current().is_alive(a));
+        assert!(!frames.current().is_alive(b));
+        frames.swap();
+        assert!(!frames.current().is_alive(a));
+        assert!(frames.next().is_alive(b));
+        assert_eq!(frames.into_current().entity_count(), 1);
+    }
+
+    #[test]
+    fn test_interest() {
+        use interest::InterestFilter;
+
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage, replicated),
+            (Velocity, vel, VectorStorage, replicated)
+        );
+        let mut server = SpawningPool::new();
+        let a = spawn_with!(server, Position{x: 1, y: 0});
+        let b = spawn_with!(server, Position{x: 50, y: 0}, Velocity{x: 1, y: 0});
+        server.add_peer(1);
+        server.add_peer(2);
+        server.set_interest(1, InterestFilter::predicate(|pool: &SpawningPool, id| {
+            pool.get::<Position>(id).is_some_and(|pos| pos.x < 10)
+        }));
+        assert_eq!(server.relevant_entities(1), Some(vec![a]));
+        assert_eq!(server.relevant_entities(2), None);
+
+        let packet = server.collect_updates(1);
+        assert!(packet.updates.iter().all(|update| update.entity == a));
+        assert_eq!(server.collect_updates(2).updates.len(), 3);
+
+        server.set(b, Position{x: 5, y: 0});
+        let packet = server.collect_updates(1);
+        assert_eq!(packet.updates.len(), 2);
+        assert!(packet.updates.iter().all(|update| update.entity == b));
+
+        server.get_mut::<Position>(a).unwrap().x = 20;
+        server.refresh_interests();
+        server.remove_entity(b);
+        let packet = server.collect_updates(1);
+        assert!(packet.updates.is_empty());
+        assert_eq!(packet.despawned, vec![a, b]);
+        assert_eq!(server.relevant_entities(1), Some(vec![]));
+
+        let c = spawn_with!(server, Position{x: 0, y: 0});
+        assert_eq!(server.collect_updates(1).updates[0].entity, c);
+        server.remove_interest(1);
+        let packet = server.collect_updates(1);
+        assert_eq!(packet.updates.iter().map(|update| update.entity).collect::<Vec<_>>(), vec![a]);
+        assert_eq!(server.relevant_entities(1), None);
+    }
+
+    #[test]
+    fn test_query_cache() {
+        use std::sync::Arc;
+        use query::Query;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position { x: 0, y: 0 }, Velocity { x: 1, y: 0 });
+        let b = spawn_with!(pool, Position { x: 0, y: 0 });
+        pool.cache_queries(true);
+
+        let first = pool.query_entities::<(Position, Velocity)>();
+        assert_eq!(*first, vec![a]);
+        pool.get_mut::<Position>(a).unwrap().x = 5;
+        assert!(Arc::ptr_eq(&first, &pool.query_entities::<(Position, Velocity)>()));
+
+        pool.set(b, Velocity { x: 0, y: 1 });
+        assert_eq!(*pool.query_entities::<(Position, Velocity)>(), vec![a, b]);
+        let mut visited = vec![];
+        pool.run(|mut q: Query<SpawningPool, (&mut Position, &Velocity)>| q.for_each(|id, (pos, vel)| {
+            pos.x += vel.x;
+            visited.push(id);
+        }));
+        assert_eq!(visited, vec![a, b]);
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 6);
+
+        pool.remove_entity(a);
+        assert_eq!(*pool.query_entities::<(Position, Velocity)>(), vec![b]);
+        pool.cancel_removal(a);
+        pool.remove::<Velocity>(b);
+        assert_eq!(*pool.query_entities::<(Velocity, Position)>(), vec![a]);
+        let mut visited = vec![];
+        pool.run(|mut q: Query<SpawningPool, (&Velocity, &Position)>| q.for_each(|id, _| visited.push(id)));
+        assert_eq!(visited, vec![a]);
+
+        pool.cache_queries(false);
+        pool.set(b, Velocity { x: 0, y: 1 });
+        assert_eq!(*pool.query_entities::<(Position, Velocity)>(), vec![a, b]);
+    }
+
+    #[test]
+    fn test_drain() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position { x: 1, y: 0 }, Velocity { x: 1, y: 1 });
+        let b = spawn_with!(pool, Velocity { x: 2, y: 2 });
+        let c = spawn_with!(pool, Velocity { x: 3, y: 3 });
+        let moving = pool.watch_query::<(Velocity,)>();
+        pool.remove_entity(c);
+
+        let drained: Vec<_> = pool.drain::<Velocity>().map(|(id, vel)| (id, vel.x)).collect();
+        assert_eq!(drained, vec![(a, 1), (b, 2)]);
+        assert_eq!(pool.count::<Velocity>(), 0);
+        assert_eq!(pool.live_entities(&moving).len(), 0);
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 1);
+        assert_eq!(pool.drain::<Velocity>().count(), 0);
+
+        pool.cancel_removal(c);
+        assert_eq!(pool.get::<Velocity>(c).unwrap().x, 3);
+
+        let mut storage = VectorStorage::new();
+        storage.set(b, Position { x: 2, y: 0 });
+        storage.set(a, Position { x: 1, y: 0 });
+        assert_eq!(storage.drain().iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![a, b]);
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn test_spawn_default() {
+        #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+        struct Health(u32);
+        #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+        struct Inventory(Vec<u32>);
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Health, health, HashMapStorage, default),
+            (Inventory, inventory, HashMapStorage, replicated, default)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_default();
+        assert_eq!(pool.get::<Health>(id), Some(&Health(0)));
+        assert_eq!(pool.get::<Inventory>(id), Some(&Inventory(vec![])));
+        assert!(pool.get::<Position>(id).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "strict")]
+    #[should_panic(expected = "Position> on entity 1, which is pending removal")]
+    fn test_strict_set_removed() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = pool.spawn_entity();
+        pool.remove_entity(a);
+        pool.set(a, Position{x: 0, y: 0});
+    }
+
+    #[test]
+    #[cfg(feature = "strict")]
+    #[should_panic(expected = "on entity 7, which was never spawned")]
+    fn test_strict_get_mut_unknown() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut pool = SpawningPool::new();
+        pool.spawn_entity();
+        pool.get_mut::<Position>(EntityId::from_raw(7).unwrap());
+    }
+
+    #[test]
+    fn test_load_into() {
+        use map::{EntityMap, MapEntities};
+        use save::{CollisionPolicy, SaveError};
+
+        #[derive(Clone, Debug, Serialize, Deserialize)]
+        struct Follows(EntityId);
+
+        impl MapEntities for Follows {
+            fn map_entities(&mut self, map: &EntityMap) {
+                self.0.map_entities(map);
+            }
+        }
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Follows, follows, HashMapStorage, map_entities)
+        );
+        let mut save = SpawningPool::new();
+        let leader = spawn_with!(save, Position{x: 1, y: 0});
+        let follower = spawn_with!(save, Position{x: 2, y: 0}, Follows(leader));
+        let skipped = save.spawn_entity();
+        save.remove_entity(skipped);
+        let far = spawn_with!(save, Position{x: 4, y: 0});
+        let mut bytes = vec![];
+        save.save(&mut bytes).unwrap();
+
+        let mut world = SpawningPool::new();
+        let existing = spawn_with!(world, Position{x: 9, y: 9});
+        match world.load_into(&bytes[..], CollisionPolicy::Error) {
+            Err(SaveError::IdCollision(ids)) => assert_eq!(ids, vec![leader]),
+            _ => panic!("collision not detected")
+        }
+        assert_eq!(world.entity_count(), 1);
+
+        let report = world.load_into(&bytes[..], CollisionPolicy::Remap).unwrap();
+        assert_eq!(report.collisions, vec![existing]);
+        let moved = report.remapped.get(leader).unwrap();
+        assert!(moved != existing && moved != follower && moved != far);
+        assert_eq!(world.get::<Position>(existing).unwrap().x, 9);
+        assert_eq!(world.get::<Position>(moved).unwrap().x, 1);
+        assert_eq!(world.get::<Follows>(follower).unwrap().0, moved);
+        assert_eq!(world.get::<Position>(far).unwrap().x, 4);
+        assert!(!world.is_alive(skipped));
+        assert_eq!(world.entity_count(), 4);
+
+        let mut world = SpawningPool::new();
+        spawn_with!(world, Position{x: 9, y: 9}, Follows(existing));
+        let report = world.load_into(&bytes[..], CollisionPolicy::Overwrite).unwrap();
+        assert!(report.remapped.is_empty());
+        assert_eq!(world.get::<Position>(leader).unwrap().x, 1);
+        assert!(world.get::<Follows>(leader).is_none());
+        assert_eq!(world.get::<Follows>(follower).unwrap().0, leader);
+        assert_eq!(world.entity_count(), 3);
+    }
+
+    #[test]
+    fn test_load_into_huge_id() {
+        use super::RawEntityId;
+        use save::CollisionPolicy;
+
+        create_spawning_pool!(
+            (Position, pos, VectorStorage)
+        );
+        let mut save = SpawningPool::new();
+        *save.next_id.get_mut() = RawEntityId::MAX - 1;
+        save.destroyed.insert_range(EntityId::from_raw(1).unwrap(), EntityId::from_raw(RawEntityId::MAX - 2).unwrap());
+        let far = save.spawn_entity();
+        let mut bytes = vec![];
+        save.save(&mut bytes).unwrap();
+
+        let mut world = SpawningPool::new();
+        let existing = spawn_with!(world, Position{x: 4, y: 0});
+        world.load_into(&bytes[..], CollisionPolicy::Error).unwrap();
+        assert_eq!(world.entities().collect::<Vec<_>>(), vec![existing, far]);
+        assert_eq!(world.entity_count(), 2);
+        assert_eq!(world.destroyed.runs(), 1);
+    }
+
+    #[test]
+    fn test_accessors() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage, accessors),
+            (Velocity, vel, HashMapStorage, replicated, accessors)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        pool.set_pos(id, Position{x: 1, y: 2});
+        pool.set_vel(id, Velocity{x: 3, y: 4});
+        pool.pos_mut(id).unwrap().x += 10;
+        assert_eq!(pool.pos(id).unwrap().x, 11);
+        assert_eq!(pool.vel(id).unwrap().y, 4);
+        let bare = pool.spawn_entity();
+        assert!(pool.vel(bare).is_none());
+    }
+
+    #[test]
+    fn test_get_many() {
+        create_spawning_pool!(
+            (Position, pos, VectorStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let a = spawn_with!(pool, Position{x: 1, y: 0});
+        let b = spawn_with!(pool, Velocity{x: 2, y: 0});
+        let c = spawn_with!(pool, Position{x: 3, y: 0});
+        let d = spawn_with!(pool, Position{x: 4, y: 0});
+        pool.remove_entity(c);
+        pool.remove_entity(d);
+        pool.cleanup_removed();
+        let e = spawn_with!(pool, Position{x: 5, y: 0});
+        pool.remove_entity(e);
+        let never = EntityId::from_raw(100).unwrap();
+
+        let xs: Vec<_> = pool.get_many::<Position>(&[b, a, c, never, a, e]).iter().map(|p| p.map(|p| p.x)).collect();
+        assert_eq!(xs, vec![None, Some(1), None, None, Some(1), None]);
+        assert_eq!(pool.get_many_iter::<Velocity, _>(vec![a, b]).map(|v| v.is_some()).collect::<Vec<_>>(), vec![false, true]);
+        assert!(pool.get_many::<Position>(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_get_all_sorted() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let ids: Vec<_> = (0..20).map(|i| spawn_with!(pool, Position{x: i % 3, y: i})).collect();
+        pool.remove_entity(ids[4]);
+
+        let sorted: Vec<_> = pool.get_all_sorted::<Position>().iter().map(|(id, _)| *id).collect();
+        let expected: Vec<_> = ids.iter().cloned().filter(|id| *id != ids[4]).collect();
+        assert_eq!(sorted, expected);
+
+        let by_x: Vec<_> = pool.get_all_sorted_by::<Position, _>(|pos| pos.x).iter().map(|(_, pos)| (pos.x, pos.y)).collect();
+        assert_eq!(by_x[..3], [(0, 0), (0, 3), (0, 6)]);
+        assert_eq!(by_x.last(), Some(&(2, 17)));
+        assert!(by_x.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_history_storage() {
+        create_spawning_pool!(
+            (Position, pos, HistoryStorage),
+            (Velocity, vel, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        pool.storage_mut::<Position>().set_history_len(3);
+        let a = pool.spawn_entity();
+        for x in 0..5 {
+            pool.set(a, Position{x, y: 0});
+        }
+        pool.get_mut::<Position>(a).unwrap().y = 1;
+        let xs: Vec<_> = pool.history::<Position>(a).unwrap().iter().map(|pos| (pos.x, pos.y)).collect();
+        assert_eq!(xs, vec![(2, 0), (3, 0), (4, 1)]);
+        assert_eq!(pool.get::<Position>(a).unwrap().x, 4);
+
+        let json = ::serde_json::to_string(&pool).unwrap();
+        let loaded: SpawningPool = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.history::<Position>(a).unwrap().len(), 1);
+        assert_eq!(loaded.get::<Position>(a).unwrap().y, 1);
+
+        pool.remove::<Position>(a);
+        assert!(pool.history::<Position>(a).is_none());
+        pool.set(a, Position{x: 7, y: 0});
+        pool.remove_entity(a);
+        assert!(pool.history::<Position>(a).is_none());
     }
 }
@@ -0,0 +1,27 @@
+//!
+//! Hooks for components that manage resources of their own
+//!
+
+use super::{EntityId};
+
+///
+/// Lifecycle callbacks for components flagged `lifecycle` in `create_spawning_pool!`
+///
+/// `on_added` runs right before the component is stored by `set`, `on_removed` receives the
+/// component when it is replaced by `set`, removed by `remove` or dropped by `cleanup_removed`.
+///
+pub trait ComponentLifecycle {
+    fn on_added(&mut self, _id: EntityId) {}
+
+    fn on_removed(self, _id: EntityId) where Self: Sized {}
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spawning_pool_if_lifecycle {
+    ([] $then:block else $otherwise:block) => { $otherwise };
+    ([lifecycle $(, $rest:ident)*] $then:block else $otherwise:block) => { $then };
+    ([$other:ident $(, $rest:ident)*] $then:block else $otherwise:block) => {
+        $crate::__spawning_pool_if_lifecycle!([$($rest),*] $then else $otherwise)
+    };
+}
@@ -0,0 +1,137 @@
+//!
+//! Live queries, sets of entities matching a component tuple that the pool keeps up to date
+//!
+//! The matches of a watched query are updated by `set`, `remove`, `remove_entity` and
+//! `cancel_removal` as they happen, so reading them costs nothing no matter how large the world
+//! is. Changes made through `storage_mut` or `split` bypass the pool and aren't seen.
+//!
+
+use std::collections::BTreeSet;
+use std::fmt;
+use map::{EntityMap, MapEntities};
+use super::{EntityId};
+
+///
+/// Handle to a query watched with `watch_query`
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LiveQuery {
+    index: usize
+}
+
+struct Watched<P> {
+    /// Indices of the components in the query
+    components: Vec<usize>,
+    matches: fn(&P, EntityId) -> bool,
+    entities: BTreeSet<EntityId>
+}
+
+impl<P> Clone for Watched<P> {
+    fn clone(&self) -> Self {
+        Watched {
+            components: self.components.clone(),
+            matches: self.matches,
+            entities: self.entities.clone()
+        }
+    }
+}
+
+///
+/// Every query watched on pool `P`
+///
+pub struct LiveQueries<P> {
+    queries: Vec<Option<Watched<P>>>
+}
+
+impl<P> Default for LiveQueries<P> {
+    fn default() -> Self {
+        LiveQueries { queries: vec![] }
+    }
+}
+
+impl<P> Clone for LiveQueries<P> {
+    fn clone(&self) -> Self {
+        LiveQueries { queries: self.queries.clone() }
+    }
+}
+
+impl<P> fmt::Debug for LiveQueries<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.queries.iter().flatten().map(|q| (&q.components, q.entities.len())))
+            .finish()
+    }
+}
+
+impl<P> LiveQueries<P> {
+    /// Starts watching the query made of `components`, with its current matches
+    pub fn watch(&mut self, components: Vec<usize>, matches: fn(&P, EntityId) -> bool, entities: BTreeSet<EntityId>) -> LiveQuery {
+        let watched = Watched { components, matches, entities };
+        let index = match self.queries.iter().position(Option::is_none) {
+            Some(index) => {
+                self.queries[index] = Some(watched);
+                index
+            },
+            None => {
+                self.queries.push(Some(watched));
+                self.queries.len() - 1
+            }
+        };
+        LiveQuery { index }
+    }
+
+    pub fn unwatch(&mut self, query: LiveQuery) {
+        if let Some(slot) = self.queries.get_mut(query.index) {
+            *slot = None;
+        }
+    }
+
+    /// Whether no query is watched, the pool skips all bookkeeping then
+    pub fn is_empty(&self) -> bool {
+        self.queries.iter().all(Option::is_none)
+    }
+
+    /// Matched entities of the query, in ascending order
+    pub fn entities(&self, query: &LiveQuery) -> &BTreeSet<EntityId> {
+        &self.queries[query.index].as_ref().expect("live query is no longer watched").entities
+    }
+
+    /// Checks the entity again against every query with component `index`
+    pub fn refresh(&mut self, pool: &P, id: EntityId, index: usize) {
+        for query in self.queries.iter_mut().flatten() {
+            if query.components.contains(&index) {
+                Self::update(query, pool, id);
+            }
+        }
+    }
+
+    /// Checks the entity again against every query
+    pub fn refresh_all(&mut self, pool: &P, id: EntityId) {
+        for query in self.queries.iter_mut().flatten() {
+            Self::update(query, pool, id);
+        }
+    }
+
+    /// Drops the entity from every query
+    pub fn remove_entity(&mut self, id: EntityId) {
+        for query in self.queries.iter_mut().flatten() {
+            query.entities.remove(&id);
+        }
+    }
+
+    fn update(query: &mut Watched<P>, pool: &P, id: EntityId) {
+        if (query.matches)(pool, id) {
+            query.entities.insert(id);
+        } else {
+            query.entities.remove(&id);
+        }
+    }
+}
+
+impl<P> MapEntities for LiveQueries<P> {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for query in self.queries.iter_mut().flatten() {
+            query.entities = query.entities.iter().filter_map(|id| map.get(*id)).collect();
+        }
+    }
+}
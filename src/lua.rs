@@ -0,0 +1,131 @@
+//!
+//! Lua bindings through mlua, enabled with the `lua` feature
+//!
+//! Wrap a pool in `LuaPool` and hand it to Lua as userdata. Entity ids are plain integers on the
+//! Lua side. Scripts then get these methods:
+//!
+//! * `pool:spawn()` returns a new entity id
+//! * `pool:despawn(id)` marks the entity for removal
+//! * `pool:cleanup()` removes all entities marked for removal, returns the destroyed ids
+//! * `pool:entities()` returns a list of live entity ids
+//! * `pool:get(id, "Pos")` returns the component as a table, or nil
+//! * `pool:set(id, "Pos", { x = 1, y = 2 })` attaches the component to the entity
+//!
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use mlua::{self, LuaSerdeExt, UserData, UserDataMethods};
+use serde_json;
+use super::{EntityId, RawEntityId};
+use inspect::ErasedPool;
+
+///
+/// Userdata wrapper sharing a pool between Rust and Lua
+///
+pub struct LuaPool<P> {
+    pool: Rc<RefCell<P>>
+}
+
+impl<P> LuaPool<P> {
+    pub fn new(pool: Rc<RefCell<P>>) -> Self {
+        LuaPool {
+            pool
+        }
+    }
+
+    pub fn pool(&self) -> &Rc<RefCell<P>> {
+        &self.pool
+    }
+}
+
+fn entity_id(raw: RawEntityId) -> mlua::Result<EntityId> {
+    EntityId::from_raw(raw).ok_or_else(|| mlua::Error::RuntimeError("invalid entity id 0".to_string()))
+}
+
+impl<P: ErasedPool + 'static> UserData for LuaPool<P> {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("spawn", |_, this, ()| {
+            Ok(this.pool.borrow_mut().spawn_entity().to_raw())
+        });
+
+        methods.add_method("despawn", |_, this, id: RawEntityId| {
+            this.pool.borrow_mut().remove_entity(entity_id(id)?);
+            Ok(())
+        });
+
+        methods.add_method("cleanup", |_, this, ()| {
+            let destroyed: Vec<RawEntityId> = this.pool.borrow_mut().cleanup_removed()
+                .iter()
+                .map(|d| d.id.to_raw())
+                .collect();
+            Ok(destroyed)
+        });
+
+        methods.add_method("entities", |_, this, ()| {
+            let ids: Vec<RawEntityId> = this.pool.borrow().live_entity_ids().iter().map(|id| id.to_raw()).collect();
+            Ok(ids)
+        });
+
+        methods.add_method("get", |lua, this, (id, name): (RawEntityId, String)| {
+            match this.pool.borrow().component_value(entity_id(id)?, &name) {
+                Some(value) => lua.to_value(&value),
+                None => Ok(mlua::Value::Nil)
+            }
+        });
+
+        methods.add_method("set", |lua, this, (id, name, value): (RawEntityId, String, mlua::Value)| {
+            let value: serde_json::Value = lua.from_value(value)?;
+            this.pool.borrow_mut()
+                .set_component_value(entity_id(id)?, &name, value)
+                .map_err(mlua::Error::external)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mlua::Lua;
+    use storage::*;
+    use create_spawning_pool;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Position {
+        pub x: i32,
+        pub y: i32
+    }
+
+    #[test]
+    fn test_lua_access() {
+        create_spawning_pool!(
+            (Position, pos, HashMapStorage)
+        );
+        let pool = Rc::new(RefCell::new(SpawningPool::new()));
+        let lua = Lua::new();
+        lua.globals().set("pool", LuaPool::new(pool.clone())).unwrap();
+
+        let x: i32 = lua.load(r#"
+            local id = pool:spawn()
+            pool:set(id, "Position", { x = 4, y = 2 })
+            local pos = pool:get(id, "Position")
+            return pos.x
+        "#).eval().unwrap();
+        assert_eq!(x, 4);
+        assert_eq!(pool.borrow().get::<Position>(EntityId::from_raw(1).unwrap()).unwrap().y, 2);
+
+        let result: mlua::Result<()> = lua.load(r#"pool:set(1, "Health", 10)"#).exec();
+        assert!(result.is_err());
+
+        let count: usize = lua.load(r#"
+            pool:spawn()
+            return #pool:entities()
+        "#).eval().unwrap();
+        assert_eq!(count, 2);
+
+        let count: usize = lua.load(r#"
+            pool:despawn(1)
+            return #pool:entities()
+        "#).eval().unwrap();
+        assert_eq!(count, 1);
+    }
+}
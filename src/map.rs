@@ -0,0 +1,126 @@
+//!
+//! Remapping entity IDs stored inside components, for merging pools and compacting IDs
+//!
+//! Components flagged `map_entities` in `create_spawning_pool!` implement `MapEntities`, and
+//! `merge` hands them the map from the merged pool's IDs to the new ones so references between
+//! entities keep pointing at the same entities.
+//!
+
+use std::collections::HashMap;
+use super::EntityId;
+use weak::EntityRefWeak;
+
+///
+/// Map from entity IDs of one pool to the IDs the entities got in another
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EntityMap {
+    map: HashMap<EntityId, EntityId>,
+    /// Generations of the old and of the new ID, for pairs inserted with `insert_weak`
+    generations: HashMap<EntityId, (u32, u32)>
+}
+
+impl EntityMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn insert(&mut self, old: EntityId, new: EntityId) {
+        self.map.insert(old, new);
+    }
+
+    pub fn get(&self, old: EntityId) -> Option<EntityId> {
+        self.map.get(&old).cloned()
+    }
+
+    /// Maps an ID along with its generation in each pool, so weak references can be remapped
+    pub fn insert_weak(&mut self, old: EntityRefWeak, new: EntityRefWeak) {
+        self.map.insert(old.id(), new.id());
+        self.generations.insert(old.id(), (old.generation(), new.generation()));
+    }
+
+    /// The weak reference to the entity's new ID, `None` if the ID isn't mapped or `old` is stale.
+    /// Pairs inserted without generations keep the generation of `old`.
+    pub fn get_weak(&self, old: EntityRefWeak) -> Option<EntityRefWeak> {
+        let new = self.get(old.id())?;
+        match self.generations.get(&old.id()) {
+            Some(&(from, to)) if from == old.generation() => Some(EntityRefWeak::new(new, to)),
+            Some(_) => None,
+            None => Some(EntityRefWeak::new(new, old.generation()))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Mapped pairs sorted by old ID
+    pub fn pairs(&self) -> Vec<(EntityId, EntityId)> {
+        let mut pairs: Vec<_> = self.map.iter().map(|(old, new)| (*old, *new)).collect();
+        pairs.sort();
+        pairs
+    }
+}
+
+impl From<EntityMap> for HashMap<EntityId, EntityId> {
+    fn from(map: EntityMap) -> Self {
+        map.map
+    }
+}
+
+///
+/// Components holding entity IDs, updated with `map_entities` when the IDs change
+///
+/// IDs missing from the map point at entities that weren't moved along, implementations decide
+/// whether to keep, clear or drop them.
+///
+pub trait MapEntities {
+    fn map_entities(&mut self, map: &EntityMap);
+}
+
+impl MapEntities for EntityId {
+    /// Keeps the ID if it isn't in the map
+    fn map_entities(&mut self, map: &EntityMap) {
+        if let Some(new) = map.get(*self) {
+            *self = new;
+        }
+    }
+}
+
+impl MapEntities for Option<EntityId> {
+    /// Clears the ID if it isn't in the map
+    fn map_entities(&mut self, map: &EntityMap) {
+        *self = self.and_then(|id| map.get(id));
+    }
+}
+
+impl MapEntities for Vec<EntityId> {
+    /// Drops the IDs that aren't in the map
+    fn map_entities(&mut self, map: &EntityMap) {
+        *self = self.iter().filter_map(|id| map.get(*id)).collect();
+    }
+}
+
+impl MapEntities for EntityRefWeak {
+    /// Weak references to entities that weren't moved along, or that were already stale, keep
+    /// their old, now invalid, ID
+    fn map_entities(&mut self, map: &EntityMap) {
+        if let Some(new) = map.get_weak(*self) {
+            *self = new;
+        }
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spawning_pool_if_map_entities {
+    ([] $then:block else $otherwise:block) => { $otherwise };
+    ([map_entities $(, $rest:ident)*] $then:block else $otherwise:block) => { $then };
+    ([$other:ident $(, $rest:ident)*] $then:block else $otherwise:block) => {
+        $crate::__spawning_pool_if_map_entities!([$($rest),*] $then else $otherwise)
+    };
+}
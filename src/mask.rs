@@ -0,0 +1,75 @@
+//!
+//! Bitmasks of the component types an entity has, for archetype style checks without a storage
+//! lookup per component
+//!
+
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+
+///
+/// One bit per component type, in declaration order, so pools are limited to 64 components
+/// when using masks
+///
+/// Masks of single components come from the generated `SpawningPool::mask::<T>()`, which can be
+/// used to define constants.
+///
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ComponentMask(pub u64);
+
+impl ComponentMask {
+    pub const NONE: ComponentMask = ComponentMask(0);
+
+    /// Mask of the component at `index` in the macro invocation
+    pub const fn bit(index: usize) -> ComponentMask {
+        assert!(index < 64, "component masks hold at most 64 components");
+        ComponentMask(1 << index)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every component of `other` is set
+    pub fn contains(self, other: ComponentMask) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether any component of `other` is set
+    pub fn intersects(self, other: ComponentMask) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn count(self) -> u32 {
+        self.0.count_ones()
+    }
+}
+
+impl BitOr for ComponentMask {
+    type Output = ComponentMask;
+
+    fn bitor(self, other: ComponentMask) -> ComponentMask {
+        ComponentMask(self.0 | other.0)
+    }
+}
+
+impl BitAnd for ComponentMask {
+    type Output = ComponentMask;
+
+    fn bitand(self, other: ComponentMask) -> ComponentMask {
+        ComponentMask(self.0 & other.0)
+    }
+}
+
+impl Not for ComponentMask {
+    type Output = ComponentMask;
+
+    fn not(self) -> ComponentMask {
+        ComponentMask(!self.0)
+    }
+}
+
+impl fmt::Debug for ComponentMask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ComponentMask({:#b})", self.0)
+    }
+}
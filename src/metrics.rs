@@ -0,0 +1,115 @@
+//!
+//! Optional access counters for profiling which components a game touches the most
+//!
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+
+///
+/// Access counts for a single component type
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ComponentMetrics {
+    pub gets: u64,
+    pub sets: u64,
+    pub removes: u64,
+    /// Calls to `get_all` and other whole-storage iterations
+    pub iterations: u64
+}
+
+///
+/// Snapshot of the counters since they were last reset
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PoolMetrics {
+    pub components: Vec<(&'static str, ComponentMetrics)>,
+    pub cleanups: u64,
+    pub cleanup_time: Duration
+}
+
+impl PoolMetrics {
+    pub fn component(&self, name: &str) -> Option<&ComponentMetrics> {
+        self.components.iter().find(|(n, _)| *n == name).map(|(_, m)| m)
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub enum Access {
+    Get = 0,
+    Set = 1,
+    Remove = 2,
+    Iterate = 3
+}
+
+///
+/// Counters kept by the pool, disabled until `enable` is called so they cost a single relaxed
+/// load per access otherwise
+///
+#[derive(Debug, Default)]
+pub struct MetricsRecorder {
+    enabled: AtomicBool,
+    counters: Vec<[AtomicU64; 4]>,
+    cleanups: AtomicU64,
+    cleanup_nanos: AtomicU64
+}
+
+impl MetricsRecorder {
+    pub fn enable(&mut self, component_count: usize) {
+        while self.counters.len() < component_count {
+            self.counters.push(Default::default());
+        }
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn disable(&mut self) {
+        self.enabled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn record(&self, component: usize, access: Access) {
+        if self.is_enabled() {
+            if let Some(counters) = self.counters.get(component) {
+                counters[access as usize].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn record_cleanup(&self, time: Duration) {
+        if self.is_enabled() {
+            self.cleanups.fetch_add(1, Ordering::Relaxed);
+            self.cleanup_nanos.fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self, names: &[&'static str]) -> PoolMetrics {
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        PoolMetrics {
+            components: names.iter()
+                .zip(self.counters.iter())
+                .map(|(name, c)| (*name, ComponentMetrics {
+                    gets: load(&c[0]),
+                    sets: load(&c[1]),
+                    removes: load(&c[2]),
+                    iterations: load(&c[3])
+                }))
+                .collect(),
+            cleanups: load(&self.cleanups),
+            cleanup_time: Duration::from_nanos(load(&self.cleanup_nanos))
+        }
+    }
+
+    pub fn reset(&self) {
+        for counters in &self.counters {
+            for c in counters {
+                c.store(0, Ordering::Relaxed);
+            }
+        }
+        self.cleanups.store(0, Ordering::Relaxed);
+        self.cleanup_nanos.store(0, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,80 @@
+//!
+//! Traits implemented by every generated pool, for libraries that work with any of them
+//!
+//! Each `create_spawning_pool!` invocation makes its own type, so helpers take `P: Pool` and list
+//! the components they need as `HasComponent` bounds:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::EntityId;
+//! use spawning_pool::pool::{HasComponent, Pool};
+//! use spawning_pool::storage::{HashMapStorage, VectorStorage};
+//!
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Health(u32);
+//! # #[derive(Clone, Debug, Serialize, Deserialize)]
+//! # struct Pos(i32, i32);
+//!
+//! fn spawn_unit<P: Pool + HasComponent<Health>>(pool: &mut P, health: u32) -> EntityId {
+//!     let id = pool.spawn_entity();
+//!     pool.set(id, Health(health));
+//!     id
+//! }
+//!
+//! create_spawning_pool!(WorldPool; (Health, health, HashMapStorage), (Pos, pos, VectorStorage));
+//! create_spawning_pool!(MenuPool; (Health, health, VectorStorage));
+//! let mut world = WorldPool::new();
+//! let mut menu = MenuPool::new();
+//! let a = spawn_unit(&mut world, 5);
+//! let b = spawn_unit(&mut menu, 3);
+//! assert_eq!(world.get::<Health>(a).unwrap().0, 5);
+//! assert_eq!(menu.get::<Health>(b).unwrap().0, 3);
+//! # }
+//! ```
+//!
+
+use super::{EntityId};
+use cleanup::Destroyed;
+
+///
+/// Entity management shared by all generated pools, with component access for the components
+/// the pool has `HasComponent` for
+///
+pub trait Pool {
+    fn spawn_entity(&mut self) -> EntityId;
+    fn remove_entity(&mut self, id: EntityId);
+    fn cleanup_removed(&mut self) -> Vec<Destroyed>;
+    fn is_alive(&self, id: EntityId) -> bool;
+    fn entity_count(&self) -> usize;
+    /// Logically independent copy, sharing whatever the pool can share until it is modified
+    fn fork(&self) -> Self where Self: Sized;
+
+    fn get<T>(&self, id: EntityId) -> Option<&T> where Self: HasComponent<T> {
+        self.component(id)
+    }
+
+    fn get_mut<T>(&mut self, id: EntityId) -> Option<&mut T> where Self: HasComponent<T> {
+        self.component_mut(id)
+    }
+
+    fn set<T>(&mut self, id: EntityId, component: T) where Self: HasComponent<T> {
+        self.insert_component(id, component);
+    }
+
+    fn remove<T>(&mut self, id: EntityId) where Self: HasComponent<T> {
+        self.clear_component(id);
+    }
+}
+
+///
+/// Implemented by generated pools for every registered component type, behaves like the pool's
+/// own `get`, `get_mut`, `set` and `remove`
+///
+pub trait HasComponent<T> {
+    fn component(&self, id: EntityId) -> Option<&T>;
+    fn component_mut(&mut self, id: EntityId) -> Option<&mut T>;
+    fn insert_component(&mut self, id: EntityId, component: T);
+    fn clear_component(&mut self, id: EntityId);
+}
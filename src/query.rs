@@ -0,0 +1,231 @@
+//!
+//! Closure systems with declared component access, run with `pool.run`
+//!
+//! A system names the components it reads as `&T` and the ones it writes as `&mut T`:
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::EntityId;
+//! use spawning_pool::query::Query;
+//! use spawning_pool::storage::{Storage, VectorStorage};
+//!
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Pos(i32);
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Vel(i32);
+//!
+//! create_spawning_pool!(
+//!     (Pos, pos, VectorStorage),
+//!     (Vel, vel, VectorStorage)
+//! );
+//! let mut pool = SpawningPool::new();
+//! let id = spawn_with!(pool, Pos(1), Vel(2));
+//! pool.run(|mut q: Query<SpawningPool, (&mut Pos, &Vel)>| {
+//!     q.for_each(|_, (pos, vel)| pos.0 += vel.0);
+//! });
+//! assert_eq!(pool.get::<Pos>(id).unwrap().0, 3);
+//! # }
+//! ```
+//!
+//! The declared `Access` of two systems tells whether they could run at the same time.
+//!
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+use cache::CachedQueries;
+use super::{EntityId};
+
+///
+/// Components a query reads and writes, by position in the macro invocation
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Access {
+    pub reads: Vec<usize>,
+    pub writes: Vec<usize>
+}
+
+impl Access {
+    /// A component written more than once, or both read and written, can't be borrowed
+    pub fn is_valid(&self) -> bool {
+        self.writes.iter().enumerate().all(|(i, w)| {
+            !self.reads.contains(w) && !self.writes[i + 1..].contains(w)
+        })
+    }
+
+    /// Whether the two accesses touch a component that at least one of them writes
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        self.writes.iter().any(|w| other.reads.contains(w) || other.writes.contains(w))
+            || other.writes.iter().any(|w| self.reads.contains(w))
+    }
+}
+
+///
+/// Raw per-component access, implemented by generated pools for every registered component type
+///
+/// The functions only touch the pool's bookkeeping and the storage of `T`, which is what lets
+/// queries hand out references into several storages at once.
+///
+pub trait ComponentAccess<T> {
+    /// Position of the component in the macro invocation
+    const INDEX: usize;
+
+    /// Live entities having the component
+    fn entities(pool: &Self) -> Vec<EntityId>;
+
+    /// # Safety
+    /// `pool` must be valid, and the storage of `T` must not be mutably borrowed for `'b`.
+    unsafe fn read<'b>(pool: *const Self, id: EntityId) -> Option<&'b T>;
+
+    /// # Safety
+    /// `pool` must be valid, and the storage of `T` must not be borrowed at all for `'b`.
+    unsafe fn write<'b>(pool: *mut Self, id: EntityId) -> Option<&'b mut T>;
+}
+
+///
+/// Component references, or tuples of them, that a `Query` hands out for each entity
+///
+pub trait QueryData<P> {
+    type Item<'b> where Self: 'b;
+
+    fn declare(access: &mut Access);
+
+    /// Entities worth visiting, a superset of the ones having every component
+    fn candidates(pool: &P) -> Vec<EntityId>;
+
+    /// # Safety
+    /// `pool` must be valid and every element must pass `Access::is_valid` together.
+    unsafe fn fetch<'b>(pool: *mut P, id: EntityId) -> Option<Self::Item<'b>> where Self: 'b;
+}
+
+impl<P: ComponentAccess<T>, T> QueryData<P> for &T {
+    type Item<'b> = &'b T where Self: 'b;
+
+    fn declare(access: &mut Access) {
+        access.reads.push(P::INDEX);
+    }
+
+    fn candidates(pool: &P) -> Vec<EntityId> {
+        P::entities(pool)
+    }
+
+    unsafe fn fetch<'b>(pool: *mut P, id: EntityId) -> Option<&'b T> where Self: 'b {
+        P::read(pool, id)
+    }
+}
+
+impl<P: ComponentAccess<T>, T> QueryData<P> for &mut T {
+    type Item<'b> = &'b mut T where Self: 'b;
+
+    fn declare(access: &mut Access) {
+        access.writes.push(P::INDEX);
+    }
+
+    fn candidates(pool: &P) -> Vec<EntityId> {
+        P::entities(pool)
+    }
+
+    unsafe fn fetch<'b>(pool: *mut P, id: EntityId) -> Option<&'b mut T> where Self: 'b {
+        P::write(pool, id)
+    }
+}
+
+macro_rules! tuple_query {
+    ($first:ident $(, $name:ident)*) => {
+        impl<P, $first: QueryData<P> $(, $name: QueryData<P>)*> QueryData<P> for ($first, $($name,)*) {
+            type Item<'b> = ($first::Item<'b>, $($name::Item<'b>,)*) where Self: 'b;
+
+            fn declare(access: &mut Access) {
+                $first::declare(access);
+                $($name::declare(access);)*
+            }
+
+            fn candidates(pool: &P) -> Vec<EntityId> {
+                $first::candidates(pool)
+            }
+
+            unsafe fn fetch<'b>(pool: *mut P, id: EntityId) -> Option<Self::Item<'b>> where Self: 'b {
+                Some(($first::fetch(pool, id)?, $($name::fetch(pool, id)?,)*))
+            }
+        }
+    };
+}
+
+tuple_query!(A);
+tuple_query!(A, B);
+tuple_query!(A, B, C);
+tuple_query!(A, B, C, D);
+tuple_query!(A, B, C, D, E);
+tuple_query!(A, B, C, D, E, F);
+tuple_query!(A, B, C, D, E, F, G);
+tuple_query!(A, B, C, D, E, F, G, H);
+
+///
+/// Borrow of a pool giving access to the components declared in `Q`
+///
+pub struct Query<'a, P: 'a, Q> {
+    pool: *mut P,
+    access: Access,
+    borrow: PhantomData<&'a mut P>,
+    data: PhantomData<fn() -> Q>
+}
+
+impl<'a, P, Q: QueryData<P>> Query<'a, P, Q> {
+    /// Panics if `Q` borrows a component mutably more than once, or both mutably and immutably
+    pub fn new(pool: &'a mut P) -> Self {
+        let mut access = Access::default();
+        Q::declare(&mut access);
+        assert!(access.is_valid(), "query borrows a component both mutably and immutably");
+        Query {
+            pool,
+            access,
+            borrow: PhantomData,
+            data: PhantomData
+        }
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// Calls `f` for every live entity having all the queried components, in entity id order.
+    /// The matched entities come from the pool's query cache when it is enabled.
+    pub fn for_each<F>(&mut self, mut f: F) where F: for<'b> FnMut(EntityId, Q::Item<'b>), P: CachedQueries {
+        // SAFETY: `self` holds the only borrow of the pool, `f` can only write to existing
+        // components so the structure read here stays valid
+        let pool = unsafe { &*self.pool };
+        let cached = pool.query_cache().is_enabled().then(|| {
+            let mut components: Vec<usize> = self.access.reads.iter().chain(&self.access.writes).cloned().collect();
+            components.sort();
+            components.dedup();
+            let structure: Vec<u64> = components.iter().map(|&index| pool.structure(index)).collect();
+            (components, structure)
+        });
+        let hit = cached.as_ref().and_then(|(components, structure)| pool.query_cache().get(components, structure));
+        let mut ids = match hit {
+            Some(ref entities) => entities.to_vec(),
+            None => Q::candidates(pool)
+        };
+        ids.sort();
+        let mut matched = vec![];
+        for id in ids {
+            // SAFETY: the access was validated in `new`, and items of the previous entity are
+            // gone since `f` can't keep them
+            if let Some(item) = unsafe { Q::fetch(self.pool, id) } {
+                matched.push(id);
+                f(id, item);
+            }
+        }
+        if let (Some((components, structure)), None) = (cached, hit) {
+            // SAFETY: the items handed to `f` are gone
+            unsafe { &*self.pool }.query_cache().insert(components, structure, Arc::new(matched));
+        }
+    }
+
+    /// Components of a single entity, or `None` if any of them is missing
+    pub fn get(&mut self, id: EntityId) -> Option<Q::Item<'_>> {
+        // SAFETY: the item borrows `self` mutably, so no other item can be alive
+        unsafe { Q::fetch(self.pool, id) }
+    }
+}
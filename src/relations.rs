@@ -0,0 +1,233 @@
+//!
+//! Typed relations between entities, like ownership or targeting, dropped automatically when
+//! either side is destroyed
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::EntityId;
+//! use spawning_pool::storage::{Storage, VectorStorage};
+//!
+//! # #[derive(Clone, Debug, Serialize, Deserialize)]
+//! # struct Pos(i32, i32);
+//! relation!(pub struct Owns);
+//!
+//! create_spawning_pool!((Pos, pos, VectorStorage));
+//! let mut pool = SpawningPool::new();
+//! let player = pool.spawn_entity();
+//! let sword = pool.spawn_entity();
+//! pool.relate::<Owns>(player, sword);
+//! assert_eq!(pool.relations::<Owns>(player), &[sword]);
+//! assert_eq!(pool.related_to::<Owns>(sword), &[player]);
+//! # }
+//! ```
+//!
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use map::{EntityMap, MapEntities};
+use super::EntityId;
+
+///
+/// Marker type naming a kind of relation, its `NAME` identifies the relation in save files
+///
+pub trait Relation {
+    const NAME: &'static str;
+}
+
+///
+/// Declares a marker struct implementing `Relation`, named after the struct
+///
+#[macro_export]
+macro_rules! relation {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        $vis struct $name;
+
+        impl $crate::relations::Relation for $name {
+            const NAME: &'static str = stringify!($name);
+        }
+    };
+}
+
+///
+/// Pairs of one kind of relation, indexed in both directions
+///
+/// Serializes as a list of `(from, to)` pairs sorted by `from`.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<(EntityId, EntityId)>", into = "Vec<(EntityId, EntityId)>")]
+pub struct RelationStorage {
+    targets: HashMap<EntityId, Vec<EntityId>>,
+    sources: HashMap<EntityId, Vec<EntityId>>
+}
+
+fn unlink(map: &mut HashMap<EntityId, Vec<EntityId>>, key: EntityId, value: EntityId) {
+    if let Some(values) = map.get_mut(&key) {
+        values.retain(|id| *id != value);
+        if values.is_empty() {
+            map.remove(&key);
+        }
+    }
+}
+
+impl RelationStorage {
+    /// Adds the pair, returns `false` if it already existed
+    pub fn relate(&mut self, from: EntityId, to: EntityId) -> bool {
+        let targets = self.targets.entry(from).or_default();
+        if targets.contains(&to) {
+            return false;
+        }
+        targets.push(to);
+        self.sources.entry(to).or_default().push(from);
+        true
+    }
+
+    /// Removes the pair, returns `false` if it didn't exist
+    pub fn unrelate(&mut self, from: EntityId, to: EntityId) -> bool {
+        if !self.is_related(from, to) {
+            return false;
+        }
+        unlink(&mut self.targets, from, to);
+        unlink(&mut self.sources, to, from);
+        true
+    }
+
+    pub fn is_related(&self, from: EntityId, to: EntityId) -> bool {
+        self.targets(from).contains(&to)
+    }
+
+    /// Entities `from` is related to, in the order the pairs were added
+    pub fn targets(&self, from: EntityId) -> &[EntityId] {
+        self.targets.get(&from).map(|t| t.as_slice()).unwrap_or(&[])
+    }
+
+    /// Entities related to `to`, in the order the pairs were added
+    pub fn sources(&self, to: EntityId) -> &[EntityId] {
+        self.sources.get(&to).map(|s| s.as_slice()).unwrap_or(&[])
+    }
+
+    /// Entities reachable from `start` in at most `max_depth` steps, breadth first and without
+    /// `start` itself. Follows pairs from `from` to `to` when `forward`, the other way otherwise.
+    /// Every entity is visited once, so cycles are safe.
+    pub fn walk(&self, start: EntityId, forward: bool, max_depth: usize) -> Vec<EntityId> {
+        let mut visited = HashSet::new();
+        visited.insert(start);
+        let mut found = vec![];
+        let mut frontier = vec![start];
+        for _ in 0..max_depth {
+            let mut next = vec![];
+            for id in frontier {
+                let neighbours = if forward { self.targets(id) } else { self.sources(id) };
+                for neighbour in neighbours {
+                    if visited.insert(*neighbour) {
+                        found.push(*neighbour);
+                        next.push(*neighbour);
+                    }
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        found
+    }
+
+    /// Drops every pair the entity is part of
+    pub fn remove_entity(&mut self, id: EntityId) {
+        if let Some(targets) = self.targets.remove(&id) {
+            for to in targets {
+                unlink(&mut self.sources, to, id);
+            }
+        }
+        if let Some(sources) = self.sources.remove(&id) {
+            for from in sources {
+                unlink(&mut self.targets, from, id);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+}
+
+impl MapEntities for RelationStorage {
+    /// Drops the pairs with an entity that isn't in the map
+    fn map_entities(&mut self, map: &EntityMap) {
+        let pairs: Vec<(EntityId, EntityId)> = ::std::mem::take(self).into();
+        *self = pairs.into_iter()
+            .filter_map(|(from, to)| Some((map.get(from)?, map.get(to)?)))
+            .collect::<Vec<_>>()
+            .into();
+    }
+}
+
+impl From<Vec<(EntityId, EntityId)>> for RelationStorage {
+    fn from(pairs: Vec<(EntityId, EntityId)>) -> Self {
+        let mut storage = RelationStorage::default();
+        for (from, to) in pairs {
+            storage.relate(from, to);
+        }
+        storage
+    }
+}
+
+impl From<RelationStorage> for Vec<(EntityId, EntityId)> {
+    fn from(storage: RelationStorage) -> Self {
+        let mut froms: Vec<_> = storage.targets.keys().cloned().collect();
+        froms.sort();
+        froms.into_iter()
+            .flat_map(|from| storage.targets(from).iter().map(move |to| (from, *to)).collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+///
+/// Relation storages of a pool, one per relation name
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Relations {
+    storages: BTreeMap<String, RelationStorage>
+}
+
+impl Relations {
+    pub fn get<R: Relation>(&self) -> Option<&RelationStorage> {
+        self.storages.get(R::NAME)
+    }
+
+    pub fn get_mut<R: Relation>(&mut self) -> &mut RelationStorage {
+        self.storages.entry(R::NAME.to_string()).or_default()
+    }
+
+    /// Drops every pair of every relation the entity is part of
+    pub fn remove_entity(&mut self, id: EntityId) {
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(id);
+        }
+        self.storages.retain(|_, storage| !storage.is_empty());
+    }
+
+    /// Adds the pairs of every relation of `other`
+    pub fn merge(&mut self, other: Relations) {
+        for (name, storage) in other.storages {
+            let pairs: Vec<(EntityId, EntityId)> = storage.into();
+            let target = self.storages.entry(name).or_default();
+            for (from, to) in pairs {
+                target.relate(from, to);
+            }
+        }
+    }
+}
+
+impl MapEntities for Relations {
+    fn map_entities(&mut self, map: &EntityMap) {
+        for storage in self.storages.values_mut() {
+            storage.map_entities(map);
+        }
+        self.storages.retain(|_, storage| !storage.is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+//!
+//! Network replication of component changes
+//!
+//! Components marked `replicated` in `create_spawning_pool!` are tracked per peer whenever they are
+//! set, mutably borrowed or removed. `collect_updates` drains the changes for one peer into a
+//! `ReplicationPacket`, which the receiving pool applies with `apply_updates`. After `compact_ids`
+//! renumbers the entities, every peer's next packet resets the receiving pool to the full state.
+//!
+
+use std::collections::{BTreeMap, BTreeSet};
+use serde_json::Value;
+use super::{EntityId};
+
+/// Identifier for a replication peer, chosen by the caller
+pub type PeerId = u32;
+
+///
+/// A single component change, `None` means the component was removed
+///
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentUpdate {
+    pub entity: EntityId,
+    pub component: String,
+    pub value: Option<Value>
+}
+
+///
+/// All changes a peer hasn't seen yet
+///
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReplicationPacket {
+    pub updates: Vec<ComponentUpdate>,
+    pub despawned: Vec<EntityId>,
+    /// The receiving pool drops all of its entities before applying the updates, which hold
+    /// every replicated component again
+    #[serde(default)]
+    pub reset: bool
+}
+
+impl ReplicationPacket {
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty() && self.despawned.is_empty() && !self.reset
+    }
+}
+
+#[derive(Debug, Default)]
+struct PeerState {
+    dirty: BTreeMap<EntityId, BTreeSet<&'static str>>,
+    despawned: BTreeSet<EntityId>,
+    reset: bool
+}
+
+///
+/// Dirty tracking for every registered peer, kept by the pool and not serialized
+///
+#[derive(Debug, Default)]
+pub struct ReplicationState {
+    peers: BTreeMap<PeerId, PeerState>
+}
+
+impl ReplicationState {
+    pub fn add_peer(&mut self, peer: PeerId) {
+        self.peers.entry(peer).or_default();
+    }
+
+    pub fn remove_peer(&mut self, peer: PeerId) {
+        self.peers.remove(&peer);
+    }
+
+    pub fn has_peer(&self, peer: PeerId) -> bool {
+        self.peers.contains_key(&peer)
+    }
+
+    pub fn peers(&self) -> Vec<PeerId> {
+        self.peers.keys().cloned().collect()
+    }
+
+    /// Marks the component dirty for every peer
+    pub fn mark_dirty(&mut self, id: EntityId, component: &'static str) {
+        for state in self.peers.values_mut() {
+            state.dirty.entry(id).or_default().insert(component);
+        }
+    }
+
+    /// Marks the component dirty for a single peer
+    pub fn mark_dirty_for(&mut self, peer: PeerId, id: EntityId, component: &'static str) {
+        if let Some(state) = self.peers.get_mut(&peer) {
+            state.dirty.entry(id).or_default().insert(component);
+        }
+    }
+
+    pub fn mark_despawned(&mut self, id: EntityId) {
+        for state in self.peers.values_mut() {
+            state.dirty.remove(&id);
+            state.despawned.insert(id);
+        }
+    }
+
+    /// Drops a despawn that hasn't been collected yet, peers that already received it are not
+    /// told about the revival
+    pub fn cancel_despawned(&mut self, id: EntityId) {
+        for state in self.peers.values_mut() {
+            state.despawned.remove(&id);
+        }
+    }
+
+    /// Drops the pending changes of every peer and has their next packet reset them, for when
+    /// entity IDs changed. The caller marks every replicated component dirty again.
+    pub fn reset(&mut self) {
+        for state in self.peers.values_mut() {
+            *state = PeerState { reset: true, ..Default::default() };
+        }
+    }
+
+    /// Whether the peer's next packet resets it, clearing the flag
+    pub fn take_reset(&mut self, peer: PeerId) -> bool {
+        match self.peers.get_mut(&peer) {
+            Some(state) => ::std::mem::replace(&mut state.reset, false),
+            None => false
+        }
+    }
+
+    /// Takes the dirty components and despawned entities of a peer, leaving it clean
+    pub fn take(&mut self, peer: PeerId) -> (Vec<(EntityId, &'static str)>, Vec<EntityId>) {
+        match self.peers.get_mut(&peer) {
+            Some(state) => {
+                let dirty = ::std::mem::take(&mut state.dirty)
+                    .into_iter()
+                    .flat_map(|(id, names)| names.into_iter().map(move |name| (id, name)))
+                    .collect();
+                let despawned = ::std::mem::take(&mut state.despawned).into_iter().collect();
+                (dirty, despawned)
+            }
+            None => (vec![], vec![])
+        }
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spawning_pool_is_replicated {
+    () => { false };
+    (replicated $(, $rest:ident)*) => { true };
+    ($other:ident $(, $rest:ident)*) => { $crate::__spawning_pool_is_replicated!($($rest),*) };
+}
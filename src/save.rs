@@ -0,0 +1,497 @@
+//!
+//! Save files, a small binary header followed by the pool as JSON
+//!
+//! The header holds magic bytes, the format version and a hash of the pool's component schema,
+//! so loading a file from another game or an incompatible build fails up front with a clear
+//! error.
+//!
+//! `save_streaming` writes the same header followed by JSON lines instead of a single JSON
+//! document: one line with everything but the storages, then one line per batch of entities of
+//! a storage. Neither it nor `load_streaming` ever hold more than a batch as JSON, which keeps
+//! memory bounded for very large pools.
+//!
+//! `save_blobs` writes the header followed by length-prefixed blobs, one with everything but
+//! the storages and one per storage. `load_blobs` reads them all and deserializes the storages
+//! at the same time on the rayon thread pool when the `rayon` feature is enabled.
+//!
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+use serde::Serialize;
+use serde_json;
+use checksum::Fnv64;
+use map::EntityMap;
+use super::{EntityId};
+
+/// First bytes of every save file
+pub const MAGIC: [u8; 4] = *b"SPWN";
+
+/// Version of the save format written by this crate
+pub const FORMAT_VERSION: u32 = 1;
+
+///
+/// Error from writing or reading a save file
+///
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The data doesn't start with the save file magic bytes
+    NotASave,
+    /// The file was written by a newer version of the format
+    UnsupportedVersion(u32),
+    /// The file was written by a pool with different components or storages
+    SchemaMismatch { expected: u64, found: u64 },
+    /// A component asked for by `load_partial` isn't registered in the pool
+    UnknownComponent(String),
+    /// Entities of the file passed to `load_into` with `CollisionPolicy::Error` have IDs the
+    /// pool already uses
+    IdCollision(Vec<EntityId>)
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SaveError::Io(ref e) => write!(f, "save file io error: {}", e),
+            SaveError::Json(ref e) => write!(f, "invalid save file contents: {}", e),
+            SaveError::NotASave => write!(f, "not a spawning pool save file"),
+            SaveError::UnsupportedVersion(version) =>
+                write!(f, "save format version {} is newer than the supported version {}", version, FORMAT_VERSION),
+            SaveError::SchemaMismatch { expected, found } =>
+                write!(f, "save file component schema {:016x} doesn't match the pool's schema {:016x}", found, expected),
+            SaveError::UnknownComponent(ref name) => write!(f, "no component named `{}` in the pool", name),
+            SaveError::IdCollision(ref ids) =>
+                write!(f, "{} entities of the save file have IDs already used by the pool, the first is {}", ids.len(), ids[0])
+        }
+    }
+}
+
+impl ::std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(e: serde_json::Error) -> Self {
+        SaveError::Json(e)
+    }
+}
+
+///
+/// Header at the start of a save file
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SaveHeader {
+    pub version: u32,
+    pub schema: u64
+}
+
+impl SaveHeader {
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&self.schema.to_le_bytes())
+    }
+
+    /// Reads and validates the magic bytes and version, the schema is left to the caller
+    pub fn read<R: Read>(reader: &mut R) -> Result<SaveHeader, SaveError> {
+        let mut magic = [0; 4];
+        if let Err(e) = reader.read_exact(&mut magic) {
+            return Err(match e.kind() {
+                io::ErrorKind::UnexpectedEof => SaveError::NotASave,
+                _ => SaveError::Io(e)
+            });
+        }
+        if magic != MAGIC {
+            return Err(SaveError::NotASave);
+        }
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version > FORMAT_VERSION {
+            return Err(SaveError::UnsupportedVersion(version));
+        }
+        let mut schema = [0; 8];
+        reader.read_exact(&mut schema)?;
+        Ok(SaveHeader {
+            version,
+            schema: u64::from_le_bytes(schema)
+        })
+    }
+}
+
+///
+/// Turns the JSON of a save into the bytes stored after the header and back, applied by
+/// `save_transformed` and `load_transformed` to encrypt, sign or compress save files
+///
+/// A tuple `(a, b)` encodes with `a` then `b` and decodes in the opposite order.
+///
+/// ```
+/// use std::io;
+/// use spawning_pool::save::SaveTransform;
+///
+/// struct Xor(u8);
+///
+/// impl SaveTransform for Xor {
+///     fn encode(&self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+///         Ok(data.into_iter().map(|b| b ^ self.0).collect())
+///     }
+///
+///     fn decode(&self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+///         self.encode(data)
+///     }
+/// }
+///
+/// let key = (Xor(0x5a), Xor(0x17));
+/// let data = key.encode(b"{}".to_vec()).unwrap();
+/// assert_eq!(key.decode(data).unwrap(), b"{}");
+/// ```
+///
+pub trait SaveTransform {
+    fn encode(&self, data: Vec<u8>) -> io::Result<Vec<u8>>;
+
+    /// Undoes `encode`, failing with `InvalidData` for data it didn't produce or that was
+    /// tampered with
+    fn decode(&self, data: Vec<u8>) -> io::Result<Vec<u8>>;
+}
+
+impl<A: SaveTransform, B: SaveTransform> SaveTransform for (A, B) {
+    fn encode(&self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        self.1.encode(self.0.encode(data)?)
+    }
+
+    fn decode(&self, data: Vec<u8>) -> io::Result<Vec<u8>> {
+        self.0.decode(self.1.decode(data)?)
+    }
+}
+
+/// Hash of the component type, field and storage names of a pool, in declaration order
+pub fn schema_hash(components: &[(&str, &str, &str)]) -> u64 {
+    let mut hasher = Fnv64::default();
+    for (component, field, storage) in components {
+        for name in &[component, field, storage] {
+            hasher.write(name.as_bytes());
+            hasher.write(&[0]);
+        }
+    }
+    hasher.finish()
+}
+
+///
+/// Options for `load_with`
+///
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+    /// Load files saved with a different schema, dropping storages the pool doesn't know and
+    /// starting missing ones empty
+    pub skip_unknown: bool,
+    /// Like `skip_unknown`, but keeps the unknown storages in the pool and writes them back on
+    /// `save`, so files from newer builds don't lose data
+    pub preserve_unknown: bool,
+    /// Component and storage names changed since the file was written
+    pub renames: Renames
+}
+
+///
+/// Names of components and storages renamed since older save files were written
+///
+/// A rename applies to component type names and storage field names alike. Files written before
+/// the renames load without a schema mismatch, and their storages are moved to the new field
+/// names.
+///
+/// ```
+/// use spawning_pool::save::{LoadOptions, Renames};
+///
+/// let options = LoadOptions {
+///     renames: Renames::new().rename("Hp", "Health").rename("hp", "health"),
+///     ..Default::default()
+/// };
+/// assert_eq!(options.renames.current_name("hp"), "health");
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Renames {
+    renames: Vec<(String, String)>
+}
+
+impl Renames {
+    pub fn new() -> Self {
+        Renames::default()
+    }
+
+    /// Adds a rename, later renames apply on top of earlier ones
+    pub fn rename(mut self, old: &str, new: &str) -> Self {
+        self.renames.push((old.to_string(), new.to_string()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.renames.is_empty()
+    }
+
+    /// What `name` is called after every rename
+    pub fn current_name<'a>(&'a self, mut name: &'a str) -> &'a str {
+        for (old, new) in &self.renames {
+            if old == name {
+                name = new;
+            }
+        }
+        name
+    }
+
+    /// What `name` was called before every rename
+    pub fn original_name<'a>(&'a self, mut name: &'a str) -> &'a str {
+        for (old, new) in self.renames.iter().rev() {
+            if new == name {
+                name = old;
+            }
+        }
+        name
+    }
+
+    /// Schema hash the pool had before the renames
+    pub fn original_schema_hash(&self, components: &[(&str, &str, &str)]) -> u64 {
+        let original: Vec<_> = components.iter()
+            .map(|(component, field, storage)| (self.original_name(component), self.original_name(field), *storage))
+            .collect();
+        schema_hash(&original)
+    }
+
+    /// Moves renamed fields of a serialized pool to their current names and returns the
+    /// `(old, new)` names moved, a field is left alone if the new name is already taken
+    pub fn apply(&self, pool: &mut serde_json::Value) -> Vec<(String, String)> {
+        let mut renamed = vec![];
+        if let serde_json::Value::Object(ref mut fields) = *pool {
+            let names: Vec<String> = fields.keys().cloned().collect();
+            for old in names {
+                let new = self.current_name(&old);
+                if new != old && !fields.contains_key(new) {
+                    if let Some(value) = fields.remove(&old) {
+                        fields.insert(new.to_string(), value);
+                        renamed.push((old.clone(), new.to_string()));
+                    }
+                }
+            }
+        }
+        renamed
+    }
+}
+
+///
+/// What `load_into` does with a loaded entity whose ID the pool already uses
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Fail with `SaveError::IdCollision` before changing the pool
+    Error,
+    /// Give the loaded entity a new ID, references to it in components flagged `map_entities` follow
+    Remap,
+    /// Destroy the pool's entity and put the loaded one in its place
+    Overwrite
+}
+
+///
+/// Outcome of `load_into`
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollisionReport {
+    /// Loaded entities whose ID the pool already used, in ascending order
+    pub collisions: Vec<EntityId>,
+    /// New IDs of the colliding entities with `CollisionPolicy::Remap`
+    pub remapped: EntityMap
+}
+
+///
+/// What `load_with` had to change to load a file
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadReport {
+    /// Storage fields in the file that the pool doesn't have, they were dropped or preserved
+    pub unknown: Vec<String>,
+    /// Storage fields of the pool missing from the file, they start empty
+    pub missing: Vec<&'static str>,
+    /// Storage fields moved from their old names by `LoadOptions::renames`
+    pub renamed: Vec<(String, String)>
+}
+
+impl LoadReport {
+    pub fn is_clean(&self) -> bool {
+        self.unknown.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Reads a serialized pool, skipping the values of fields `keep` rejects without building them
+pub fn read_fields<R: Read, F: Fn(&str) -> bool>(reader: R, keep: F) -> Result<serde_json::Map<String, serde_json::Value>, SaveError> {
+    struct Fields<F>(F);
+
+    impl<'de, F: Fn(&str) -> bool> ::serde::de::Visitor<'de> for Fields<F> {
+        type Value = serde_json::Map<String, serde_json::Value>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a serialized pool")
+        }
+
+        fn visit_map<A: ::serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut fields = serde_json::Map::new();
+            while let Some(name) = map.next_key::<String>()? {
+                if (self.0)(&name) {
+                    fields.insert(name, map.next_value()?);
+                } else {
+                    map.next_value::<::serde::de::IgnoredAny>()?;
+                }
+            }
+            Ok(fields)
+        }
+    }
+
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let fields = ::serde::Deserializer::deserialize_map(&mut deserializer, Fields(keep))?;
+    deserializer.end()?;
+    Ok(fields)
+}
+
+/// Removes the fields not in `known` from a serialized pool and returns them
+pub fn remove_unknown_fields(pool: &mut serde_json::Value, known: &[&str]) -> BTreeMap<String, serde_json::Value> {
+    let mut unknown = BTreeMap::new();
+    if let serde_json::Value::Object(ref mut fields) = *pool {
+        let names: Vec<String> = fields.keys().filter(|k| !known.contains(&k.as_str())).cloned().collect();
+        for name in names {
+            if let Some(value) = fields.remove(&name) {
+                unknown.insert(name, value);
+            }
+        }
+    }
+    unknown
+}
+
+///
+/// Serialized storages from a save file that the pool doesn't know, kept to be saved again
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PreservedData {
+    fields: BTreeMap<String, serde_json::Value>
+}
+
+impl PreservedData {
+    pub fn new(fields: BTreeMap<String, serde_json::Value>) -> Self {
+        PreservedData {
+            fields
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.fields.keys().map(|k| k.as_str()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&serde_json::Value> {
+        self.fields.get(name)
+    }
+
+    /// Adds the preserved storages to a serialized pool, fields it already has win
+    pub fn merge_into(&self, pool: &mut serde_json::Value) {
+        if let serde_json::Value::Object(ref mut fields) = *pool {
+            for (name, value) in &self.fields {
+                fields.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+}
+
+/// Writes one line of a streaming save, the storage's field name and a JSON list of
+/// `(id, component)` pairs
+pub fn write_batch<W: Write, T: Serialize>(writer: &mut W, storage: &str, entities: &[(EntityId, &T)]) -> Result<(), SaveError> {
+    writer.write_all(storage.as_bytes())?;
+    writer.write_all(b"\t")?;
+    serde_json::to_writer(&mut *writer, entities)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Splits a line written by `write_batch` into the storage name and its JSON list
+pub fn split_batch(line: &str) -> Result<(&str, &str), SaveError> {
+    line.split_once('\t').ok_or_else(|| invalid_data("streaming save line without a storage name"))
+}
+
+/// Error for a batch or blob of a storage the pool doesn't have
+pub fn unknown_storage(name: &str) -> SaveError {
+    invalid_data(format!("unknown storage `{}` in save", name))
+}
+
+fn invalid_data<T: fmt::Display>(message: T) -> SaveError {
+    SaveError::Json(<serde_json::Error as ::serde::de::Error>::custom(message))
+}
+
+/// Writes a named blob, the name and the contents each prefixed by their length
+pub fn write_blob<W: Write>(writer: &mut W, name: &str, bytes: &[u8]) -> io::Result<()> {
+    writer.write_all(&(name.len() as u32).to_le_bytes())?;
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+/// Reads every blob written by `write_blob` until the end of the data
+pub fn read_blobs<R: Read>(reader: &mut R) -> Result<Vec<(String, Vec<u8>)>, SaveError> {
+    let mut blobs = vec![];
+    loop {
+        let mut len = [0; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {},
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(blobs),
+            Err(e) => return Err(e.into())
+        }
+        let mut name = vec![0; u32::from_le_bytes(len) as usize];
+        reader.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|_| invalid_data("blob name isn't valid utf-8"))?;
+        let mut len = [0; 8];
+        reader.read_exact(&mut len)?;
+        let mut bytes = vec![];
+        reader.by_ref().take(u64::from_le_bytes(len)).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != u64::from_le_bytes(len) {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        blobs.push((name, bytes));
+    }
+}
+
+/// Bound on the storages of `load_blobs`, `Send` with the `rayon` feature and nothing otherwise
+#[cfg(feature = "rayon")]
+pub trait BlobStorage: Send {}
+#[cfg(feature = "rayon")]
+impl<T: Send> BlobStorage for T {}
+
+/// Bound on the storages of `load_blobs`, `Send` with the `rayon` feature and nothing otherwise
+#[cfg(not(feature = "rayon"))]
+pub trait BlobStorage {}
+#[cfg(not(feature = "rayon"))]
+impl<T> BlobStorage for T {}
+
+/// Work given to `run_all`, only needs to be `Send` with the `rayon` feature
+#[cfg(feature = "rayon")]
+pub type BlobTask<'a> = Box<dyn FnOnce() + Send + 'a>;
+/// Work given to `run_all`, only needs to be `Send` with the `rayon` feature
+#[cfg(not(feature = "rayon"))]
+pub type BlobTask<'a> = Box<dyn FnOnce() + 'a>;
+
+/// Runs every task, on the rayon thread pool with the `rayon` feature, and returns once all are
+/// done
+pub fn run_all(tasks: Vec<BlobTask<'_>>) {
+    #[cfg(feature = "rayon")]
+    ::rayon::scope(|scope| {
+        for task in tasks {
+            scope.spawn(move |_| task());
+        }
+    });
+    #[cfg(not(feature = "rayon"))]
+    for task in tasks {
+        task();
+    }
+}
@@ -0,0 +1,111 @@
+//!
+//! A minimal system runner, enabled with the `schedule` feature
+//!
+//! Systems are closures taking the pool mutably. They run in the order they were added, grouped
+//! in stages, and entities removed during a stage are cleaned up before the next stage starts.
+//!
+
+use inspect::ErasedPool;
+
+type System<P> = Box<dyn FnMut(&mut P)>;
+
+struct Stage<P> {
+    name: &'static str,
+    systems: Vec<System<P>>
+}
+
+///
+/// Ordered list of stages, each running its systems and then `cleanup_removed`
+///
+pub struct Schedule<P> {
+    stages: Vec<Stage<P>>
+}
+
+impl<P: ErasedPool> Schedule<P> {
+    /// Schedule with a single stage named `"update"`
+    pub fn new() -> Self {
+        Schedule {
+            stages: vec![Stage { name: "update", systems: vec![] }]
+        }
+    }
+
+    /// Adds a system to the last stage
+    #[allow(clippy::should_implement_trait)]
+    pub fn add<F>(mut self, system: F) -> Self where F: FnMut(&mut P) + 'static {
+        if let Some(stage) = self.stages.last_mut() {
+            stage.systems.push(Box::new(system));
+        }
+        self
+    }
+
+    /// Starts a new stage, systems added from now on run after the previous stages
+    pub fn stage(mut self, name: &'static str) -> Self {
+        self.stages.push(Stage { name, systems: vec![] });
+        self
+    }
+
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|stage| stage.name).collect()
+    }
+
+    pub fn run(&mut self, pool: &mut P) {
+        for stage in &mut self.stages {
+            let _span = ::__spawning_pool_span!("stage", name = stage.name);
+            for system in &mut stage.systems {
+                system(pool);
+            }
+            pool.cleanup_removed();
+        }
+    }
+}
+
+impl<P: ErasedPool> Default for Schedule<P> {
+    fn default() -> Self {
+        Schedule::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Schedule;
+    use storage::*;
+    use {EntityId, create_spawning_pool};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct Health(i32);
+
+    #[test]
+    fn test_schedule_stages() {
+        create_spawning_pool!(
+            (Health, health, HashMapStorage)
+        );
+        let mut pool = SpawningPool::new();
+        let id = pool.spawn_entity();
+        pool.set(id, Health(1));
+
+        let mut schedule = Schedule::new()
+            .add(|pool: &mut SpawningPool| {
+                let ids: Vec<EntityId> = pool.get_all::<Health>().iter().map(|(id, _)| *id).collect();
+                for id in ids {
+                    pool.get_mut::<Health>(id).unwrap().0 -= 1;
+                }
+            })
+            .add(|pool: &mut SpawningPool| {
+                let dead: Vec<EntityId> = pool.get_all::<Health>().iter()
+                    .filter(|(_, h)| h.0 <= 0)
+                    .map(|(id, _)| *id)
+                    .collect();
+                for id in dead {
+                    pool.remove_entity(id);
+                }
+            })
+            .stage("late")
+            .add(move |pool: &mut SpawningPool| {
+                assert!(pool.force_get::<Health>(id).is_none());
+            });
+        assert_eq!(schedule.stage_names(), vec!["update", "late"]);
+
+        schedule.run(&mut pool);
+        assert_eq!(pool.pending_removals().count(), 0);
+    }
+}
@@ -0,0 +1,147 @@
+//!
+//! Struct-of-arrays component storage, every field of a component kept in its own `Vec`
+//!
+//! Systems that only touch a field or two of a big component, like the `x` of every position,
+//! read tightly packed memory instead of loading whole structs. Pool storages have to hand out
+//! `&T`, which a split component can't provide, so SoA storages live next to the pool and are
+//! indexed by the same entity ids.
+//!
+//! ```
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::EntityId;
+//!
+//! soa_component! {
+//!     #[derive(Clone, Debug, PartialEq)]
+//!     pub struct Body {
+//!         pub x: f32,
+//!         pub vx: f32
+//!     }
+//!     storage BodySoa, columns BodyColumns, proxy BodyMut;
+//! }
+//!
+//! let mut bodies = BodySoa::new();
+//! let id = EntityId::from_raw(1).unwrap();
+//! bodies.set(id, Body { x: 0.0, vx: 2.0 });
+//!
+//! let columns = bodies.columns_mut();
+//! for (x, vx) in columns.x.iter_mut().zip(&columns.vx) {
+//!     *x += vx;
+//! }
+//! *bodies.get_mut(id).unwrap().vx = 0.0;
+//! assert_eq!(bodies.get(id), Some(Body { x: 2.0, vx: 0.0 }));
+//! # }
+//! ```
+//!
+
+///
+/// Declares a component struct along with its SoA storage, a struct of field columns and a
+/// proxy of mutable field references returned by `get_mut`, field types must be `Clone`
+///
+#[macro_export]
+macro_rules! soa_component {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident : $ty:ty),+ $(,)*
+        }
+        storage $storage:ident, columns $columns:ident, proxy $proxy:ident;
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($field_vis $field: $ty),+
+        }
+
+        /// One `Vec` per field, all as long as the storage
+        #[derive(Clone, Default)]
+        $vis struct $columns {
+            $(pub $field: Vec<$ty>),+
+        }
+
+        #[allow(dead_code)]
+        $vis struct $proxy<'a> {
+            $(pub $field: &'a mut $ty),+
+        }
+
+        #[derive(Clone, Default)]
+        $vis struct $storage {
+            ids: Vec<$crate::EntityId>,
+            slots: ::std::collections::HashMap<$crate::EntityId, usize>,
+            columns: $columns
+        }
+
+        #[allow(dead_code)]
+        impl $storage {
+            pub fn new() -> Self {
+                Default::default()
+            }
+
+            pub fn len(&self) -> usize {
+                self.ids.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.ids.is_empty()
+            }
+
+            /// Entity of every row, in column order
+            pub fn ids(&self) -> &[$crate::EntityId] {
+                &self.ids
+            }
+
+            pub fn contains(&self, id: $crate::EntityId) -> bool {
+                self.slots.contains_key(&id)
+            }
+
+            pub fn columns(&self) -> &$columns {
+                &self.columns
+            }
+
+            /// Mutable columns, their lengths must not be changed
+            pub fn columns_mut(&mut self) -> &mut $columns {
+                &mut self.columns
+            }
+
+            /// Copy of the entity's component, gathered from the columns
+            pub fn get(&self, id: $crate::EntityId) -> Option<$name> {
+                let slot = *self.slots.get(&id)?;
+                Some($name {
+                    $($field: self.columns.$field[slot].clone()),+
+                })
+            }
+
+            pub fn get_mut(&mut self, id: $crate::EntityId) -> Option<$proxy<'_>> {
+                let slot = *self.slots.get(&id)?;
+                let columns = &mut self.columns;
+                Some($proxy {
+                    $($field: &mut columns.$field[slot]),+
+                })
+            }
+
+            pub fn set(&mut self, id: $crate::EntityId, component: $name) {
+                match self.slots.get(&id) {
+                    Some(&slot) => {
+                        $(self.columns.$field[slot] = component.$field;)+
+                    },
+                    None => {
+                        self.slots.insert(id, self.ids.len());
+                        self.ids.push(id);
+                        $(self.columns.$field.push(component.$field);)+
+                    }
+                }
+            }
+
+            /// Removes the entity's row by moving the last row into its place
+            pub fn remove(&mut self, id: $crate::EntityId) -> Option<$name> {
+                let slot = self.slots.remove(&id)?;
+                self.ids.swap_remove(slot);
+                if let Some(moved) = self.ids.get(slot) {
+                    self.slots.insert(*moved, slot);
+                }
+                Some($name {
+                    $($field: self.columns.$field.swap_remove(slot)),+
+                })
+            }
+        }
+    };
+}
@@ -2,33 +2,107 @@
 //! Storage structures for use with Spawning Pool
 //!
 
-use std::collections::{HashMap};
-use super::{EntityId};
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "deterministic")]
+use std::collections::BTreeMap;
+#[cfg(not(feature = "deterministic"))]
+use std::collections::hash_map::RandomState;
+#[cfg(feature = "deterministic")]
+use std::hash::BuildHasherDefault;
+use std::error;
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use super::{EntityId, RawEntityId};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "deterministic")]
+use checksum::Fnv64;
+#[cfg(feature = "indexmap")]
+use indexmap::IndexMap;
 
 ///
 /// Storage trait for component storage
 ///
+/// Storages also need to implement `Clone`, pools share them between forks and clone them on
+/// first write.
+///
+#[allow(anonymous_parameters)]
 pub trait Storage<T: Clone> {
-    fn new() -> Self;
+    fn new() -> Self where Self: Sized;
     fn get(&self, EntityId) -> Option<&T>;
     fn get_all(&self) -> Vec<(EntityId, &T)>;
     fn get_mut(&mut self, EntityId) -> Option<&mut T>;
     fn set(&mut self, EntityId, T);
     fn remove(&mut self, EntityId);
+
+    /// Appends every stored component to `out`, storages override this to skip the `Vec` that
+    /// `get_all` allocates
+    fn get_all_into<'a>(&'a self, out: &mut Vec<(EntityId, &'a T)>) {
+        out.extend(self.get_all());
+    }
+
+    /// Number of stored components
+    fn len(&self) -> usize {
+        self.get_all().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the component and hands it back
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        let comp = self.get(id).cloned();
+        self.remove(id);
+        comp
+    }
+
+    /// Removes every component and hands them back, in no particular order
+    fn drain(&mut self) -> Vec<(EntityId, T)> {
+        let ids: Vec<EntityId> = self.get_all().iter().map(|(id, _)| *id).collect();
+        ids.into_iter().filter_map(|id| Some((id, self.take(id)?))).collect()
+    }
+
+    /// Makes room for at least `additional` more components so later inserts don't grow the
+    /// storage
+    fn reserve(&mut self, _additional: usize) {}
 }
 
+///
+/// Components by entity in `HashMapStorage`, `HistoryStorage`, `SpatialGridStorage` and
+/// `IndexedStorage`, a `BTreeMap` with the `deterministic` feature so their iteration and
+/// serialization follow the entity ids
+///
+#[cfg(not(feature = "deterministic"))]
+pub type ComponentMap<T> = HashMap<EntityId, T>;
+#[cfg(feature = "deterministic")]
+pub type ComponentMap<T> = BTreeMap<EntityId, T>;
+
+///
+/// Hasher of the storages' own indices, `Fnv64` with the `deterministic` feature instead of a
+/// randomly seeded one
+///
+#[cfg(not(feature = "deterministic"))]
+pub type HashState = RandomState;
+#[cfg(feature = "deterministic")]
+pub type HashState = BuildHasherDefault<Fnv64>;
+
 ///
 /// Hash map implementation of the storage trait, probably the best default storage to use
 ///
+/// With the `deterministic` feature it is backed by a `BTreeMap`, trading some speed for
+/// `get_all` visiting components in entity id order.
+///
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HashMapStorage<T: Clone> {
-    storage: HashMap<EntityId, T>
+    storage: ComponentMap<T>
 }
 
 impl<T: Clone> Storage<T> for HashMapStorage<T> {
     fn new() -> Self {
         HashMapStorage {
-            storage: HashMap::new()
+            storage: ComponentMap::new()
         }
     }
 
@@ -48,6 +122,10 @@ impl<T: Clone> Storage<T> for HashMapStorage<T> {
         all
     }
 
+    fn get_all_into<'a>(&'a self, out: &mut Vec<(EntityId, &'a T)>) {
+        out.extend(self.storage.iter().map(|(k, v)| (*k, v)));
+    }
+
     fn set(&mut self, id: EntityId, comp: T) {
         self.storage.insert(id, comp);
     }
@@ -55,6 +133,85 @@ impl<T: Clone> Storage<T> for HashMapStorage<T> {
     fn remove(&mut self, id: EntityId) {
         self.storage.remove(&id);
     }
+
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        self.storage.remove(&id)
+    }
+
+    fn drain(&mut self) -> Vec<(EntityId, T)> {
+        ::std::mem::take(&mut self.storage).into_iter().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        #[cfg(not(feature = "deterministic"))]
+        self.storage.reserve(additional);
+        #[cfg(feature = "deterministic")]
+        let _ = additional;
+    }
+}
+
+///
+/// Insertion ordered hash map implementation of the storage trait, `get_all` visits components in
+/// the order they were first set so iteration is deterministic
+///
+/// Removing keeps the order of the remaining components, which costs O(n).
+///
+#[cfg(feature = "indexmap")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexMapStorage<T: Clone> {
+    storage: IndexMap<EntityId, T>
+}
+
+#[cfg(feature = "indexmap")]
+impl<T: Clone> Storage<T> for IndexMapStorage<T> {
+    fn new() -> Self {
+        IndexMapStorage {
+            storage: IndexMap::new()
+        }
+    }
+
+    fn get(&self, id: EntityId) -> Option<&T> {
+        self.storage.get(&id)
+    }
+
+    fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        self.storage.get_mut(&id)
+    }
+
+    fn get_all(&self) -> Vec<(EntityId, &T)> {
+        self.storage.iter().map(|(k, v)| (*k, v)).collect()
+    }
+
+    fn get_all_into<'a>(&'a self, out: &mut Vec<(EntityId, &'a T)>) {
+        out.extend(self.storage.iter().map(|(k, v)| (*k, v)));
+    }
+
+    fn set(&mut self, id: EntityId, comp: T) {
+        self.storage.insert(id, comp);
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        self.storage.shift_remove(&id);
+    }
+
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        self.storage.shift_remove(&id)
+    }
+
+    fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        #[cfg(not(feature = "deterministic"))]
+        self.storage.reserve(additional);
+        #[cfg(feature = "deterministic")]
+        let _ = additional;
+    }
 }
 
 ///
@@ -64,33 +221,92 @@ impl<T: Clone> Storage<T> for HashMapStorage<T> {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VectorStorage<T: Clone> {
-    size: u64,
-    storage: Vec<Option<T>>
+    storage: Vec<Option<T>>,
+    #[serde(default)]
+    max_capacity: Option<usize>
+}
+
+const VECTOR_STORAGE_INITIAL: usize = 100;
+
+///
+/// Entity id too large for a `VectorStorage` created with `with_max_capacity`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityError {
+    pub id: EntityId,
+    pub max_capacity: usize
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "entity {} doesn't fit in a vector storage of at most {} slots", self.id, self.max_capacity)
+    }
+}
+
+impl error::Error for CapacityError {}
+
+impl<T: Clone> VectorStorage<T> {
+    /// Storage that never grows past `max_capacity` slots, so a huge entity id can't make it
+    /// allocate without bounds. `set` panics for ids that don't fit, `try_set` returns an error.
+    pub fn with_max_capacity(max_capacity: usize) -> Self {
+        let mut storage = Vec::new();
+        storage.resize_with(VECTOR_STORAGE_INITIAL.min(max_capacity), || None);
+        VectorStorage {
+            storage,
+            max_capacity: Some(max_capacity)
+        }
+    }
+
+    pub fn max_capacity(&self) -> Option<usize> {
+        self.max_capacity
+    }
+
+    /// Like `set`, returning an error instead of panicking when the id is beyond the max capacity
+    pub fn try_set(&mut self, id: EntityId, comp: T) -> Result<(), CapacityError> {
+        let index = id.to_raw() as usize;
+        if let Some(max_capacity) = self.max_capacity {
+            if index >= max_capacity {
+                return Err(CapacityError { id, max_capacity });
+            }
+        }
+        if index >= self.storage.len() {
+            let mut len = (index + 1).max(self.storage.len() * 2);
+            if let Some(max_capacity) = self.max_capacity {
+                len = len.min(max_capacity);
+            }
+            self.storage.resize_with(len, || None);
+        }
+        self.storage[index] = Some(comp);
+        Ok(())
+    }
+
+    /// Frees the empty slots after the highest stored id
+    pub fn shrink_to_fit(&mut self) {
+        let len = self.storage.iter().rposition(Option::is_some).map_or(0, |last| last + 1);
+        self.storage.truncate(len);
+        self.storage.shrink_to_fit();
+    }
 }
 
 impl<T: Clone> Storage<T> for VectorStorage<T> {
     fn new() -> Self {
+        let mut storage = Vec::new();
+        storage.resize_with(VECTOR_STORAGE_INITIAL, || None);
         VectorStorage {
-            size: 100,
-            storage: vec![None; 100]
+            storage,
+            max_capacity: None
         }
     }
 
     fn get(&self, id: EntityId) -> Option<&T> {
-        if id >= self.size {
-            return None;
-        }
-        match self.storage.get(id as usize) {
+        match self.storage.get(id.to_raw() as usize) {
             Some(c) => c.as_ref(),
             None => None
         }
     }
 
     fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
-        if id >= self.size {
-            return None;
-        }
-        match self.storage.get_mut(id as usize) {
+        match self.storage.get_mut(id.to_raw() as usize) {
             Some(c) => c.as_mut(),
             None => None
         }
@@ -99,24 +315,888 @@ impl<T: Clone> Storage<T> for VectorStorage<T> {
     fn get_all(&self) -> Vec<(EntityId, &T)> {
         let mut all = vec![];
         for (id, comp) in self.storage.iter().enumerate() {
-            if let Some(ref c) = *comp {
-                all.push((id as EntityId, c));
+            if let (Some(ref c), Some(id)) = (comp, EntityId::from_raw(id as RawEntityId)) {
+                all.push((id, c));
             }
         }
         all
     }
 
+    fn get_all_into<'a>(&'a self, out: &mut Vec<(EntityId, &'a T)>) {
+        let comps = self.storage.iter().enumerate()
+            .filter_map(|(id, comp)| match (comp, EntityId::from_raw(id as RawEntityId)) {
+                (Some(c), Some(id)) => Some((id, c)),
+                _ => None
+            });
+        out.extend(comps);
+    }
+
+    fn set(&mut self, id: EntityId, comp: T) {
+        if let Err(e) = self.try_set(id, comp) {
+            panic!("{}", e);
+        }
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        if let Some(slot) = self.storage.get_mut(id.to_raw() as usize) {
+            *slot = None;
+        }
+    }
+
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        self.storage.get_mut(id.to_raw() as usize).and_then(Option::take)
+    }
+
+    fn drain(&mut self) -> Vec<(EntityId, T)> {
+        self.storage.iter_mut().enumerate()
+            .filter_map(|(id, comp)| Some((EntityId::from_raw(id as RawEntityId)?, comp.take()?)))
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.storage.iter().filter(|c| c.is_some()).count()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        let mut len = self.storage.len() + additional;
+        if let Some(max_capacity) = self.max_capacity {
+            len = len.min(max_capacity);
+        }
+        self.storage.resize_with(len, || None);
+    }
+}
+
+const NO_SLOT: u32 = u32::MAX;
+
+///
+/// Slab implementation of the storage trait, components are packed in a dense vector with a free
+/// list and found through a small per entity slot index
+///
+/// Uses 4 bytes per entity ID instead of `VectorStorage`'s full component slot, without hashing
+/// on access. Works best when entity IDs are recycled and stay low.
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlabStorage<T: Clone> {
+    slots: Vec<Option<(EntityId, T)>>,
+    free: Vec<u32>,
+    index: Vec<u32>
+}
+
+impl<T: Clone> SlabStorage<T> {
+    fn slot(&self, id: EntityId) -> Option<usize> {
+        match self.index.get(id.to_raw() as usize) {
+            Some(slot) if *slot != NO_SLOT => Some(*slot as usize),
+            _ => None
+        }
+    }
+}
+
+impl<T: Clone> Storage<T> for SlabStorage<T> {
+    fn new() -> Self {
+        SlabStorage {
+            slots: vec![],
+            free: vec![],
+            index: vec![]
+        }
+    }
+
+    fn get(&self, id: EntityId) -> Option<&T> {
+        let slot = self.slot(id)?;
+        self.slots[slot].as_ref().map(|(_, c)| c)
+    }
+
+    fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        let slot = self.slot(id)?;
+        self.slots[slot].as_mut().map(|(_, c)| c)
+    }
+
+    fn get_all(&self) -> Vec<(EntityId, &T)> {
+        self.slots.iter()
+            .filter_map(|slot| slot.as_ref().map(|(id, c)| (*id, c)))
+            .collect()
+    }
+
+    fn get_all_into<'a>(&'a self, out: &mut Vec<(EntityId, &'a T)>) {
+        out.extend(self.slots.iter().filter_map(|slot| slot.as_ref().map(|(id, c)| (*id, c))));
+    }
+
+    fn set(&mut self, id: EntityId, comp: T) {
+        if let Some(slot) = self.slot(id) {
+            self.slots[slot] = Some((id, comp));
+            return;
+        }
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot as usize] = Some((id, comp));
+                slot
+            }
+            None => {
+                self.slots.push(Some((id, comp)));
+                (self.slots.len() - 1) as u32
+            }
+        };
+        let raw = id.to_raw() as usize;
+        if raw >= self.index.len() {
+            self.index.resize(raw + 1, NO_SLOT);
+        }
+        self.index[raw] = slot;
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        self.take(id);
+    }
+
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        let slot = self.slot(id)?;
+        self.index[id.to_raw() as usize] = NO_SLOT;
+        self.free.push(slot as u32);
+        self.slots[slot].take().map(|(_, c)| c)
+    }
+
+    fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        let spare = self.free.len();
+        if additional > spare {
+            self.slots.reserve(additional - spare);
+        }
+    }
+}
+
+const ARENA_BLOCK_SIZE: usize = 1024;
+
+///
+/// Arena implementation of the storage trait, components live in fixed size blocks that are
+/// allocated up front and never moved or grown
+///
+/// All component memory is freed at once by `free_all`, for example on level unload. Create it
+/// with `with_capacity` to get the whole level in a single block. Serializes as a list of
+/// `(id, component)` pairs.
+///
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "Vec<(EntityId, T)>", into = "Vec<(EntityId, T)>")]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct ArenaStorage<T: Clone> {
+    blocks: Vec<Vec<Option<(EntityId, T)>>>,
+    block_size: usize,
+    free: Vec<u32>,
+    index: Vec<u32>,
+    len: usize,
+    /// First block with unused space
+    fill: usize
+}
+
+impl<T: Clone> ArenaStorage<T> {
+    /// Storage whose first block holds `capacity` components
+    pub fn with_capacity(capacity: usize) -> Self {
+        ArenaStorage {
+            blocks: vec![Vec::with_capacity(capacity.max(1))],
+            block_size: capacity.max(1),
+            free: vec![],
+            index: vec![],
+            len: 0,
+            fill: 0
+        }
+    }
+
+    /// Drops every component and releases all blocks
+    pub fn free_all(&mut self) {
+        self.blocks = vec![];
+        self.free = vec![];
+        self.index = vec![];
+        self.len = 0;
+        self.fill = 0;
+    }
+
+    /// Number of allocated blocks
+    pub fn blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn slot(&self, id: EntityId) -> Option<(usize, usize)> {
+        match self.index.get(id.to_raw() as usize) {
+            Some(slot) if *slot != NO_SLOT => {
+                let slot = *slot as usize;
+                Some((slot / self.block_size, slot % self.block_size))
+            }
+            _ => None
+        }
+    }
+
+    fn entries(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.blocks.iter()
+            .flat_map(|block| block.iter())
+            .filter_map(|entry| entry.as_ref().map(|(id, c)| (*id, c)))
+    }
+}
+
+impl<T: Clone> Storage<T> for ArenaStorage<T> {
+    fn new() -> Self {
+        ArenaStorage::with_capacity(ARENA_BLOCK_SIZE)
+    }
+
+    fn get(&self, id: EntityId) -> Option<&T> {
+        let (block, offset) = self.slot(id)?;
+        self.blocks[block][offset].as_ref().map(|(_, c)| c)
+    }
+
+    fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        let (block, offset) = self.slot(id)?;
+        self.blocks[block][offset].as_mut().map(|(_, c)| c)
+    }
+
+    fn get_all(&self) -> Vec<(EntityId, &T)> {
+        self.entries().collect()
+    }
+
+    fn get_all_into<'a>(&'a self, out: &mut Vec<(EntityId, &'a T)>) {
+        out.extend(self.entries());
+    }
+
     fn set(&mut self, id: EntityId, comp: T) {
-        if id >= self.size {
-            self.storage.resize((id * 2) as usize, None);
-            self.size = id * 2;
+        if let Some((block, offset)) = self.slot(id) {
+            self.blocks[block][offset] = Some((id, comp));
+            return;
         }
-        self.storage[id as usize] = Some(comp);
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                let slot = slot as usize;
+                self.blocks[slot / self.block_size][slot % self.block_size] = Some((id, comp));
+                slot
+            }
+            None => {
+                while self.blocks.get(self.fill).map(|b| b.len() == self.block_size).unwrap_or(false) {
+                    self.fill += 1;
+                }
+                if self.fill == self.blocks.len() {
+                    self.blocks.push(Vec::with_capacity(self.block_size));
+                }
+                let block = &mut self.blocks[self.fill];
+                block.push(Some((id, comp)));
+                self.fill * self.block_size + block.len() - 1
+            }
+        };
+        let raw = id.to_raw() as usize;
+        if raw >= self.index.len() {
+            self.index.resize(raw + 1, NO_SLOT);
+        }
+        self.index[raw] = slot as u32;
+        self.len += 1;
     }
 
     fn remove(&mut self, id: EntityId) {
-        if id < self.size {
-            self.storage[id as usize] = None;
+        self.take(id);
+    }
+
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        let (block, offset) = self.slot(id)?;
+        self.index[id.to_raw() as usize] = NO_SLOT;
+        self.free.push((block * self.block_size + offset) as u32);
+        self.len -= 1;
+        self.blocks[block][offset].take().map(|(_, c)| c)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        let spare = self.free.len() + self.blocks[self.fill..].iter().map(|b| self.block_size - b.len()).sum::<usize>();
+        let mut needed = additional.saturating_sub(spare);
+        while needed > 0 {
+            self.blocks.push(Vec::with_capacity(self.block_size));
+            needed = needed.saturating_sub(self.block_size);
+        }
+    }
+}
+
+impl<T: Clone> From<Vec<(EntityId, T)>> for ArenaStorage<T> {
+    fn from(entries: Vec<(EntityId, T)>) -> Self {
+        let mut storage = ArenaStorage::with_capacity(entries.len().max(ARENA_BLOCK_SIZE));
+        for (id, comp) in entries {
+            storage.set(id, comp);
+        }
+        storage
+    }
+}
+
+impl<T: Clone> From<ArenaStorage<T>> for Vec<(EntityId, T)> {
+    fn from(storage: ArenaStorage<T>) -> Self {
+        storage.blocks.into_iter()
+            .flat_map(|block| block.into_iter())
+            .flatten()
+            .collect()
+    }
+}
+
+///
+/// Components that have a position in the world, needed for `SpatialGridStorage`
+///
+pub trait HasPosition {
+    fn position(&self) -> (f32, f32);
+
+    /// Side length of the grid cells used to index the component
+    fn grid_cell_size() -> f32 {
+        16.0
+    }
+}
+
+type GridCell = (i64, i64);
+
+fn grid_cell<T: HasPosition>(pos: (f32, f32)) -> GridCell {
+    let size = T::grid_cell_size();
+    ((pos.0 / size).floor() as i64, (pos.1 / size).floor() as i64)
+}
+
+///
+/// Hash map storage that also keeps a uniform grid index over the component positions, for fast
+/// area queries
+///
+/// Components handed out by `get_mut` may move, so they are taken out of the grid and checked
+/// one by one until the next mutable call puts them back.
+///
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "ComponentMap<T>")]
+pub struct SpatialGridStorage<T: Clone + HasPosition> {
+    storage: ComponentMap<T>,
+    cells: HashMap<GridCell, Vec<EntityId>, HashState>,
+    cell_of: HashMap<EntityId, GridCell, HashState>,
+    unindexed: Vec<EntityId>
+}
+
+impl<T: Clone + HasPosition> SpatialGridStorage<T> {
+    fn unindex(&mut self, id: EntityId) {
+        if let Some(cell) = self.cell_of.remove(&id) {
+            let empty = match self.cells.get_mut(&cell) {
+                Some(ids) => {
+                    ids.retain(|i| *i != id);
+                    ids.is_empty()
+                }
+                None => false
+            };
+            if empty {
+                self.cells.remove(&cell);
+            }
+        }
+        self.unindexed.retain(|i| *i != id);
+    }
+
+    fn index(&mut self, id: EntityId) {
+        if let Some(comp) = self.storage.get(&id) {
+            let cell = grid_cell::<T>(comp.position());
+            self.cells.entry(cell).or_default().push(id);
+            self.cell_of.insert(id, cell);
+        }
+    }
+
+    fn reindex(&mut self) {
+        let ids = ::std::mem::take(&mut self.unindexed);
+        for id in ids {
+            self.index(id);
         }
     }
+
+    /// Entities with a position inside the rectangle, edges included
+    pub fn in_rect(&self, min: (f32, f32), max: (f32, f32)) -> Vec<EntityId> {
+        let inside = |pos: (f32, f32)| pos.0 >= min.0 && pos.0 <= max.0 && pos.1 >= min.1 && pos.1 <= max.1;
+        let (min_cell, max_cell) = (grid_cell::<T>(min), grid_cell::<T>(max));
+        let mut found = vec![];
+        for x in min_cell.0..(max_cell.0 + 1) {
+            for y in min_cell.1..(max_cell.1 + 1) {
+                if let Some(ids) = self.cells.get(&(x, y)) {
+                    found.extend(ids.iter().filter(|id| inside(self.storage[id].position())));
+                }
+            }
+        }
+        found.extend(self.unindexed.iter().filter(|id| inside(self.storage[id].position())));
+        found
+    }
+
+    /// Entities within `radius` of `point`
+    pub fn near(&self, point: (f32, f32), radius: f32) -> Vec<EntityId> {
+        let min = (point.0 - radius, point.1 - radius);
+        let max = (point.0 + radius, point.1 + radius);
+        self.in_rect(min, max)
+            .into_iter()
+            .filter(|id| {
+                let pos = self.storage[id].position();
+                let (dx, dy) = (pos.0 - point.0, pos.1 - point.1);
+                dx * dx + dy * dy <= radius * radius
+            })
+            .collect()
+    }
+}
+
+impl<T: Clone + HasPosition> From<ComponentMap<T>> for SpatialGridStorage<T> {
+    fn from(storage: ComponentMap<T>) -> Self {
+        let ids: Vec<EntityId> = storage.keys().cloned().collect();
+        let mut spatial = SpatialGridStorage {
+            storage,
+            cells: HashMap::default(),
+            cell_of: HashMap::default(),
+            unindexed: vec![]
+        };
+        for id in ids {
+            spatial.index(id);
+        }
+        spatial
+    }
+}
+
+impl<T: Clone + HasPosition + Serialize> Serialize for SpatialGridStorage<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.storage.serialize(serializer)
+    }
+}
+
+impl<T: Clone + HasPosition> Storage<T> for SpatialGridStorage<T> {
+    fn new() -> Self {
+        SpatialGridStorage::from(ComponentMap::new())
+    }
+
+    fn get(&self, id: EntityId) -> Option<&T> {
+        self.storage.get(&id)
+    }
+
+    fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        self.reindex();
+        if self.storage.contains_key(&id) {
+            self.unindex(id);
+            self.unindexed.push(id);
+        }
+        self.storage.get_mut(&id)
+    }
+
+    fn get_all(&self) -> Vec<(EntityId, &T)> {
+        let mut all = vec![];
+        for (k, v) in &self.storage {
+            all.push((*k, v));
+        }
+        all
+    }
+
+    fn get_all_into<'a>(&'a self, out: &mut Vec<(EntityId, &'a T)>) {
+        out.extend(self.storage.iter().map(|(k, v)| (*k, v)));
+    }
+
+    fn set(&mut self, id: EntityId, comp: T) {
+        self.reindex();
+        self.unindex(id);
+        self.storage.insert(id, comp);
+        self.index(id);
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        self.reindex();
+        self.unindex(id);
+        self.storage.remove(&id);
+    }
+
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        self.reindex();
+        self.unindex(id);
+        self.storage.remove(&id)
+    }
+
+    fn drain(&mut self) -> Vec<(EntityId, T)> {
+        let drained = ::std::mem::replace(self, Self::new());
+        drained.storage.into_iter().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        #[cfg(not(feature = "deterministic"))]
+        self.storage.reserve(additional);
+        #[cfg(feature = "deterministic")]
+        let _ = additional;
+    }
+}
+
+///
+/// Components that can be looked up by a key, needed for `IndexedStorage`
+///
+pub trait IndexKey {
+    type Key: Clone + Eq + Hash + Debug;
+    fn index_key(&self) -> Self::Key;
+}
+
+///
+/// Hash map storage that also keeps a reverse index from each component's key to the entities
+/// having it
+///
+/// Components handed out by `get_mut` may change their key, so they are taken out of the index
+/// and checked one by one until the next mutable call puts them back.
+///
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "ComponentMap<T>")]
+pub struct IndexedStorage<T: Clone + IndexKey> {
+    storage: ComponentMap<T>,
+    index: HashMap<T::Key, Vec<EntityId>, HashState>,
+    key_of: HashMap<EntityId, T::Key, HashState>,
+    unindexed: Vec<EntityId>
+}
+
+impl<T: Clone + IndexKey> IndexedStorage<T> {
+    fn unindex(&mut self, id: EntityId) {
+        if let Some(key) = self.key_of.remove(&id) {
+            let empty = match self.index.get_mut(&key) {
+                Some(ids) => {
+                    ids.retain(|i| *i != id);
+                    ids.is_empty()
+                }
+                None => false
+            };
+            if empty {
+                self.index.remove(&key);
+            }
+        }
+        self.unindexed.retain(|i| *i != id);
+    }
+
+    fn index(&mut self, id: EntityId) {
+        if let Some(comp) = self.storage.get(&id) {
+            let key = comp.index_key();
+            self.index.entry(key.clone()).or_default().push(id);
+            self.key_of.insert(id, key);
+        }
+    }
+
+    fn reindex(&mut self) {
+        let ids = ::std::mem::take(&mut self.unindexed);
+        for id in ids {
+            self.index(id);
+        }
+    }
+
+    /// Entities whose component has the given key
+    pub fn find(&self, key: &T::Key) -> Vec<EntityId> {
+        let mut found = match self.index.get(key) {
+            Some(ids) => ids.clone(),
+            None => vec![]
+        };
+        found.extend(self.unindexed.iter().filter(|id| self.storage[id].index_key() == *key));
+        found
+    }
+}
+
+impl<T: Clone + IndexKey> From<ComponentMap<T>> for IndexedStorage<T> {
+    fn from(storage: ComponentMap<T>) -> Self {
+        let ids: Vec<EntityId> = storage.keys().cloned().collect();
+        let mut indexed = IndexedStorage {
+            storage,
+            index: HashMap::default(),
+            key_of: HashMap::default(),
+            unindexed: vec![]
+        };
+        for id in ids {
+            indexed.index(id);
+        }
+        indexed
+    }
+}
+
+impl<T: Clone + IndexKey + Serialize> Serialize for IndexedStorage<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.storage.serialize(serializer)
+    }
+}
+
+impl<T: Clone + IndexKey> Storage<T> for IndexedStorage<T> {
+    fn new() -> Self {
+        IndexedStorage::from(ComponentMap::new())
+    }
+
+    fn get(&self, id: EntityId) -> Option<&T> {
+        self.storage.get(&id)
+    }
+
+    fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        self.reindex();
+        if self.storage.contains_key(&id) {
+            self.unindex(id);
+            self.unindexed.push(id);
+        }
+        self.storage.get_mut(&id)
+    }
+
+    fn get_all(&self) -> Vec<(EntityId, &T)> {
+        let mut all = vec![];
+        for (k, v) in &self.storage {
+            all.push((*k, v));
+        }
+        all
+    }
+
+    fn get_all_into<'a>(&'a self, out: &mut Vec<(EntityId, &'a T)>) {
+        out.extend(self.storage.iter().map(|(k, v)| (*k, v)));
+    }
+
+    fn set(&mut self, id: EntityId, comp: T) {
+        self.reindex();
+        self.unindex(id);
+        self.storage.insert(id, comp);
+        self.index(id);
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        self.reindex();
+        self.unindex(id);
+        self.storage.remove(&id);
+    }
+
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        self.reindex();
+        self.unindex(id);
+        self.storage.remove(&id)
+    }
+
+    fn drain(&mut self) -> Vec<(EntityId, T)> {
+        let drained = ::std::mem::replace(self, Self::new());
+        drained.storage.into_iter().collect()
+    }
+
+    fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        #[cfg(not(feature = "deterministic"))]
+        self.storage.reserve(additional);
+        #[cfg(feature = "deterministic")]
+        let _ = additional;
+    }
+}
+
+///
+/// Hash map storage that also keeps the last values set on each entity, for interpolation,
+/// rewinding and debugging
+///
+/// Every `set` appends to the entity's history, dropping the oldest value past `history_len`,
+/// while `get_mut` changes the current value in place. Only current values are saved, so loaded
+/// storages start with one value per entity and the default length.
+///
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "ComponentMap<T>")]
+pub struct HistoryStorage<T: Clone> {
+    history: ComponentMap<VecDeque<T>>,
+    len: usize
+}
+
+impl<T: Clone> HistoryStorage<T> {
+    pub const DEFAULT_LEN: usize = 8;
+
+    /// Number of values kept per entity, the current one included
+    pub fn history_len(&self) -> usize {
+        self.len
+    }
+
+    /// Changes the number of values kept per entity, at least one, trimming longer histories
+    pub fn set_history_len(&mut self, len: usize) {
+        self.len = len.max(1);
+        for values in self.history.values_mut() {
+            while values.len() > self.len {
+                values.pop_front();
+            }
+        }
+    }
+
+    /// Values set on the entity, oldest first and ending with the current one
+    pub fn history(&self, id: EntityId) -> Option<&VecDeque<T>> {
+        self.history.get(&id)
+    }
+}
+
+impl<T: Clone> From<ComponentMap<T>> for HistoryStorage<T> {
+    fn from(storage: ComponentMap<T>) -> Self {
+        HistoryStorage {
+            history: storage.into_iter().map(|(id, comp)| (id, VecDeque::from(vec![comp]))).collect(),
+            len: Self::DEFAULT_LEN
+        }
+    }
+}
+
+impl<T: Clone + Serialize> Serialize for HistoryStorage<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.history.iter().filter_map(|(id, values)| Some((id, values.back()?))))
+    }
+}
+
+impl<T: Clone> Storage<T> for HistoryStorage<T> {
+    fn new() -> Self {
+        HistoryStorage::from(ComponentMap::new())
+    }
+
+    fn get(&self, id: EntityId) -> Option<&T> {
+        self.history.get(&id).and_then(VecDeque::back)
+    }
+
+    fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        self.history.get_mut(&id).and_then(VecDeque::back_mut)
+    }
+
+    fn get_all(&self) -> Vec<(EntityId, &T)> {
+        self.history.iter().filter_map(|(id, values)| Some((*id, values.back()?))).collect()
+    }
+
+    fn set(&mut self, id: EntityId, comp: T) {
+        let values = self.history.entry(id).or_default();
+        if values.len() >= self.len {
+            values.pop_front();
+        }
+        values.push_back(comp);
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        self.history.remove(&id);
+    }
+
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        self.history.remove(&id).and_then(|mut values| values.pop_back())
+    }
+
+    fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        #[cfg(not(feature = "deterministic"))]
+        self.history.reserve(additional);
+        #[cfg(feature = "deterministic")]
+        let _ = additional;
+    }
+}
+
+///
+/// Adapter that puts another storage in a `RefCell`, so several components can be borrowed
+/// mutably at once through a shared pool reference with runtime borrow checking
+///
+/// References handed out by `get` and `get_all` aren't tracked by the `RefCell`, so `borrow_mut`
+/// refuses to run after them until the storage is mutated or `release_borrows` is called.
+///
+pub struct CellStorage<T: Clone, S: Storage<T> = HashMapStorage<T>> {
+    cell: RefCell<S>,
+    shared: Cell<bool>,
+    marker: PhantomData<T>
+}
+
+impl<T: Clone, S: Storage<T>> CellStorage<T, S> {
+    /// Shared access to the inner storage, panics if it is mutably borrowed
+    pub fn borrow(&self) -> Ref<'_, S> {
+        self.cell.borrow()
+    }
+
+    /// Mutable access to the inner storage, panics if it is already borrowed
+    pub fn borrow_mut(&self) -> RefMut<'_, S> {
+        assert!(!self.shared.get(), "component storage was read through the pool since the last `release_borrows`");
+        self.cell.borrow_mut()
+    }
+
+    fn inner(&self) -> &S {
+        self.shared.set(true);
+        // SAFETY: `shared` keeps `borrow_mut` from running while the returned reference, which
+        // borrows `self`, may be alive. It is only reset through `&mut self`.
+        unsafe { self.cell.try_borrow_unguarded() }.expect("component storage is mutably borrowed")
+    }
+
+    fn inner_mut(&mut self) -> &mut S {
+        self.shared.set(false);
+        self.cell.get_mut()
+    }
+
+    /// Lets `borrow_mut` run again after `get` or `get_all`, the `&mut self` receiver proves that
+    /// none of the references they returned are alive
+    pub fn release_borrows(&mut self) {
+        self.shared.set(false);
+    }
+}
+
+impl<T: Clone, S: Storage<T>> From<S> for CellStorage<T, S> {
+    fn from(storage: S) -> Self {
+        CellStorage {
+            cell: RefCell::new(storage),
+            shared: Cell::new(false),
+            marker: PhantomData
+        }
+    }
+}
+
+impl<T: Clone, S: Storage<T> + Clone> Clone for CellStorage<T, S> {
+    fn clone(&self) -> Self {
+        CellStorage::from(self.borrow().clone())
+    }
+}
+
+impl<T: Clone, S: Storage<T> + Debug> Debug for CellStorage<T, S> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self.cell.try_borrow() {
+            Ok(storage) => f.debug_tuple("CellStorage").field(&*storage).finish(),
+            Err(_) => f.write_str("CellStorage(<borrowed>)")
+        }
+    }
+}
+
+impl<T: Clone, S: Storage<T> + Serialize> Serialize for CellStorage<T, S> {
+    fn serialize<Se: Serializer>(&self, serializer: Se) -> Result<Se::Ok, Se::Error> {
+        match self.cell.try_borrow() {
+            Ok(storage) => storage.serialize(serializer),
+            Err(_) => Err(::serde::ser::Error::custom("component storage is mutably borrowed"))
+        }
+    }
+}
+
+impl<'de, T: Clone, S: Storage<T> + Deserialize<'de>> Deserialize<'de> for CellStorage<T, S> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        S::deserialize(deserializer).map(CellStorage::from)
+    }
+}
+
+impl<T: Clone, S: Storage<T>> Storage<T> for CellStorage<T, S> {
+    fn new() -> Self {
+        CellStorage::from(S::new())
+    }
+
+    fn get(&self, id: EntityId) -> Option<&T> {
+        self.inner().get(id)
+    }
+
+    fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        self.inner_mut().get_mut(id)
+    }
+
+    fn get_all(&self) -> Vec<(EntityId, &T)> {
+        self.inner().get_all()
+    }
+
+    fn get_all_into<'a>(&'a self, out: &mut Vec<(EntityId, &'a T)>) {
+        self.inner().get_all_into(out);
+    }
+
+    fn set(&mut self, id: EntityId, comp: T) {
+        self.inner_mut().set(id, comp);
+    }
+
+    fn remove(&mut self, id: EntityId) {
+        self.inner_mut().remove(id);
+    }
+
+    fn take(&mut self, id: EntityId) -> Option<T> {
+        self.inner_mut().take(id)
+    }
+
+    fn drain(&mut self) -> Vec<(EntityId, T)> {
+        self.inner_mut().drain()
+    }
+
+    fn len(&self) -> usize {
+        self.borrow().len()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.inner_mut().reserve(additional);
+    }
 }
@@ -0,0 +1,104 @@
+//!
+//! Quick overview of a pool's population, for health checks in logs
+//!
+
+use std::collections::HashMap;
+use std::fmt;
+use mask::ComponentMask;
+use super::EntityId;
+
+///
+/// Population counts of a pool, returned by `summary`
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorldSummary {
+    /// Live entities with at least one component
+    pub entities: usize,
+    /// Live component count per type, in declaration order
+    pub components: Vec<(&'static str, usize)>,
+    /// `histogram[k]` is the number of live entities with exactly `k` components
+    pub histogram: Vec<usize>,
+    /// Entity with the most components and its component count, the lowest id wins ties
+    pub largest: Option<(EntityId, usize)>,
+    pub pending_removals: usize
+}
+
+impl fmt::Display for WorldSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} entities, {} pending removal", self.entities, self.pending_removals)?;
+        for (name, count) in &self.components {
+            writeln!(f, "    {}: {}", name, count)?;
+        }
+        let histogram: Vec<String> = self.histogram.iter().enumerate()
+            .filter(|(_, n)| **n > 0)
+            .map(|(k, n)| format!("{} with {}", n, k))
+            .collect();
+        if !histogram.is_empty() {
+            writeln!(f, "    components per entity: {}", histogram.join(", "))?;
+        }
+        if let Some((id, count)) = self.largest {
+            writeln!(f, "    largest entity: {} ({} components)", id, count)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Live entities sharing one set of component types
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Archetype {
+    pub mask: ComponentMask,
+    /// Component names, in declaration order
+    pub components: Vec<&'static str>,
+    pub entities: usize
+}
+
+///
+/// Live entities grouped by the components they have, returned by `archetype_report`. Shows
+/// which components occur together, to pick storages or groupings for them.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchetypeReport {
+    /// Most common archetypes first, ties ordered by mask
+    pub archetypes: Vec<Archetype>
+}
+
+impl ArchetypeReport {
+    /// Groups the masks of entities, `names` are the pool's components in declaration order
+    pub fn from_masks<I: IntoIterator<Item = ComponentMask>>(names: &[&'static str], masks: I) -> ArchetypeReport {
+        let mut counts: HashMap<ComponentMask, usize> = HashMap::new();
+        for mask in masks {
+            *counts.entry(mask).or_insert(0) += 1;
+        }
+        let mut archetypes: Vec<Archetype> = counts.into_iter()
+            .map(|(mask, entities)| Archetype {
+                mask,
+                components: names.iter().enumerate()
+                    .filter(|&(index, _)| mask.contains(ComponentMask::bit(index)))
+                    .map(|(_, name)| *name)
+                    .collect(),
+                entities
+            })
+            .collect();
+        archetypes.sort_by(|a, b| b.entities.cmp(&a.entities).then(a.mask.0.cmp(&b.mask.0)));
+        ArchetypeReport { archetypes }
+    }
+
+    /// Number of live entities having both components
+    pub fn together(&self, a: &str, b: &str) -> usize {
+        self.archetypes.iter()
+            .filter(|archetype| archetype.components.contains(&a) && archetype.components.contains(&b))
+            .map(|archetype| archetype.entities)
+            .sum()
+    }
+}
+
+impl fmt::Display for ArchetypeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for archetype in &self.archetypes {
+            writeln!(f, "{}: {}", archetype.entities, archetype.components.join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//!
+//! Helpers for writing tests against generated pools
+//!
+//! ```
+//! # #[macro_use] extern crate serde_derive;
+//! #[macro_use] extern crate spawning_pool;
+//! # fn main() {
+//! use spawning_pool::EntityId;
+//! use spawning_pool::storage::{Storage, VectorStorage};
+//! use spawning_pool::testing::{TestPool, assert_entity_has, assert_entity_lacks};
+//!
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Pos(i32, i32);
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! struct Vel(i32, i32);
+//!
+//! create_spawning_pool!(
+//!     (Pos, pos, VectorStorage),
+//!     (Vel, vel, VectorStorage)
+//! );
+//! let (pool, ids) = TestPool::<SpawningPool>::new()
+//!     .entity().with(Pos(1, 2)).with(Vel(0, 1)).done()
+//!     .entity().with(Pos(3, 4)).done()
+//!     .build_with_ids();
+//! assert_entity_has::<Vel, _>(&pool, ids[0]);
+//! assert_entity_lacks::<Vel, _>(&pool, ids[1]);
+//! # }
+//! ```
+//!
+
+use std::any::type_name;
+use super::{EntityId};
+use bundle::SetComponent;
+use diff::PoolDiff;
+use fetch::GetComponent;
+use inspect::{ErasedPool, Inspect};
+
+///
+/// Fluent builder for a pool filled with test entities
+///
+pub struct TestPool<P> {
+    pool: P,
+    ids: Vec<EntityId>
+}
+
+impl<P: Default + ErasedPool> TestPool<P> {
+    pub fn new() -> Self {
+        TestPool {
+            pool: P::default(),
+            ids: vec![]
+        }
+    }
+
+    /// Spawns a new entity, finish it with `done`
+    pub fn entity(mut self) -> TestEntity<P> {
+        let id = self.pool.spawn_entity();
+        self.ids.push(id);
+        TestEntity {
+            test: self,
+            id
+        }
+    }
+
+    pub fn build(self) -> P {
+        self.pool
+    }
+
+    /// The pool and the IDs of the entities, in the order they were added
+    pub fn build_with_ids(self) -> (P, Vec<EntityId>) {
+        (self.pool, self.ids)
+    }
+}
+
+impl<P: Default + ErasedPool> Default for TestPool<P> {
+    fn default() -> Self {
+        TestPool::new()
+    }
+}
+
+///
+/// Entity being built by a `TestPool`
+///
+pub struct TestEntity<P> {
+    test: TestPool<P>,
+    id: EntityId
+}
+
+impl<P> TestEntity<P> {
+    pub fn with<T>(mut self, component: T) -> Self where P: SetComponent<T> {
+        self.test.pool.set_component(self.id, component);
+        self
+    }
+
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    pub fn done(self) -> TestPool<P> {
+        self.test
+    }
+}
+
+/// Panics unless the live entity has component `T`
+pub fn assert_entity_has<T, P: GetComponent<T>>(pool: &P, id: EntityId) {
+    if pool.get_component(id).is_none() {
+        panic!("entity {} should have component {}", id, type_name::<T>());
+    }
+}
+
+/// Panics if the live entity has component `T`
+pub fn assert_entity_lacks<T, P: GetComponent<T>>(pool: &P, id: EntityId) {
+    if pool.get_component(id).is_some() {
+        panic!("entity {} should not have component {}", id, type_name::<T>());
+    }
+}
+
+/// Panics with the differences if the pools don't hold the same entities and component values
+pub fn assert_pools_eq<P: Inspect>(a: &P, b: &P) {
+    let diff = PoolDiff::between(a, b);
+    if !diff.is_empty() {
+        panic!("pools differ: {:?}", diff);
+    }
+}
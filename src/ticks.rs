@@ -0,0 +1,240 @@
+//!
+//! Change detection, recording when each component was last written
+//!
+
+use std::collections::HashSet;
+use map::{EntityMap, MapEntities};
+use super::{EntityId, RawEntityId};
+
+/// Value of the pool's change counter
+pub type Tick = u64;
+
+const NO_TICK: Tick = Tick::MAX;
+
+///
+/// Tick of the last `set` or `get_mut` of every component, kept per component type
+///
+/// Ticks are stored densely by raw entity ID so marking a write stays cheap on the hot path.
+///
+#[derive(Debug, Clone, Default)]
+pub struct ChangeTicks {
+    current: Tick,
+    changed: Vec<Vec<Tick>>,
+    /// Counts the times each component was added or removed, for `structure`
+    added_or_removed: Vec<u64>,
+    /// Counts the entity removals and the removals taken back
+    removals: u64
+}
+
+impl ChangeTicks {
+    pub fn current(&self) -> Tick {
+        self.current
+    }
+
+    pub fn advance(&mut self) -> Tick {
+        self.current += 1;
+        self.current
+    }
+
+    #[inline]
+    pub fn mark(&mut self, component: usize, id: EntityId) {
+        while self.changed.len() <= component {
+            self.changed.push(vec![]);
+        }
+        let changed = &mut self.changed[component];
+        let index = id.to_raw() as usize;
+        if index >= changed.len() {
+            changed.resize(index + 1, NO_TICK);
+        }
+        changed[index] = self.current;
+    }
+
+    pub fn remove(&mut self, component: usize, id: EntityId) {
+        if let Some(tick) = self.changed.get_mut(component).and_then(|changed| changed.get_mut(id.to_raw() as usize)) {
+            *tick = NO_TICK;
+        }
+    }
+
+    pub fn remove_entity(&mut self, id: EntityId) {
+        for changed in &mut self.changed {
+            if let Some(tick) = changed.get_mut(id.to_raw() as usize) {
+                *tick = NO_TICK;
+            }
+        }
+        self.removals += 1;
+    }
+
+    /// Records that the component was added to or removed from an entity
+    pub fn touch(&mut self, component: usize) {
+        while self.added_or_removed.len() <= component {
+            self.added_or_removed.push(0);
+        }
+        self.added_or_removed[component] += 1;
+    }
+
+    /// Records that an entity was queued for removal or taken back out of the queue
+    pub fn touch_removals(&mut self) {
+        self.removals += 1;
+    }
+
+    /// Counter that grows whenever the set of live entities having the component may have
+    /// changed, writes to existing components leave it alone
+    pub fn structure(&self, component: usize) -> u64 {
+        self.removals + self.added_or_removed.get(component).cloned().unwrap_or(0)
+    }
+
+    /// Entities whose component was written at or after `tick`, sorted by id
+    pub fn changed_since(&self, component: usize, tick: Tick) -> Vec<EntityId> {
+        match self.changed.get(component) {
+            Some(changed) => changed.iter().enumerate()
+                .filter(|(_, t)| **t != NO_TICK && **t >= tick)
+                .filter_map(|(raw, _)| EntityId::from_raw(raw as RawEntityId))
+                .collect(),
+            None => vec![]
+        }
+    }
+}
+
+///
+/// Tick each entity was spawned at, recorded once enabled with `track_spawn_ticks`
+///
+#[derive(Debug, Clone, Default)]
+pub struct SpawnTicks {
+    enabled: bool,
+    ticks: Vec<Tick>
+}
+
+impl SpawnTicks {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Disabling also forgets every recorded tick
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.ticks = vec![];
+        }
+    }
+
+    pub fn record(&mut self, id: EntityId, tick: Tick) {
+        if !self.enabled {
+            return;
+        }
+        let index = id.to_raw() as usize;
+        if index >= self.ticks.len() {
+            self.ticks.resize(index + 1, NO_TICK);
+        }
+        self.ticks[index] = tick;
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<Tick> {
+        match self.ticks.get(id.to_raw() as usize) {
+            Some(tick) if *tick != NO_TICK => Some(*tick),
+            _ => None
+        }
+    }
+
+    pub fn remove(&mut self, id: EntityId) {
+        if let Some(tick) = self.ticks.get_mut(id.to_raw() as usize) {
+            *tick = NO_TICK;
+        }
+    }
+}
+
+impl MapEntities for ChangeTicks {
+    /// Drops the ticks of entities that aren't in the map
+    fn map_entities(&mut self, map: &EntityMap) {
+        let pairs = map.pairs();
+        for changed in self.changed.iter_mut() {
+            let old = ::std::mem::take(changed);
+            for &(from, to) in &pairs {
+                if let Some(&tick) = old.get(from.to_raw() as usize) {
+                    if tick != NO_TICK {
+                        let index = to.to_raw() as usize;
+                        if index >= changed.len() {
+                            changed.resize(index + 1, NO_TICK);
+                        }
+                        changed[index] = tick;
+                    }
+                }
+            }
+        }
+        self.removals += 1;
+    }
+}
+
+impl MapEntities for SpawnTicks {
+    fn map_entities(&mut self, map: &EntityMap) {
+        let ticks = ::std::mem::take(&mut self.ticks);
+        for (old, new) in map.pairs() {
+            if let Some(&tick) = ticks.get(old.to_raw() as usize) {
+                if tick != NO_TICK {
+                    self.record(new, tick);
+                }
+            }
+        }
+    }
+}
+
+///
+/// Entities that lost a component since the trackers were last cleared, per component type,
+/// recorded once enabled with `track_removals`
+///
+#[derive(Debug, Clone, Default)]
+pub struct RemovalTracker {
+    enabled: bool,
+    /// Removals in the order they happened, with a set of the same ids for deduplication
+    removed: Vec<(Vec<EntityId>, HashSet<EntityId>)>
+}
+
+impl RemovalTracker {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Disabling also forgets every recorded removal
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.removed = vec![];
+        }
+    }
+
+    /// Records the removal, entities already listed for the component are not added again
+    #[inline]
+    pub fn record(&mut self, component: usize, id: EntityId) {
+        if !self.enabled {
+            return;
+        }
+        while self.removed.len() <= component {
+            self.removed.push(Default::default());
+        }
+        let (ids, seen) = &mut self.removed[component];
+        if seen.insert(id) {
+            ids.push(id);
+        }
+    }
+
+    pub fn removed(&self, component: usize) -> &[EntityId] {
+        match self.removed.get(component) {
+            Some((ids, _)) => ids,
+            None => &[]
+        }
+    }
+
+    pub fn forget(&mut self, component: usize, id: EntityId) {
+        if let Some((ids, seen)) = self.removed.get_mut(component) {
+            if seen.remove(&id) {
+                ids.retain(|i| *i != id);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for (ids, seen) in &mut self.removed {
+            ids.clear();
+            seen.clear();
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//!
+//! Mapping between entity IDs and persistent UUIDs
+//!
+
+use std::collections::{BTreeMap, HashMap};
+use uuid::Uuid;
+use map::{EntityMap, MapEntities};
+use super::{EntityId};
+
+///
+/// Two way map between entities and their UUIDs, serialized together with the pool
+///
+/// Only entities that have been given a UUID are tracked, so the map costs nothing for games
+/// that don't use it. It is saved in entity id order.
+///
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "HashMap<EntityId, Uuid>", into = "BTreeMap<EntityId, Uuid>")]
+pub struct UuidMap {
+    by_entity: HashMap<EntityId, Uuid>,
+    by_uuid: HashMap<Uuid, EntityId>
+}
+
+impl UuidMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<Uuid> {
+        self.by_entity.get(&id).cloned()
+    }
+
+    pub fn entity(&self, uuid: &Uuid) -> Option<EntityId> {
+        self.by_uuid.get(uuid).cloned()
+    }
+
+    /// Assigns `uuid` to the entity, replacing any previous mapping of either of them
+    pub fn insert(&mut self, id: EntityId, uuid: Uuid) {
+        self.remove(id);
+        if let Some(old) = self.by_uuid.insert(uuid, id) {
+            self.by_entity.remove(&old);
+        }
+        self.by_entity.insert(id, uuid);
+    }
+
+    pub fn remove(&mut self, id: EntityId) -> Option<Uuid> {
+        let uuid = self.by_entity.remove(&id);
+        if let Some(ref uuid) = uuid {
+            self.by_uuid.remove(uuid);
+        }
+        uuid
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_entity.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_entity.is_empty()
+    }
+}
+
+impl From<HashMap<EntityId, Uuid>> for UuidMap {
+    fn from(by_entity: HashMap<EntityId, Uuid>) -> Self {
+        let by_uuid = by_entity.iter().map(|(id, uuid)| (*uuid, *id)).collect();
+        UuidMap {
+            by_entity,
+            by_uuid
+        }
+    }
+}
+
+impl MapEntities for UuidMap {
+    /// Drops the UUIDs of entities that aren't in the map
+    fn map_entities(&mut self, map: &EntityMap) {
+        let entries: HashMap<EntityId, Uuid> = ::std::mem::take(self).into();
+        *self = entries.into_iter()
+            .filter_map(|(id, uuid)| Some((map.get(id)?, uuid)))
+            .collect::<HashMap<_, _>>()
+            .into();
+    }
+}
+
+impl From<UuidMap> for HashMap<EntityId, Uuid> {
+    fn from(map: UuidMap) -> Self {
+        map.by_entity
+    }
+}
+
+impl From<UuidMap> for BTreeMap<EntityId, Uuid> {
+    fn from(map: UuidMap) -> Self {
+        map.by_entity.into_iter().collect()
+    }
+}
@@ -0,0 +1,79 @@
+//!
+//! Weak entity references that can be checked for validity after their target dies
+//!
+
+use super::{EntityId, RawEntityId};
+
+///
+/// Reference to an entity that components can store, turned back into an ID with
+/// `pool.upgrade(weak)` only while the entity is alive
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EntityRefWeak {
+    id: EntityId,
+    generation: u32
+}
+
+impl EntityRefWeak {
+    pub fn new(id: EntityId, generation: u32) -> Self {
+        EntityRefWeak { id, generation }
+    }
+
+    /// The referenced ID, without checking that it is still valid
+    pub fn id(&self) -> EntityId {
+        self.id
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+///
+/// Generation of every entity ID, bumped each time an entity with that ID is destroyed
+///
+/// Serializes as a list of `(id, generation)` pairs for IDs with a non-zero generation.
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<(EntityId, u32)>", into = "Vec<(EntityId, u32)>")]
+pub struct Generations {
+    generations: Vec<u32>
+}
+
+impl Generations {
+    #[inline]
+    pub fn get(&self, id: EntityId) -> u32 {
+        self.generations.get(id.to_raw() as usize).cloned().unwrap_or(0)
+    }
+
+    pub fn bump(&mut self, id: EntityId) {
+        let index = id.to_raw() as usize;
+        if index >= self.generations.len() {
+            self.generations.resize(index + 1, 0);
+        }
+        self.generations[index] = self.generations[index].wrapping_add(1);
+    }
+}
+
+impl From<Vec<(EntityId, u32)>> for Generations {
+    fn from(entries: Vec<(EntityId, u32)>) -> Self {
+        let mut generations = Generations::default();
+        for (id, generation) in entries {
+            let index = id.to_raw() as usize;
+            if index >= generations.generations.len() {
+                generations.generations.resize(index + 1, 0);
+            }
+            generations.generations[index] = generation;
+        }
+        generations
+    }
+}
+
+impl From<Generations> for Vec<(EntityId, u32)> {
+    fn from(generations: Generations) -> Self {
+        generations.generations.iter().enumerate()
+            .filter(|(_, generation)| **generation != 0)
+            .filter_map(|(i, generation)| EntityId::from_raw(i as RawEntityId).map(|id| (id, *generation)))
+            .collect()
+    }
+}